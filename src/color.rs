@@ -0,0 +1,144 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{env, ffi::OsString, io::IsTerminal};
+
+use nu_ansi_term::{Color, Style};
+use tracing::Level;
+
+/// Whether or not ANSI terminal escape codes are written, see [`color`](crate::TracingConfig::color).
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum ColorMode {
+    /// Write colors when the writer is a terminal.  A non-empty `NO_COLOR` environment variable disables colors,
+    /// a `CLICOLOR_FORCE` environment variable other than `0` enables them even when the writer is not a terminal.
+    #[default]
+    Auto,
+    /// Always write colors
+    Always,
+    /// Never write colors
+    Never,
+}
+
+impl ColorMode {
+    /// Whether or not colors should be written to the given writer in this mode.
+    pub fn enabled<W>(self, writer: &W) -> bool
+    where
+        W: IsTerminal,
+    {
+        self.resolve(
+            env::var_os("NO_COLOR"),
+            env::var_os("CLICOLOR_FORCE"),
+            writer.is_terminal(),
+        )
+    }
+
+    /// Whether or not colors should be written to the given [`Output`](crate::Output) in this mode.
+    pub(crate) fn enabled_for(self, output: &crate::Output) -> bool {
+        self.resolve(
+            env::var_os("NO_COLOR"),
+            env::var_os("CLICOLOR_FORCE"),
+            output.is_terminal(),
+        )
+    }
+
+    fn resolve(
+        self,
+        no_color: Option<OsString>,
+        clicolor_force: Option<OsString>,
+        is_terminal: bool,
+    ) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                if no_color.is_some_and(|value| !value.is_empty()) {
+                    false
+                } else if clicolor_force.is_some_and(|value| !value.is_empty() && value != "0") {
+                    true
+                } else {
+                    is_terminal
+                }
+            }
+        }
+    }
+}
+
+/// The colors used by the [`full_text`](crate::full_text), [`compact_text`](crate::compact_text) and
+/// [`pretty_text`](crate::pretty_text) formats when colors are enabled.
+///
+/// The default theme matches the `tracing-subscriber` colors.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Theme {
+    /// The style of the `TRACE` level
+    pub trace: Style,
+    /// The style of the `DEBUG` level
+    pub debug: Style,
+    /// The style of the `INFO` level
+    pub info: Style,
+    /// The style of the `WARN` level
+    pub warn: Style,
+    /// The style of the `ERROR` level
+    pub error: Style,
+    /// The style of the event target.  When [`None`], the target is dimmed by the `full` and `compact`
+    /// formats, and bold in the level style by the `pretty` format
+    pub target: Option<Style>,
+    /// The style of the span names
+    pub span: Style,
+}
+
+impl Theme {
+    /// The style of the given level.
+    #[must_use]
+    pub fn level(&self, level: Level) -> Style {
+        match level {
+            Level::TRACE => self.trace,
+            Level::DEBUG => self.debug,
+            Level::INFO => self.info,
+            Level::WARN => self.warn,
+            Level::ERROR => self.error,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            trace: Style::new().fg(Color::Purple),
+            debug: Style::new().fg(Color::Blue),
+            info: Style::new().fg(Color::Green),
+            warn: Style::new().fg(Color::Yellow),
+            error: Style::new().fg(Color::Red),
+            target: None,
+            span: Style::new().bold(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::OsString;
+
+    use super::ColorMode;
+
+    fn var(value: &str) -> OsString {
+        OsString::from(value)
+    }
+
+    #[test]
+    fn color_mode_resolves() {
+        assert!(ColorMode::Always.resolve(Some(var("1")), None, false));
+        assert!(!ColorMode::Never.resolve(None, Some(var("1")), true));
+        assert!(ColorMode::Auto.resolve(None, None, true));
+        assert!(!ColorMode::Auto.resolve(None, None, false));
+        assert!(!ColorMode::Auto.resolve(Some(var("1")), None, true));
+        assert!(ColorMode::Auto.resolve(Some(var("")), None, true));
+        assert!(ColorMode::Auto.resolve(None, Some(var("1")), false));
+        assert!(!ColorMode::Auto.resolve(None, Some(var("0")), false));
+        assert!(!ColorMode::Auto.resolve(Some(var("1")), Some(var("1")), true));
+    }
+}
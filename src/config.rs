@@ -15,7 +15,244 @@ pub trait Config {
     fn quiet(&self) -> u8;
     /// Get the verbose count (these are normally pulled from the command line arguments)
     fn verbose(&self) -> u8;
+    /// Gets the application name recorded by formats that carry one, i.e. the `name` of a Bunyan record,
+    /// the syslog `APP-NAME`, the journal `SYSLOG_IDENTIFIER`, the default CEF product or the default Loki
+    /// `service_name` label.
+    /// This defaults to the file stem of the current executable
+    #[cfg(any(
+        feature = "bunyan",
+        feature = "cef",
+        feature = "http",
+        all(feature = "syslog", unix),
+        all(feature = "journald", target_os = "linux")
+    ))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(
+            feature = "bunyan",
+            feature = "cef",
+            feature = "http",
+            all(feature = "syslog", unix),
+            all(feature = "journald", target_os = "linux")
+        )))
+    )]
+    fn app_name(&self) -> String {
+        crate::utils::default_app_name()
+    }
+    /// Gets the path of the journal socket the [`journald`](crate::journald) layer writes to.
+    /// This defaults to `/run/systemd/journal/socket`
+    #[cfg(all(feature = "journald", target_os = "linux"))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "journald", target_os = "linux"))))]
+    fn journald_path(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(crate::format::journald::DEFAULT_JOURNALD_PATH)
+    }
+    /// Gets the process identifier recorded by the [`syslog`](crate::syslog) format.
+    /// This defaults to the current process ID
+    #[cfg(all(feature = "syslog", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+    fn proc_id(&self) -> String {
+        std::process::id().to_string()
+    }
+    /// Gets the device vendor written in the header of [`cef`](crate::cef) records.
+    /// This defaults to `Unknown`
+    #[cfg(feature = "cef")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cef")))]
+    fn cef_vendor(&self) -> String {
+        "Unknown".to_string()
+    }
+    /// Gets the device product written in the header of [`cef`](crate::cef) records.
+    /// This defaults to the [`app_name`](Config::app_name)
+    #[cfg(feature = "cef")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cef")))]
+    fn cef_product(&self) -> String {
+        self.app_name()
+    }
+    /// Gets the device version written in the header of [`cef`](crate::cef) records.
+    /// This defaults to `Unknown`
+    #[cfg(feature = "cef")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cef")))]
+    fn cef_version(&self) -> String {
+        "Unknown".to_string()
+    }
+    /// Gets the columns written by the [`delimited`](crate::delimited) format, in order.
+    /// This defaults to the columns enabled by the other `with_*` settings, see [`Column::from_config`](crate::Column::from_config)
+    #[cfg(feature = "delimited")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "delimited")))]
+    fn columns(&self) -> Vec<crate::Column> {
+        crate::Column::from_config(self)
+    }
+    /// Gets the field delimiter used by the [`delimited`](crate::delimited) format.
+    /// This defaults to [`Comma`](crate::Delimiter::Comma)
+    #[cfg(feature = "delimited")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "delimited")))]
+    fn delimiter(&self) -> crate::Delimiter {
+        crate::Delimiter::Comma
+    }
+    /// Sets whether or not the [`json_format`](crate::json_format) format writes the event fields at the top level of
+    /// the JSON object rather than nested under the fields key.  A field whose name collides with one of the
+    /// [`json_keys`](Config::json_keys) written for the event is kept nested under the fields key instead.
+    /// This defaults to false
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    fn flatten_event(&self) -> bool {
+        false
+    }
+    /// Gets the key names used by the [`json_format`](crate::json_format) format.
+    /// This defaults to the key names of the `tracing-subscriber` JSON format
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    fn json_keys(&self) -> crate::JsonKeys {
+        crate::JsonKeys::default()
+    }
+    /// Sets whether or not the [`json_format`](crate::json_format) format merges the fields of the spans in scope
+    /// (from root to leaf) into the event fields.  Event fields win over span fields with the same name.
+    /// This defaults to false
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    fn merge_span_fields(&self) -> bool {
+        false
+    }
+    /// Gets the facility used by the [`syslog`](crate::syslog) format.
+    /// This defaults to [`User`](crate::Facility::User)
+    #[cfg(all(feature = "syslog", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+    fn syslog_facility(&self) -> crate::Facility {
+        crate::Facility::User
+    }
+    /// Gets the message format used by the [`syslog`](crate::syslog) format.
+    /// This defaults to [`Rfc5424`](crate::SyslogFormat::Rfc5424)
+    #[cfg(all(feature = "syslog", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+    fn syslog_format(&self) -> crate::SyslogFormat {
+        crate::SyslogFormat::Rfc5424
+    }
+    /// Gets the path of the Unix datagram socket the [`syslog`](crate::syslog) format writes to.
+    /// This defaults to `/dev/log`
+    #[cfg(all(feature = "syslog", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+    fn syslog_path(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(crate::format::syslog::DEFAULT_SYSLOG_PATH)
+    }
+    /// Gets the line layout of the [`template`](crate::template) format, see [`Template`](crate::Template).
+    /// This defaults to `{timestamp} {level:>5} {target}: {message} {fields}`
+    #[cfg(feature = "template")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "template")))]
+    fn template(&self) -> String {
+        crate::format::template::DEFAULT_TEMPLATE.to_string()
+    }
+    /// Gets the size limits applied to every event by every format, see [`Limits`](crate::Limits).
+    /// This defaults to no limits
+    fn limits(&self) -> crate::Limits {
+        crate::Limits::default()
+    }
+    /// Gets how the [`full_text`](crate::full_text), [`compact_text`](crate::compact_text), [`pretty_text`](crate::pretty_text) and
+    /// `syslog` formats write line breaks and other control characters in event messages and in event and span
+    /// field values.  The structured formats always escape them as their encoding requires.
+    /// This defaults to [`Verbatim`](crate::NewlinePolicy::Verbatim)
+    fn newline_policy(&self) -> crate::NewlinePolicy {
+        crate::NewlinePolicy::Verbatim
+    }
+    /// Gets how the [`full_text`](crate::full_text), [`compact_text`](crate::compact_text), [`pretty_text`](crate::pretty_text),
+    /// `template` and `syslog` formats abbreviate the event target.
+    /// This defaults to [`Full`](crate::TargetAbbreviation::Full)
+    fn target_abbreviation(&self) -> crate::TargetAbbreviation {
+        crate::TargetAbbreviation::Full
+    }
+    /// Gets the log file the format layers write to instead of standard output, see [`FileOutput`](crate::FileOutput).
+    /// The events are also sent to the [`network_output`](Config::network_output) and `http_output`, if any.
+    /// The `syslog` and `journald` layers always write to their daemon.
+    /// This defaults to [`None`](std::option::Option::None), standard output
+    fn file_output(&self) -> Option<crate::FileOutput> {
+        None
+    }
+    /// Gets the collector the format layers send events to instead of standard output, see
+    /// [`NetworkOutput`](crate::NetworkOutput).  The events are also written to the
+    /// [`file_output`](Config::file_output) and `http_output`, if any.
+    /// This defaults to [`None`](std::option::Option::None)
+    fn network_output(&self) -> Option<crate::NetworkOutput> {
+        None
+    }
+    /// Gets the HTTP endpoint the format layers push events to instead of standard output, see
+    /// [`HttpOutput`](crate::HttpOutput).  The events are also written to the
+    /// [`network_output`](Config::network_output) and [`file_output`](Config::file_output), if any.  An output
+    /// [`HttpWriter::new`](crate::HttpWriter::new) rejects is reported to the [`error handler`](crate::set_error_handler)
+    /// and left out.
+    /// This defaults to [`None`](std::option::Option::None)
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    fn http_output(&self) -> Option<crate::HttpOutput> {
+        None
+    }
+    /// Gets whether or not the format layers write to standard output as well as to the `http_output`,
+    /// [`network_output`](Config::network_output) and [`file_output`](Config::file_output).  Each event is formatted
+    /// once, and a failing write to one output does not stop the writes to the others.
+    /// This defaults to false
+    fn tee_stdout(&self) -> bool {
+        false
+    }
+    /// Gets the least severe level of the events the format layers write to standard error rather than standard
+    /// output, i.e. [`WARN`](tracing::Level::WARN) for warnings and errors.  Colors are decided for each stream.
+    /// This is ignored with an `http_output`, [`network_output`](Config::network_output) or
+    /// [`file_output`](Config::file_output), unless [`tee_stdout`](Config::tee_stdout) is set.
+    /// This defaults to [`None`](std::option::Option::None), all events to standard output
+    fn stderr_level(&self) -> Option<tracing::Level> {
+        None
+    }
+    /// Gets the non-blocking writer configuration, which moves the writing of formatted events off the
+    /// thread that emitted them, see [`NonBlocking`](crate::NonBlocking).
+    /// This defaults to [`None`](std::option::Option::None), writing on the emitting thread
+    fn non_blocking(&self) -> Option<crate::NonBlocking> {
+        None
+    }
+    /// Gets the flight recorder configuration of the [`flight_recorder`](crate::flight_recorder) layer, which keeps
+    /// the most recent events at its own level and dumps them on request, see [`FlightRecorder`](crate::FlightRecorder).
+    /// This defaults to the last 4096 events at [`TRACE`](tracing::Level::TRACE), dumped to standard error, without a
+    /// panic hook
+    fn flight_recorder(&self) -> crate::FlightRecorder {
+        crate::FlightRecorder::default()
+    }
+    /// Gets the routes of the [`routing`](crate::routing) layer, which write the events of some targets to their own
+    /// log files, each in its own format, see [`Route`](crate::Route).
+    /// This defaults to no routes, every event goes to the default layer
+    fn routes(&self) -> Vec<crate::Route> {
+        Vec::new()
+    }
+    /// Gets the key/value pairs rendered as ordinary event fields on every event, i.e. the service name,
+    /// version or environment.  Event fields with the same name take precedence in the structured formats.  The
+    /// `tracing-subscriber` layers of [`full`](crate::full), [`compact`](crate::compact), [`pretty`](crate::pretty)
+    /// and [`json`](crate::json) do not render them, use the `_text` and [`json_format`](crate::json_format)
+    /// constructors instead.
+    /// This defaults to no fields
+    fn static_fields(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+    /// Gets the color mode of the [`full_text`](crate::full_text), [`compact_text`](crate::compact_text) and
+    /// [`pretty_text`](crate::pretty_text) formats.  The mode is resolved against the output the layers write to.
+    /// This defaults to [`Auto`](crate::ColorMode::Auto), or [`Never`](crate::ColorMode::Never) when
+    /// [`with_ansi`](Self::with_ansi) is false
+    fn color(&self) -> crate::ColorMode {
+        if self.with_ansi() {
+            crate::ColorMode::Auto
+        } else {
+            crate::ColorMode::Never
+        }
+    }
+    /// Gets the colors used by the [`full_text`](crate::full_text), [`compact_text`](crate::compact_text) and
+    /// [`pretty_text`](crate::pretty_text) formats when colors are enabled.
+    /// This defaults to the `tracing-subscriber` colors
+    fn theme(&self) -> crate::Theme {
+        crate::Theme::default()
+    }
+    /// Gets the event timestamp configuration used by the [`full_text`](crate::full_text),
+    /// [`compact_text`](crate::compact_text), [`pretty_text`](crate::pretty_text), [`json_format`](crate::json_format) and `delimited`
+    /// formats.  The remaining formats use the timestamp mandated by their protocol.
+    /// This defaults to [`System`](crate::TimerConfig::System)
+    fn timer(&self) -> crate::TimerConfig {
+        crate::TimerConfig::System
+    }
     /// Sets whether or not the formatter emits ANSI terminal escape codes for colors and other text formatting.
+    /// The [`full_text`](crate::full_text), [`compact_text`](crate::compact_text) and [`pretty_text`](crate::pretty_text) formats use
+    /// the [`color`](Self::color) mode instead, which is derived from this setting by default.
     /// This defaults to true
     fn with_ansi(&self) -> bool {
         true
@@ -55,6 +292,11 @@ pub trait Config {
     fn with_span_list(&self) -> bool {
         false
     }
+    /// Sets whether or not the ID of the current span is displayed when formatting events, as 16 hex digits.
+    /// This defaults to false
+    fn with_span_ids(&self) -> bool {
+        false
+    }
     /// Sets whether or not an event’s target is displayed.
     /// This defaults to false
     fn with_target(&self) -> bool {
@@ -70,4 +312,10 @@ pub trait Config {
     fn with_thread_names(&self) -> bool {
         false
     }
+    /// Sets whether or not the trace ID of the current span is displayed when formatting events, as 32 hex digits.
+    /// The trace ID is generated for the root span of the current span, so it is shared by all of its descendants.
+    /// This defaults to false
+    fn with_trace_id(&self) -> bool {
+        false
+    }
 }
@@ -0,0 +1,377 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::fmt::{Error as FmtError, Result as FmtResult};
+
+use serde_json::{Map, Value};
+use tracing::{Event, Level, Subscriber, metadata::LevelFilter};
+use tracing_subscriber::{
+    Layer,
+    filter::Filtered,
+    fmt::{
+        self, FmtContext, FormatEvent, FormatFields, FormattedFields,
+        format::{JsonFields, Writer},
+    },
+    registry::LookupSpan,
+};
+
+use crate::{
+    Output, TracingConfig,
+    format::{
+        ids::SpanIds,
+        visitor::{JsonVisitor, span_fields},
+    },
+    limits::Limits,
+    utils::{get_effective_level, utc_timestamp},
+};
+
+/// A [`FormatEvent`] implementation that writes [Bunyan](https://github.com/trentm/node-bunyan) records,
+/// one JSON object per line.
+///
+/// Every record carries the core Bunyan fields (`v`, `name`, `hostname`, `pid`, `level`, `time`, `msg`).
+/// Event fields are added at the top level of the record, unless their name collides with one of the
+/// core fields.  The current span and trace IDs are added as `span_id` and `trace_id` when enabled.
+/// The configured [`limits`](crate::TracingConfig::limits) apply to the message and the event fields.
+#[cfg_attr(docsrs, doc(cfg(feature = "bunyan")))]
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug)]
+pub struct Bunyan {
+    name: String,
+    hostname: String,
+    pid: u32,
+    static_fields: Vec<(String, String)>,
+    limits: Limits,
+    display_target: bool,
+    display_filename: bool,
+    display_line_number: bool,
+    display_thread_id: bool,
+    display_thread_name: bool,
+    display_current_span: bool,
+    display_span_id: bool,
+    display_trace_id: bool,
+}
+
+impl Bunyan {
+    /// Create a [`Bunyan`] formatter configured from the given [`TracingConfig`].
+    pub fn new<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self {
+            name: config.app_name(),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            pid: std::process::id(),
+            static_fields: config.static_fields(),
+            limits: config.limits(),
+            display_target: config.with_target(),
+            display_filename: config.with_file(),
+            display_line_number: config.with_line_number(),
+            display_thread_id: config.with_thread_ids(),
+            display_thread_name: config.with_thread_names(),
+            display_current_span: config.with_current_span(),
+            display_span_id: config.with_span_ids(),
+            display_trace_id: config.with_trace_id(),
+        }
+    }
+}
+
+impl Bunyan {
+    /// Assemble the record from the core fields, the message, the context fields and the event fields.
+    fn assemble(
+        &self,
+        head: &Map<String, Value>,
+        values: &Map<String, Value>,
+        context: &Map<String, Value>,
+        static_fields: bool,
+    ) -> Map<String, Value> {
+        let mut record = head.clone();
+        let mut visitor = JsonVisitor {
+            values: values.clone(),
+        };
+        let _old = record.insert(
+            "msg".to_string(),
+            Value::from(visitor.take_message().unwrap_or_default()),
+        );
+        record.extend(context.clone());
+        for (key, value) in visitor.values {
+            let _value = record.entry(key).or_insert(value);
+        }
+        let static_fields = if static_fields {
+            self.static_fields.as_slice()
+        } else {
+            &[]
+        };
+        for (key, value) in static_fields {
+            let _value = record
+                .entry(key.clone())
+                .or_insert_with(|| Value::from(value.as_str()));
+        }
+        record
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for Bunyan
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        let meta = event.metadata();
+        let mut limits = self.limits.event();
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        limits.apply(&mut visitor.values);
+
+        let mut head = Map::new();
+        let _old = head.insert("v".to_string(), Value::from(0));
+        let _old = head.insert("name".to_string(), Value::from(self.name.as_str()));
+        let _old = head.insert("hostname".to_string(), Value::from(self.hostname.as_str()));
+        let _old = head.insert("pid".to_string(), Value::from(self.pid));
+        let _old = head.insert("level".to_string(), Value::from(level(*meta.level())));
+        let _old = head.insert("time".to_string(), Value::from(utc_timestamp()));
+
+        let mut context = Map::new();
+        if self.display_target {
+            let _old = context.insert("target".to_string(), Value::from(meta.target()));
+        }
+
+        let mut src = Map::new();
+        if self.display_filename
+            && let Some(file) = meta.file()
+        {
+            let _old = src.insert("file".to_string(), Value::from(file));
+        }
+        if self.display_line_number
+            && let Some(line) = meta.line()
+        {
+            let _old = src.insert("line".to_string(), Value::from(line));
+        }
+        if !src.is_empty() {
+            let _old = context.insert("src".to_string(), Value::Object(src));
+        }
+
+        let current_thread = std::thread::current();
+        if self.display_thread_name
+            && let Some(name) = current_thread.name()
+        {
+            let _old = context.insert("threadName".to_string(), Value::from(name));
+        }
+        if self.display_thread_id {
+            let _old = context.insert(
+                "tid".to_string(),
+                Value::from(format!("{:?}", current_thread.id())),
+            );
+        }
+
+        // Synthesized span lifecycle events carry the span metadata, so they always
+        // get a span object, regular events only when the current span is requested.
+        if (meta.is_span() || self.display_current_span)
+            && let Some(span) = ctx.parent_span()
+        {
+            let mut span_object = Map::new();
+            let _old = span_object.insert("name".to_string(), Value::from(span.name()));
+            if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                span_object.extend(span_fields(&fields.fields));
+            }
+            let _old = context.insert("span".to_string(), Value::Object(span_object));
+        }
+        let ids = SpanIds::new(
+            ctx.parent_span().as_ref(),
+            self.display_span_id,
+            self.display_trace_id,
+        );
+        for (name, value) in ids.pairs() {
+            let _old = context.insert(name.to_string(), Value::from(value));
+        }
+
+        let encode = |values: &Map<String, Value>,
+                      (context, static_fields): &(Map<String, Value>, bool)| {
+            serde_json::to_string(&self.assemble(&head, values, context, *static_fields))
+        };
+        // the encoded size includes the line ending, the span then the static fields are shed if the record
+        // does not fit without its own fields
+        let mut values = visitor.values;
+        let mut context = (context, true);
+        let fitted = self.limits.fit_record(
+            &mut values,
+            &mut context,
+            |values, context| encode(values, context).map_or(0, |line| line.len() + 1),
+            |(context, static_fields)| {
+                context.shift_remove("span").is_some() || std::mem::replace(static_fields, false)
+            },
+        );
+        limits.mark(fitted);
+        let line = encode(&values, &context).map_err(|_| FmtError)?;
+        limits.finish();
+        writeln!(writer, "{line}")
+    }
+}
+
+/// Map a [`Level`] to the numeric Bunyan level.
+fn level(level: Level) -> u8 {
+    match level {
+        Level::TRACE => 10,
+        Level::DEBUG => 20,
+        Level::INFO => 30,
+        Level::WARN => 40,
+        Level::ERROR => 50,
+    }
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "bunyan")))]
+/// Create a [`Bunyan`] format layer configured from the given [`TracingConfig`].
+///
+/// Span enter/exit (and new/close) records are emitted when [`with_span_events`](TracingConfig::with_span_events)
+/// is configured.
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{bunyan, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = bunyan(&config);
+/// let layer = layer.with_filter(level_filter);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn bunyan<C, S>(config: &C) -> (fmt::Layer<S, JsonFields, Bunyan, Output>, LevelFilter)
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let output = Output::new(config);
+    let layer = fmt::layer().with_ansi(false).fmt_fields(JsonFields::new());
+
+    // span events can only be configured while the layer still has a `Format` event formatter
+    let layer = if let Some(fmt_span) = config.with_span_events() {
+        layer.with_span_events(fmt_span)
+    } else {
+        layer
+    };
+    let layer = layer.event_format(Bunyan::new(config)).with_writer(output);
+    let level = get_effective_level(config.quiet(), config.verbose());
+    let level_filter = LevelFilter::from(level);
+    (layer, level_filter)
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "bunyan")))]
+/// Create a [`Bunyan`] format filtered layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{bunyan_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let layer = bunyan_filtered(&config);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn filtered<C, S>(
+    config: &C,
+) -> Filtered<fmt::Layer<S, JsonFields, Bunyan, Output>, LevelFilter, S>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = bunyan(config);
+    layer.with_filter(level_filter)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+    use tracing::{Level, debug, error, info, span, trace, warn};
+    use tracing_subscriber::Layer;
+
+    use super::{bunyan, filtered as bunyan_filtered};
+
+    use crate::{
+        TestAll, set_default,
+        utils::test::{TestConfig, TestWriter},
+    };
+
+    #[test]
+    fn bunyan_filtered_works() {
+        let config = TestConfig;
+        let layer = bunyan_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "bunyan_filtered_works");
+        let _enter = span.enter();
+        error!("error level");
+        warn!("warn level");
+        info!("info level");
+        debug!("debug level");
+        trace!("trace level");
+    }
+
+    #[test]
+    fn bunyan_filtered_all_works() {
+        let config = TestAll;
+        let layer = bunyan_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::TRACE, "bunyan_filtered_all_works");
+        let _enter = span.enter();
+        error!("error level");
+        warn!("warn level");
+        info!("info level");
+        debug!("debug level");
+        trace!("trace level");
+    }
+
+    #[test]
+    fn bunyan_records_are_valid() {
+        let writer = TestWriter::default();
+        let config = TestAll;
+        let (layer, level_filter) = bunyan(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "bunyan_records_are_valid", answer = 42);
+        span.in_scope(|| warn!(count = 3, "warn level"));
+
+        let records = writer
+            .lines()
+            .iter()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<Value>>();
+        let event = records.iter().find(|r| r["msg"] == "warn level").unwrap();
+        assert_eq!(event["v"], 0);
+        assert_eq!(event["level"], 40);
+        assert_eq!(event["pid"], std::process::id());
+        assert_eq!(event["count"], 3);
+        assert!(event["name"].is_string());
+        assert!(event["hostname"].is_string());
+        assert!(event["time"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(event["span"]["answer"], 42);
+        assert_eq!(event["span_id"].as_str().unwrap().len(), 16);
+        assert_eq!(event["trace_id"].as_str().unwrap().len(), 32);
+
+        let enter = records.iter().find(|r| r["msg"] == "enter").unwrap();
+        assert_eq!(enter["level"], 30);
+        assert_eq!(enter["span"]["name"], "bunyan_records_are_valid");
+        assert!(records.iter().any(|r| r["msg"] == "exit"));
+    }
+}
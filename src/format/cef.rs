@@ -0,0 +1,427 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    fmt::{self as stdfmt, Result as FmtResult},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+    metadata::LevelFilter,
+};
+use tracing_subscriber::{
+    Layer,
+    filter::Filtered,
+    fmt::{
+        self, FmtContext, FormatEvent, FormatFields,
+        format::{DefaultFields, Writer},
+    },
+    registry::LookupSpan,
+};
+
+use crate::{
+    Output, TracingConfig, format::ids::SpanIds, limits::Limits, utils::get_effective_level,
+};
+
+/// The name of the event field used as the CEF signature ID, if present.
+const SIGNATURE_ID_FIELD: &str = "signature_id";
+
+/// The extension keys written by the format itself, which event and static fields are renamed away from.
+const RESERVED_KEYS: [&str; 3] = ["rt", "span_id", "trace_id"];
+
+/// The prefix of the event and static fields named after a reserved extension key, i.e. `field_rt`.
+const RESERVED_KEY_PREFIX: &str = "field_";
+
+/// A [`FormatEvent`] implementation that writes [Common Event Format](https://www.microfocus.com/documentation/arcsight/arcsight-smartconnectors/pdfdoc/common-event-format-v25/common-event-format-v25.pdf)
+/// records, one per line:
+///
+/// `CEF:0|Vendor|Product|Version|SignatureID|Name|Severity|Extension`
+///
+/// * The signature ID is taken from the `signature_id` event field, falling back to the event target.
+/// * The name is the event message.
+/// * The severity is mapped from the event level (`TRACE` 1, `DEBUG` 3, `INFO` 5, `WARN` 7, `ERROR` 9).
+/// * The extension holds the receipt time (`rt`, milliseconds since the Unix epoch) followed by the
+///   remaining event fields and the [`static_fields`](crate::TracingConfig::static_fields), then the
+///   `span_id` and `trace_id` of the current span when enabled.  Fields named `rt`, `span_id` or `trace_id`
+///   are prefixed with `field_` so they cannot be mistaken for these keys.
+/// * The [`limits`](crate::TracingConfig::limits) apply to the name and the event fields of the extension.  A
+///   record cut short by the maximum event size is never cut within an escape sequence.
+#[cfg_attr(docsrs, doc(cfg(feature = "cef")))]
+#[derive(Clone, Debug)]
+pub struct Cef {
+    vendor: String,
+    product: String,
+    version: String,
+    static_fields: Vec<(String, String)>,
+    limits: Limits,
+    display_span_id: bool,
+    display_trace_id: bool,
+}
+
+impl Cef {
+    /// Create a [`Cef`] formatter configured from the given [`TracingConfig`].
+    pub fn new<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self {
+            vendor: config.cef_vendor(),
+            product: config.cef_product(),
+            version: config.cef_version(),
+            static_fields: config.static_fields(),
+            limits: config.limits(),
+            display_span_id: config.with_span_ids(),
+            display_trace_id: config.with_trace_id(),
+        }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for Cef
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        let meta = event.metadata();
+        let mut limits = self.limits.event();
+        let mut visitor = CefVisitor::default();
+        event.record(&mut visitor);
+        let signature_id = visitor
+            .signature_id
+            .unwrap_or_else(|| meta.target().to_string());
+        let name = visitor.message.unwrap_or_default();
+
+        let mut line = String::new();
+        let mut record = Writer::new(&mut line);
+        write!(
+            record,
+            "CEF:0|{}|{}|{}|{}|{}|{}|rt={}",
+            escape_header(&self.vendor),
+            escape_header(&self.product),
+            escape_header(&self.version),
+            escape_header(&signature_id),
+            escape_header(&limits.value("message", &name)),
+            severity(*meta.level()),
+            receipt_time(),
+        )?;
+        for (key, value) in visitor.extension {
+            if limits.admit(&key) {
+                write!(
+                    record,
+                    " {key}={}",
+                    escape_extension(&limits.value(&key, &value))
+                )?;
+            }
+        }
+        for (key, value) in &self.static_fields {
+            write!(
+                record,
+                " {}={}",
+                extension_key(key),
+                escape_extension(value)
+            )?;
+        }
+        let ids = SpanIds::new(
+            ctx.parent_span().as_ref(),
+            self.display_span_id,
+            self.display_trace_id,
+        );
+        for (key, value) in ids.pairs() {
+            write!(record, " {key}={value}")?;
+        }
+        writeln!(record)?;
+        limits.mark(self.limits.truncate_escaped_event(&mut line, "\n", '\\'));
+        limits.finish();
+        writer.write_str(&line)
+    }
+}
+
+/// Map a [`Level`] to a CEF severity (0-10).
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::TRACE => 1,
+        Level::DEBUG => 3,
+        Level::INFO => 5,
+        Level::WARN => 7,
+        Level::ERROR => 9,
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+fn receipt_time() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis())
+        .unwrap_or_default()
+}
+
+/// Escape a header value: backslashes and pipes are escaped, line breaks are replaced by spaces.
+fn escape_header(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '|' => escaped.push_str("\\|"),
+            '\r' | '\n' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escape an extension value: backslashes and equal signs are escaped, line breaks are encoded as `\n` and `\r`.
+fn escape_extension(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '=' => escaped.push_str("\\="),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Extension keys may only contain alphanumeric characters, anything else is replaced with an underscore.
+/// Names of reserved keys are prefixed.
+fn extension_key(name: &str) -> String {
+    let name = name.strip_prefix("r#").unwrap_or(name);
+    let key: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if RESERVED_KEYS.contains(&key.as_str()) {
+        format!("{RESERVED_KEY_PREFIX}{key}")
+    } else {
+        key
+    }
+}
+
+#[derive(Debug, Default)]
+struct CefVisitor {
+    message: Option<String>,
+    signature_id: Option<String>,
+    extension: Vec<(String, String)>,
+}
+
+impl CefVisitor {
+    fn record(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "message" => self.message = Some(value),
+            SIGNATURE_ID_FIELD => self.signature_id = Some(value),
+            name => self.extension.push((extension_key(name), value)),
+        }
+    }
+}
+
+impl Visit for CefVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn stdfmt::Debug) {
+        self.record(field, format!("{value:?}"));
+    }
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "cef")))]
+/// Create a [`Cef`] format layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{cef, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = cef(&config);
+/// let layer = layer.with_filter(level_filter);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!(signature_id = "auth-100", user = "alice", "login succeeded");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn cef<C, S>(config: &C) -> (fmt::Layer<S, DefaultFields, Cef, Output>, LevelFilter)
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let layer = fmt::layer()
+        .with_ansi(false)
+        .event_format(Cef::new(config))
+        .with_writer(Output::new(config));
+    let level = get_effective_level(config.quiet(), config.verbose());
+    let level_filter = LevelFilter::from(level);
+    (layer, level_filter)
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "cef")))]
+/// Create a [`Cef`] format filtered layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{cef_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let layer = cef_filtered(&config);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!(signature_id = "auth-100", user = "alice", "login succeeded");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn filtered<C, S>(
+    config: &C,
+) -> Filtered<fmt::Layer<S, DefaultFields, Cef, Output>, LevelFilter, S>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = cef(config);
+    layer.with_filter(level_filter)
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::{Level, debug, error, info, span, trace, warn};
+    use tracing_subscriber::Layer;
+
+    use super::{cef, escape_extension, escape_header, extension_key, filtered as cef_filtered};
+
+    use crate::{
+        TestAll, TracingConfig, set_default,
+        utils::test::{TestConfig, TestWriter},
+    };
+
+    #[derive(Clone, Debug)]
+    struct TestCef;
+
+    impl TracingConfig for TestCef {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn cef_vendor(&self) -> String {
+            "Acme|Corp".to_string()
+        }
+
+        fn cef_product(&self) -> String {
+            "Gateway".to_string()
+        }
+
+        fn cef_version(&self) -> String {
+            "1.2.3".to_string()
+        }
+    }
+
+    #[test]
+    fn cef_filtered_works() {
+        let config = TestConfig;
+        let layer = cef_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "cef_filtered_works");
+        let _enter = span.enter();
+        error!("error level");
+        warn!("warn level");
+        info!("info level");
+        debug!("debug level");
+        trace!("trace level");
+    }
+
+    #[test]
+    fn cef_filtered_all_works() {
+        let config = TestAll;
+        let layer = cef_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::TRACE, "cef_filtered_all_works");
+        let _enter = span.enter();
+        error!("error level");
+        warn!("warn level");
+        info!("info level");
+        debug!("debug level");
+        trace!("trace level");
+    }
+
+    #[test]
+    fn escaping_works() {
+        assert_eq!(escape_header(r"a|b\c"), r"a\|b\\c");
+        assert_eq!(escape_header("two\nlines"), "two lines");
+        assert_eq!(escape_extension(r"a=b\c"), r"a\=b\\c");
+        assert_eq!(escape_extension("two\nlines"), r"two\nlines");
+    }
+
+    #[test]
+    fn reserved_keys_are_prefixed() {
+        assert_eq!(extension_key("rt"), "field_rt");
+        assert_eq!(extension_key("r#trace_id"), "field_trace_id");
+        assert_eq!(extension_key("span.id"), "field_span_id");
+        assert_eq!(extension_key("art"), "art");
+    }
+
+    #[test]
+    fn cef_records_are_valid() {
+        let writer = TestWriter::default();
+        let config = TestCef;
+        let (layer, level_filter) = cef(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        warn!(
+            signature_id = "auth-401",
+            user.name = "mallory",
+            query = "a=b",
+            rt = 1,
+            "login failed"
+        );
+        info!("no signature");
+
+        let lines = writer.lines();
+        assert!(
+            lines[0].starts_with("CEF:0|Acme\\|Corp|Gateway|1.2.3|auth-401|login failed|7|rt="),
+            "{}",
+            lines[0]
+        );
+        assert!(lines[0].ends_with(r" user_name=mallory query=a\=b field_rt=1"));
+        assert!(lines[1].starts_with(
+            "CEF:0|Acme\\|Corp|Gateway|1.2.3|tracing_subscriber_init::format::cef::test|no signature|5|rt="
+        ));
+    }
+
+    #[test]
+    fn span_and_trace_ids_are_extensions() {
+        let writer = TestWriter::default();
+        let (layer, _) = cef(&TestAll);
+        let _unused = set_default(vec![layer.with_writer(writer.clone()).boxed()]);
+        let span = span!(Level::INFO, "request");
+        span.in_scope(|| info!("inside"));
+
+        let lines = writer.lines();
+        let span_id = format!(" span_id={:016x} trace_id=", span.id().unwrap().into_u64());
+        let (_, trace_id) = lines[0].split_once(&span_id).expect("span and trace ids");
+        assert_eq!(trace_id.len(), 32);
+    }
+}
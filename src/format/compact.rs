@@ -14,9 +14,10 @@ use tracing_subscriber::{
         self,
         format::{Compact, DefaultFields, Format},
     },
+    registry::LookupSpan,
 };
 
-use crate::{TracingConfig, utils::get_effective_level};
+use crate::{Output, TracingConfig, format::text::TextFormat, utils::get_effective_level};
 
 /// Create a [`Compact`](tracing_subscriber::fmt::format::Compact) format layer configured from the given [`TracingConfig`].
 ///
@@ -90,6 +91,84 @@ where
     layer.with_filter(level_filter)
 }
 
+/// Create a [`Compact`](tracing_subscriber::fmt::format::Compact) style [`TextFormat`] layer configured from the given [`TracingConfig`].
+///
+/// Unlike [`compact`](crate::compact), the layer writes to the configured [`Output`] and applies the timer, colors, static
+/// fields, limits and the other text settings of the [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{compact_text, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = compact_text(&config);
+/// let layer = layer.with_filter(level_filter);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn compact_text<C, S>(
+    config: &C,
+) -> (
+    fmt::Layer<S, DefaultFields, TextFormat, Output>,
+    LevelFilter,
+)
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let output = Output::new(config);
+    let layer = fmt::layer().with_ansi(config.color().enabled_for(&output));
+
+    // span events can only be configured while the layer still has a `Format` event formatter
+    let layer = if let Some(fmt_span) = config.with_span_events() {
+        layer.with_span_events(fmt_span)
+    } else {
+        layer
+    };
+    let layer = layer
+        .event_format(TextFormat::compact(config))
+        .with_writer(output);
+    let level = get_effective_level(config.quiet(), config.verbose());
+    let level_filter = LevelFilter::from(level);
+    (layer, level_filter)
+}
+
+/// Create a [`Compact`](tracing_subscriber::fmt::format::Compact) style [`TextFormat`] filtered layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{compact_text_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let layer = compact_text_filtered(&config);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn text_filtered<C, S>(
+    config: &C,
+) -> Filtered<fmt::Layer<S, DefaultFields, TextFormat, Output>, LevelFilter, S>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = compact_text(config);
+    layer.with_filter(level_filter)
+}
+
 #[cfg(test)]
 mod test {
     use tracing::{Level, debug, error, info, span, trace, warn};
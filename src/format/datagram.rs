@@ -0,0 +1,58 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{io, os::unix::net::UnixDatagram, path::PathBuf, sync::Mutex};
+
+/// A lazily connected Unix datagram socket.
+///
+/// If a send fails the connection is re-established and the send retried once, so a restarted
+/// daemon is picked up without restarting the process.
+#[derive(Debug)]
+pub(crate) struct Datagram {
+    path: PathBuf,
+    socket: Mutex<Option<UnixDatagram>>,
+}
+
+impl Datagram {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            socket: Mutex::new(None),
+        }
+    }
+
+    fn connect(&self) -> io::Result<UnixDatagram> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(&self.path)?;
+        Ok(socket)
+    }
+
+    /// Run the given send function against the connected socket, reconnecting and retrying once on failure.
+    pub(crate) fn send_with<F>(&self, send: F) -> io::Result<()>
+    where
+        F: Fn(&UnixDatagram) -> io::Result<()>,
+    {
+        let mut socket = self
+            .socket
+            .lock()
+            .map_err(|_| io::Error::other("datagram socket lock poisoned"))?;
+
+        if let Some(connected) = socket.as_ref()
+            && send(connected).is_ok()
+        {
+            return Ok(());
+        }
+
+        // never connected, or the daemon went away, so (re)connect and retry once
+        *socket = None;
+        let connected = self.connect()?;
+        send(&connected)?;
+        *socket = Some(connected);
+        Ok(())
+    }
+}
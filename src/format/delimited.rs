@@ -0,0 +1,577 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    fmt::{Error as FmtError, Result as FmtResult},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use serde_json::{Map, Value};
+use tracing::{Event, Metadata, Subscriber, metadata::LevelFilter};
+use tracing_subscriber::{
+    Layer,
+    filter::Filtered,
+    fmt::{
+        self, FmtContext, FormatEvent, FormatFields,
+        format::{DefaultFields, Writer},
+        time::FormatTime,
+    },
+    registry::LookupSpan,
+};
+
+use crate::{
+    Output, Timer, TracingConfig,
+    format::{ids::SpanIds, visitor::JsonVisitor},
+    limits::Limits,
+    utils::get_effective_level,
+};
+
+/// A column of the [`Delimited`] format.
+#[cfg_attr(docsrs, doc(cfg(feature = "delimited")))]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Column {
+    /// The time the event was formatted, as configured by the [`timer`](crate::TracingConfig::timer)
+    Timestamp,
+    /// The event level
+    Level,
+    /// The event target
+    Target,
+    /// The source code file path of the event
+    File,
+    /// The source code line number of the event
+    Line,
+    /// The ID of the thread the event was emitted on
+    ThreadId,
+    /// The name of the thread the event was emitted on
+    ThreadName,
+    /// The ID of the current span, empty outside of a span
+    SpanId,
+    /// The trace ID of the current span, empty outside of a span
+    TraceId,
+    /// The event message
+    Message,
+    /// The remaining event fields, as a JSON object
+    Fields,
+}
+
+impl Column {
+    /// The default column set derived from the `with_*` settings of the given [`TracingConfig`].
+    ///
+    /// The timestamp, message and fields columns are always included.
+    pub fn from_config<C>(config: &C) -> Vec<Column>
+    where
+        C: TracingConfig + ?Sized,
+    {
+        [
+            (Column::Timestamp, true),
+            (Column::Level, config.with_level()),
+            (Column::Target, config.with_target()),
+            (Column::File, config.with_file()),
+            (Column::Line, config.with_line_number()),
+            (Column::ThreadId, config.with_thread_ids()),
+            (Column::ThreadName, config.with_thread_names()),
+            (Column::SpanId, config.with_span_ids()),
+            (Column::TraceId, config.with_trace_id()),
+            (Column::Message, true),
+            (Column::Fields, true),
+        ]
+        .into_iter()
+        .filter_map(|(column, enabled)| enabled.then_some(column))
+        .collect()
+    }
+
+    /// The name of this column in the header row.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Timestamp => "timestamp",
+            Column::Level => "level",
+            Column::Target => "target",
+            Column::File => "file",
+            Column::Line => "line",
+            Column::ThreadId => "thread_id",
+            Column::ThreadName => "thread_name",
+            Column::SpanId => "span_id",
+            Column::TraceId => "trace_id",
+            Column::Message => "message",
+            Column::Fields => "fields",
+        }
+    }
+}
+
+/// The field delimiter of the [`Delimited`] format.
+#[cfg_attr(docsrs, doc(cfg(feature = "delimited")))]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Delimiter {
+    /// Comma separated values
+    #[default]
+    Comma,
+    /// Tab separated values
+    Tab,
+}
+
+impl Delimiter {
+    fn as_char(self) -> char {
+        match self {
+            Delimiter::Comma => ',',
+            Delimiter::Tab => '\t',
+        }
+    }
+}
+
+/// A [`FormatEvent`] implementation that writes one delimited (CSV or TSV) row per event.
+///
+/// A header row naming the columns is written before the first row.  With the [`delimited`] layer, the
+/// configured [`Output`] writes the header instead, at the start of every new or empty log file, including the
+/// files started by a rotation, and before the first row on the other destinations.  Values containing the
+/// delimiter, quotes or line breaks are quoted as described in [RFC 4180](https://www.rfc-editor.org/rfc/rfc4180).
+/// The configured [`limits`](crate::TracingConfig::limits) apply to the message and fields columns.
+#[cfg_attr(docsrs, doc(cfg(feature = "delimited")))]
+#[derive(Clone, Debug)]
+pub struct Delimited {
+    columns: Vec<Column>,
+    delimiter: Delimiter,
+    timer: Timer,
+    static_fields: Vec<(String, String)>,
+    limits: Limits,
+    header_written: Arc<AtomicBool>,
+    /// The header of the [`Output`] writing the header row, while it is alive
+    output_header: Option<Weak<[u8]>>,
+}
+
+impl Delimited {
+    /// Create a [`Delimited`] formatter configured from the given [`TracingConfig`].
+    pub fn new<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self {
+            columns: config.columns(),
+            delimiter: config.delimiter(),
+            timer: Timer::new(&config.timer()),
+            static_fields: config.static_fields(),
+            limits: config.limits(),
+            header_written: Arc::new(AtomicBool::new(false)),
+            output_header: None,
+        }
+    }
+
+    /// The header row naming the columns.
+    fn header(&self) -> Result<String, FmtError> {
+        let mut row = String::new();
+        self.write_row(
+            &mut Writer::new(&mut row),
+            self.columns.iter().map(|column| column.name().to_string()),
+        )?;
+        Ok(row)
+    }
+
+    /// Whether or not the header row is written by an [`Output`] rather than by this formatter.
+    fn output_writes_header(&self) -> bool {
+        self.output_header
+            .as_ref()
+            .is_some_and(|header| header.strong_count() > 0)
+    }
+
+    fn write_row<I>(&self, writer: &mut Writer<'_>, values: I) -> FmtResult
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let delimiter = self.delimiter.as_char();
+        for (idx, value) in values.into_iter().enumerate() {
+            if idx > 0 {
+                writer.write_char(delimiter)?;
+            }
+            write_value(writer, &value, delimiter)?;
+        }
+        writeln!(writer)
+    }
+
+    /// Render the row of an event from its fields, the message included.
+    fn event_row(
+        &self,
+        meta: &Metadata<'_>,
+        timestamp: &str,
+        ids: &SpanIds,
+        fields: &Map<String, Value>,
+    ) -> Result<String, FmtError> {
+        let mut visitor = JsonVisitor {
+            values: fields.clone(),
+        };
+        let message = visitor.take_message().unwrap_or_default();
+        for (key, value) in &self.static_fields {
+            let _value = visitor
+                .values
+                .entry(key.clone())
+                .or_insert_with(|| Value::from(value.as_str()));
+        }
+        let current_thread = std::thread::current();
+
+        let values = self.columns.iter().map(|column| match column {
+            Column::Timestamp => timestamp.to_string(),
+            Column::Level => meta.level().to_string(),
+            Column::Target => meta.target().to_string(),
+            Column::File => meta.file().unwrap_or_default().to_string(),
+            Column::Line => meta.line().map(|line| line.to_string()).unwrap_or_default(),
+            Column::ThreadId => format!("{:?}", current_thread.id()),
+            Column::ThreadName => current_thread.name().unwrap_or_default().to_string(),
+            Column::SpanId => ids.span_id.clone().unwrap_or_default(),
+            Column::TraceId => ids.trace_id.clone().unwrap_or_default(),
+            Column::Message => message.clone(),
+            Column::Fields => Value::Object(visitor.values.clone()).to_string(),
+        });
+        let mut row = String::new();
+        self.write_row(&mut Writer::new(&mut row), values)?;
+        Ok(row)
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for Delimited
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        if !self.output_writes_header() && !self.header_written.swap(true, Ordering::AcqRel) {
+            writer.write_str(&self.header()?)?;
+        }
+
+        let meta = event.metadata();
+        let mut limits = self.limits.event();
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        limits.apply(&mut visitor.values);
+        let mut timestamp = String::new();
+        self.timer.format_time(&mut Writer::new(&mut timestamp))?;
+        let ids = SpanIds::new(
+            ctx.parent_span().as_ref(),
+            self.columns.contains(&Column::SpanId),
+            self.columns.contains(&Column::TraceId),
+        );
+
+        let mut fields = visitor.values;
+        let fitted = self.limits.fit_record(
+            &mut fields,
+            &mut (),
+            |fields, ()| {
+                self.event_row(meta, &timestamp, &ids, fields)
+                    .map_or(0, |row| row.len())
+            },
+            |()| false,
+        );
+        limits.mark(fitted);
+        let row = self.event_row(meta, &timestamp, &ids, &fields)?;
+        limits.finish();
+        writer.write_str(&row)
+    }
+}
+
+/// Write a single value, quoting it if it contains the delimiter, a quote or a line break.
+fn write_value(writer: &mut Writer<'_>, value: &str, delimiter: char) -> FmtResult {
+    if value.contains([delimiter, '"', '\n', '\r']) {
+        writer.write_char('"')?;
+        writer.write_str(&value.replace('"', "\"\""))?;
+        writer.write_char('"')
+    } else {
+        writer.write_str(value)
+    }
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "delimited")))]
+/// Create a [`Delimited`] format layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{delimited, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = delimited(&config);
+/// let layer = layer.with_filter(level_filter);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn delimited<C, S>(config: &C) -> (fmt::Layer<S, DefaultFields, Delimited, Output>, LevelFilter)
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let mut format = Delimited::new(config);
+    let header: Arc<[u8]> = Arc::from(format.header().unwrap_or_default().into_bytes());
+    format.output_header = Some(Arc::downgrade(&header));
+    let output = Output::with_header(config, Some(header));
+    let layer = fmt::layer().with_ansi(false);
+
+    // span events can only be configured while the layer still has a `Format` event formatter
+    let layer = if let Some(fmt_span) = config.with_span_events() {
+        layer.with_span_events(fmt_span)
+    } else {
+        layer
+    };
+    let layer = layer.event_format(format).with_writer(output);
+    let level = get_effective_level(config.quiet(), config.verbose());
+    let level_filter = LevelFilter::from(level);
+    (layer, level_filter)
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "delimited")))]
+/// Create a [`Delimited`] format filtered layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{delimited_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let layer = delimited_filtered(&config);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn filtered<C, S>(
+    config: &C,
+) -> Filtered<fmt::Layer<S, DefaultFields, Delimited, Output>, LevelFilter, S>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = delimited(config);
+    layer.with_filter(level_filter)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use tracing::{Level, debug, error, info, span, trace, warn};
+    use tracing_subscriber::Layer;
+
+    use super::{Column, Delimiter, delimited, filtered as delimited_filtered};
+
+    use crate::{
+        FileOutput, RollingFile, Rotation, TestAll, TracingConfig, set_default,
+        utils::test::{TestConfig, TestWriter},
+    };
+
+    #[derive(Clone, Debug)]
+    struct TestTsv;
+
+    impl TracingConfig for TestTsv {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn columns(&self) -> Vec<Column> {
+            vec![Column::Level, Column::Message, Column::Fields]
+        }
+
+        fn delimiter(&self) -> Delimiter {
+            Delimiter::Tab
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestIds;
+
+    impl TracingConfig for TestIds {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn columns(&self) -> Vec<Column> {
+            vec![Column::SpanId, Column::TraceId, Column::Message]
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestRotated {
+        directory: PathBuf,
+    }
+
+    impl TracingConfig for TestRotated {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn columns(&self) -> Vec<Column> {
+            vec![Column::Level, Column::Message]
+        }
+
+        fn file_output(&self) -> Option<FileOutput> {
+            Some(FileOutput {
+                directory: self.directory.clone(),
+                prefix: "app".to_string(),
+                rotation: Rotation {
+                    max_size: Some(40),
+                    interval: None,
+                },
+                ..FileOutput::default()
+            })
+        }
+    }
+
+    #[test]
+    fn delimited_filtered_works() {
+        let config = TestConfig;
+        let layer = delimited_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "delimited_filtered_works");
+        let _enter = span.enter();
+        error!("error level");
+        warn!("warn level");
+        info!("info level");
+        debug!("debug level");
+        trace!("trace level");
+    }
+
+    #[test]
+    fn delimited_filtered_all_works() {
+        let config = TestAll;
+        let layer = delimited_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::TRACE, "delimited_filtered_all_works");
+        let _enter = span.enter();
+        error!("error level");
+        warn!("warn level");
+        info!("info level");
+        debug!("debug level");
+        trace!("trace level");
+    }
+
+    #[test]
+    fn default_columns_follow_config() {
+        assert_eq!(
+            Column::from_config(&TestConfig),
+            vec![
+                Column::Timestamp,
+                Column::Level,
+                Column::Message,
+                Column::Fields
+            ]
+        );
+        assert_eq!(Column::from_config(&TestAll).len(), 11);
+    }
+
+    #[test]
+    fn csv_header_once_and_quoting() {
+        let writer = TestWriter::default();
+        let config = TestConfig;
+        let (layer, level_filter) = delimited(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        info!(user = "a,b", "first");
+        info!(count = 2, "say \"hi\"");
+
+        let lines = writer.lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "timestamp,level,message,fields");
+        assert!(lines[1].ends_with(r#",INFO,first,"{""user"":""a,b""}""#));
+        assert!(lines[2].ends_with(r#",INFO,"say ""hi""","{""count"":2}""#));
+    }
+
+    #[test]
+    fn tsv_columns_from_config() {
+        let writer = TestWriter::default();
+        let config = TestTsv;
+        let (layer, level_filter) = delimited(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        warn!(count = 2, "tab separated");
+
+        assert_eq!(
+            writer.lines(),
+            vec![
+                "level\tmessage\tfields".to_string(),
+                "WARN\ttab separated\t\"{\"\"count\"\":2}\"".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn span_and_trace_id_columns() {
+        let writer = TestWriter::default();
+        let (layer, _) = delimited(&TestIds);
+        let _unused = set_default(vec![layer.with_writer(writer.clone()).boxed()]);
+        info!("outside");
+        let span = span!(Level::INFO, "request");
+        span.in_scope(|| info!("inside"));
+
+        let lines = writer.lines();
+        assert_eq!(lines[0], "span_id,trace_id,message");
+        assert_eq!(lines[1], ",,outside");
+        let row = lines[2].split(',').collect::<Vec<_>>();
+        assert_eq!(row[0], format!("{:016x}", span.id().unwrap().into_u64()));
+        assert_eq!(row[1].len(), 32);
+        assert_eq!(row[2], "inside");
+    }
+
+    #[test]
+    fn header_starts_every_file() {
+        let directory = std::env::temp_dir().join(format!("tsi-delimited-{}", std::process::id()));
+        let _res = fs::remove_dir_all(&directory);
+        let config = TestRotated {
+            directory: directory.clone(),
+        };
+        // the second layer appends to the file of the first, as after a restart
+        for messages in [&["first"][..], &["second", "third"]] {
+            let (layer, _) = delimited(&config);
+            let _unused = set_default(vec![layer.boxed()]);
+            for message in messages {
+                info!("{message}");
+            }
+        }
+
+        let file = RollingFile::new(config.file_output().unwrap());
+        let mut contents = file
+            .rotated_files()
+            .unwrap()
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect::<Vec<_>>();
+        contents.push(fs::read_to_string(file.path()).unwrap());
+        assert_eq!(
+            contents,
+            vec![
+                "level,message\nINFO,first\nINFO,second\n",
+                "level,message\nINFO,third\n",
+            ]
+        );
+        let _res = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    hash::{BuildHasher, Hasher, RandomState},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+
+/// The trace ID of a root span, generated the first time an event within it is formatted.
+#[derive(Clone, Copy, Debug)]
+struct TraceId(u128);
+
+impl TraceId {
+    fn generate() -> Self {
+        static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+        let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(sequence);
+        let high = hasher.finish();
+        hasher.write_u64(sequence);
+        let low = hasher.finish();
+        Self(u128::from(high) << 64 | u128::from(low))
+    }
+}
+
+/// The span and trace IDs of the current span, as enabled by
+/// [`with_span_ids`](crate::TracingConfig::with_span_ids) and [`with_trace_id`](crate::TracingConfig::with_trace_id).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct SpanIds {
+    /// The ID of the current span, as 16 hex digits
+    pub(crate) span_id: Option<String>,
+    /// The trace ID shared by all spans under the same root span, as 32 hex digits
+    pub(crate) trace_id: Option<String>,
+}
+
+impl SpanIds {
+    /// The IDs of the given current span, if any.
+    pub(crate) fn new<R>(
+        span: Option<&SpanRef<'_, R>>,
+        with_span_ids: bool,
+        with_trace_id: bool,
+    ) -> Self
+    where
+        R: for<'a> LookupSpan<'a>,
+    {
+        let Some(span) = span else {
+            return Self::default();
+        };
+        let span_id = with_span_ids.then(|| format!("{:016x}", span.id().into_u64()));
+        let trace_id = with_trace_id.then(|| {
+            let root = span.scope().from_root().next();
+            let root = root.as_ref().unwrap_or(span);
+            let existing = root.extensions().get::<TraceId>().copied();
+            let trace_id = existing.unwrap_or_else(|| {
+                // another thread may have assigned the ID since it was read
+                let mut extensions = root.extensions_mut();
+                if let Some(trace_id) = extensions.get_mut::<TraceId>() {
+                    *trace_id
+                } else {
+                    let trace_id = TraceId::generate();
+                    extensions.insert(trace_id);
+                    trace_id
+                }
+            });
+            format!("{:032x}", trace_id.0)
+        });
+        Self { span_id, trace_id }
+    }
+
+    /// The enabled IDs as `(name, value)` pairs.
+    pub(crate) fn pairs(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [("span_id", &self.span_id), ("trace_id", &self.trace_id)]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_deref().map(|value| (name, value)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        sync::{Arc, Barrier},
+        thread,
+    };
+
+    use tracing::{Dispatch, Level, dispatcher, info, span};
+    use tracing_subscriber::{layer::SubscriberExt, registry};
+
+    use super::TraceId;
+    use crate::{TimerConfig, TracingConfig, compact_text, utils::test::TestWriter};
+
+    #[derive(Clone, Copy, Debug)]
+    struct Traced;
+
+    impl TracingConfig for Traced {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            0
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_trace_id(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn trace_ids_are_unique() {
+        assert_ne!(TraceId::generate().0, TraceId::generate().0);
+    }
+
+    #[test]
+    fn threads_share_the_trace_id_of_a_new_root_span() {
+        const ROOTS: usize = 1000;
+        const THREADS: usize = 8;
+        let output = TestWriter::default();
+        let (layer, _) = compact_text(&Traced);
+        let dispatch = Dispatch::new(registry().with(layer.with_writer(output.clone())));
+        let roots: Arc<Vec<_>> = Arc::new(dispatcher::with_default(&dispatch, || {
+            (0..ROOTS).map(|_| span!(Level::INFO, "root")).collect()
+        }));
+        let barrier = Arc::new(Barrier::new(THREADS));
+        // every thread formats the first event of each root at about the same time
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let dispatch = dispatch.clone();
+                let roots = Arc::clone(&roots);
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    dispatcher::with_default(&dispatch, || {
+                        let _ready = barrier.wait();
+                        for root in roots.iter() {
+                            root.in_scope(|| info!("formatted"));
+                        }
+                    });
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let lines = output.lines();
+        assert_eq!(lines.len(), ROOTS * THREADS);
+        let trace_ids: HashSet<&str> = lines
+            .iter()
+            .filter_map(|line| {
+                line.split_whitespace()
+                    .find(|word| word.starts_with("trace_id="))
+            })
+            .collect();
+        assert_eq!(trace_ids.len(), ROOTS);
+    }
+}
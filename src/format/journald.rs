@@ -0,0 +1,542 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Write},
+    mem::MaybeUninit,
+    os::{fd::AsFd, unix::net::UnixDatagram},
+};
+
+use rustix::{
+    cmsg_space,
+    fs::{MemfdFlags, SealFlags, fcntl_add_seals, memfd_create},
+    io::Errno,
+    net::{SendAncillaryBuffer, SendAncillaryMessage, SendFlags, sendmsg},
+};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+    metadata::LevelFilter,
+    span::{Attributes, Id, Record},
+};
+use tracing_subscriber::{Layer, filter::Filtered, layer::Context, registry::LookupSpan};
+
+use crate::{
+    TracingConfig,
+    format::{datagram::Datagram, ids::SpanIds},
+    limits::{EventLimits, Limits},
+    utils::{get_effective_level, syslog_severity},
+};
+
+/// The default path of the systemd journal socket.
+pub(crate) const DEFAULT_JOURNALD_PATH: &str = "/run/systemd/journal/socket";
+
+/// A [`Layer`] that writes events to the systemd journal using the
+/// [native journal protocol](https://systemd.io/JOURNAL_NATIVE_PROTOCOL/).
+///
+/// Each event is sent with the `PRIORITY`, `SYSLOG_IDENTIFIER`, `TARGET`, `CODE_FILE` and `CODE_LINE` fields,
+/// a `SPAN_NAME` field per entered span, `SPAN_ID` and `TRACE_ID` fields for the current span if enabled, and the
+/// span, event and static fields as uppercase journal fields (the event `message` becomes `MESSAGE`).  The configured [`limits`](crate::TracingConfig::limits) apply to the
+/// event fields, a `TRUNCATED` field marks entries shrunk to the maximum event size.  Entries too large for a
+/// single datagram are passed to the journal in a sealed memfd.
+///
+/// Send failures are ignored, so a missing journal never interrupts the application.
+#[cfg_attr(docsrs, doc(cfg(all(feature = "journald", target_os = "linux"))))]
+#[derive(Debug)]
+pub struct Journald {
+    socket: Datagram,
+    syslog_identifier: String,
+    static_fields: Vec<(String, String)>,
+    limits: Limits,
+    display_span_id: bool,
+    display_trace_id: bool,
+}
+
+impl Journald {
+    /// Create a [`Journald`] layer configured from the given [`TracingConfig`].
+    pub fn new<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self {
+            socket: Datagram::new(config.journald_path()),
+            syslog_identifier: config.app_name(),
+            static_fields: config.static_fields(),
+            limits: config.limits(),
+            display_span_id: config.with_span_ids(),
+            display_trace_id: config.with_trace_id(),
+        }
+    }
+
+    fn send(&self, payload: &[u8]) -> io::Result<()> {
+        self.socket.send_with(|socket| match socket.send(payload) {
+            Ok(_) => Ok(()),
+            Err(e) if is_too_large(&e) => send_memfd(socket, payload),
+            Err(e) => Err(e),
+        })
+    }
+}
+
+/// The journal fields recorded on a span, by journal field name.
+///
+/// The span extensions are shared by every layer of the subscriber, so the fields are recorded once by the first
+/// [`Journald`] layer, and recording a field again replaces its value.
+#[derive(Debug, Default)]
+struct SpanFields(Vec<(String, Vec<u8>)>);
+
+impl SpanFields {
+    fn record(&mut self, name: String, value: Vec<u8>) {
+        if let Some((_, existing)) = self.0.iter_mut().find(|(key, _)| *key == name) {
+            *existing = value;
+        } else {
+            self.0.push((name, value));
+        }
+    }
+}
+
+impl<S> Layer<S> for Journald
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if extensions.get_mut::<SpanFields>().is_none() {
+                let mut fields = SpanFields::default();
+                attrs.record(&mut JournalVisitor(&mut fields));
+                extensions.insert(fields);
+            }
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(fields) = span.extensions_mut().get_mut::<SpanFields>()
+        {
+            values.record(&mut JournalVisitor(fields));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let mut payload = Vec::new();
+        put_field(
+            &mut payload,
+            "PRIORITY",
+            syslog_severity(*meta.level()).to_string().as_bytes(),
+        );
+        put_field(
+            &mut payload,
+            "SYSLOG_IDENTIFIER",
+            self.syslog_identifier.as_bytes(),
+        );
+        put_field(&mut payload, "TARGET", meta.target().as_bytes());
+        if let Some(file) = meta.file() {
+            put_field(&mut payload, "CODE_FILE", file.as_bytes());
+        }
+        if let Some(line) = meta.line() {
+            put_field(&mut payload, "CODE_LINE", line.to_string().as_bytes());
+        }
+
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                put_field(&mut payload, "SPAN_NAME", span.name().as_bytes());
+                if let Some(fields) = span.extensions().get::<SpanFields>() {
+                    for (name, value) in &fields.0 {
+                        put_field(&mut payload, name, value);
+                    }
+                }
+            }
+        }
+        let ids = SpanIds::new(
+            ctx.event_span(event).as_ref(),
+            self.display_span_id,
+            self.display_trace_id,
+        );
+        if let Some(span_id) = &ids.span_id {
+            put_field(&mut payload, "SPAN_ID", span_id.as_bytes());
+        }
+        if let Some(trace_id) = &ids.trace_id {
+            put_field(&mut payload, "TRACE_ID", trace_id.as_bytes());
+        }
+
+        let mut limits = self.limits.event();
+        let mut visitor = EventVisitor {
+            limits: &mut limits,
+            fields: Vec::new(),
+        };
+        event.record(&mut visitor);
+        let mut fields = visitor.fields;
+        let encode = |fields: &[(String, String)]| {
+            let mut payload = payload.clone();
+            for (key, value) in fields.iter().chain(&self.static_fields) {
+                if let Some(name) = field_name(key) {
+                    put_field(&mut payload, &name, value.as_bytes());
+                }
+            }
+            payload
+        };
+        let fitted = self
+            .limits
+            .fit_pairs(&mut fields, |fields| encode(fields).len());
+        limits.mark(fitted);
+        limits.finish();
+        let _res = self.send(&encode(&fields));
+    }
+}
+
+/// Records visited fields as journal fields.
+struct JournalVisitor<'a>(&'a mut SpanFields);
+
+impl Visit for JournalVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if let Some(name) = field_name(field.name()) {
+            self.0.record(name, value.as_bytes().to_vec());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if let Some(name) = field_name(field.name()) {
+            self.0.record(name, format!("{value:?}").into_bytes());
+        }
+    }
+}
+
+/// Collects the event fields, applying the limits.
+struct EventVisitor<'a> {
+    limits: &'a mut EventLimits,
+    fields: Vec<(String, String)>,
+}
+
+impl EventVisitor<'_> {
+    fn record(&mut self, field: &Field, value: &str) {
+        let name = field.name().strip_prefix("r#").unwrap_or(field.name());
+        if self.limits.admit(name) {
+            let value = self.limits.value(name, value).into_owned();
+            self.fields.push((name.to_string(), value));
+        }
+    }
+}
+
+impl Visit for EventVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, &format!("{value:?}"));
+    }
+}
+
+/// Convert a tracing field name to a valid journal field name.
+///
+/// Journal field names consist of uppercase letters, digits and underscores, may not start with an
+/// underscore (those are trusted fields) or a digit, and are at most 64 characters long.
+fn field_name(name: &str) -> Option<String> {
+    let name = name.strip_prefix("r#").unwrap_or(name);
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let sanitized = sanitized.trim_start_matches(|c: char| c == '_' || c.is_ascii_digit());
+    (!sanitized.is_empty()).then(|| sanitized.chars().take(64).collect())
+}
+
+/// Append a field to the payload, using the binary form for values containing a newline.
+fn put_field(payload: &mut Vec<u8>, name: &str, value: &[u8]) {
+    payload.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        payload.push(b'\n');
+        payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        payload.push(b'=');
+    }
+    payload.extend_from_slice(value);
+    payload.push(b'\n');
+}
+
+fn is_too_large(e: &io::Error) -> bool {
+    matches!(
+        Errno::from_io_error(e),
+        Some(Errno::MSGSIZE | Errno::NOBUFS)
+    )
+}
+
+/// Pass a payload too large for a datagram to the journal as a sealed memfd.
+fn send_memfd(socket: &UnixDatagram, payload: &[u8]) -> io::Result<()> {
+    let fd = memfd_create(
+        "tracing-journald",
+        MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING,
+    )?;
+    let mut file = File::from(fd);
+    file.write_all(payload)?;
+    fcntl_add_seals(
+        &file,
+        SealFlags::SHRINK | SealFlags::GROW | SealFlags::WRITE | SealFlags::SEAL,
+    )?;
+
+    let fds = [file.as_fd()];
+    let mut space = [MaybeUninit::uninit(); cmsg_space!(ScmRights(1))];
+    let mut control = SendAncillaryBuffer::new(&mut space);
+    if !control.push(SendAncillaryMessage::ScmRights(&fds)) {
+        return Err(io::Error::other("unable to attach the journal memfd"));
+    }
+    let _sent = sendmsg(socket, &[], &mut control, SendFlags::empty())?;
+    Ok(())
+}
+
+#[cfg_attr(docsrs, doc(cfg(all(feature = "journald", target_os = "linux"))))]
+/// Create a [`Journald`] layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{journald, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = journald(&config);
+/// let layer = layer.with_filter(level_filter);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn journald<C>(config: &C) -> (Journald, LevelFilter)
+where
+    C: TracingConfig,
+{
+    let layer = Journald::new(config);
+    let level = get_effective_level(config.quiet(), config.verbose());
+    let level_filter = LevelFilter::from(level);
+    (layer, level_filter)
+}
+
+#[cfg_attr(docsrs, doc(cfg(all(feature = "journald", target_os = "linux"))))]
+/// Create a [`Journald`] filtered layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{journald_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let layer = journald_filtered(&config);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn filtered<C, S>(config: &C) -> Filtered<Journald, LevelFilter, S>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = journald(config);
+    layer.with_filter(level_filter)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs::File,
+        io::{IoSliceMut, Read, Seek, SeekFrom},
+        mem::MaybeUninit,
+        os::unix::net::UnixDatagram,
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use rustix::{
+        cmsg_space,
+        net::{RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, recvmsg},
+    };
+    use tracing::{Level, info, span, warn};
+    use tracing_subscriber::Layer;
+
+    use super::{field_name, filtered as journald_filtered, put_field};
+
+    use crate::{TracingConfig, set_default};
+
+    #[derive(Clone, Debug)]
+    struct TestJournald {
+        path: PathBuf,
+    }
+
+    impl TracingConfig for TestJournald {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn app_name(&self) -> String {
+            "journald-test".to_string()
+        }
+
+        fn journald_path(&self) -> PathBuf {
+            self.path.clone()
+        }
+
+        fn with_span_ids(&self) -> bool {
+            true
+        }
+
+        fn with_trace_id(&self) -> bool {
+            true
+        }
+    }
+
+    fn listen(path: &Path) -> UnixDatagram {
+        let _res = std::fs::remove_file(path);
+        let socket = UnixDatagram::bind(path).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+    }
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tsi-{name}-{}.sock", std::process::id()))
+    }
+
+    #[test]
+    fn field_names_are_sanitized() {
+        assert_eq!(field_name("message"), Some("MESSAGE".to_string()));
+        assert_eq!(field_name("http.status"), Some("HTTP_STATUS".to_string()));
+        assert_eq!(field_name("r#type"), Some("TYPE".to_string()));
+        assert_eq!(field_name("_secret"), Some("SECRET".to_string()));
+        assert_eq!(field_name("1st"), Some("ST".to_string()));
+        assert_eq!(field_name("__"), None);
+        assert_eq!(field_name(&"a".repeat(80)).unwrap().len(), 64);
+    }
+
+    #[test]
+    fn multi_line_values_use_binary_form() {
+        let mut payload = Vec::new();
+        put_field(&mut payload, "A", b"one");
+        put_field(&mut payload, "B", b"two\nlines");
+        let mut expected = b"A=one\nB\n".to_vec();
+        expected.extend_from_slice(&9_u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\n");
+        assert_eq!(payload, expected);
+    }
+
+    #[test]
+    fn journald_fields_work() {
+        let path = socket_path("journald");
+        let listener = listen(&path);
+        let config = TestJournald { path: path.clone() };
+        let layer = journald_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+
+        let span = span!(Level::INFO, "request", request_id = 7);
+        span.in_scope(|| warn!(http.status = 503, "upstream failed"));
+
+        let mut buf = vec![0; 65536];
+        let len = listener.recv(&mut buf).unwrap();
+        let entry = String::from_utf8_lossy(&buf[..len]).into_owned();
+        assert!(entry.contains("PRIORITY=4\n"), "{entry}");
+        assert!(entry.contains("SYSLOG_IDENTIFIER=journald-test\n"));
+        assert!(entry.contains("TARGET=tracing_subscriber_init::format::journald::test\n"));
+        assert!(entry.contains("CODE_FILE=src/format/journald.rs\n"));
+        assert!(entry.contains("CODE_LINE="));
+        assert!(entry.contains("SPAN_NAME=request\n"));
+        let span_id = span.id().unwrap().into_u64();
+        assert!(entry.contains(&format!("SPAN_ID={span_id:016x}\n")));
+        assert!(entry.contains("TRACE_ID="));
+        assert!(entry.contains("REQUEST_ID=7\n"));
+        assert!(entry.contains("HTTP_STATUS=503\n"));
+        assert!(entry.contains("MESSAGE=upstream failed\n"));
+        let _res = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn layers_share_span_fields() {
+        let path = socket_path("journald-shared");
+        let listener = listen(&path);
+        let config = TestJournald { path: path.clone() };
+        let _unused = set_default(vec![
+            journald_filtered(&config).boxed(),
+            journald_filtered(&config).boxed(),
+        ]);
+
+        let span = span!(
+            Level::INFO,
+            "request",
+            request_id = 7,
+            user = tracing::field::Empty
+        );
+        span.record("user", "alice");
+        span.in_scope(|| info!("shared"));
+
+        let mut buf = vec![0; 65536];
+        for _ in 0..2 {
+            let len = listener.recv(&mut buf).unwrap();
+            let entry = String::from_utf8_lossy(&buf[..len]).into_owned();
+            assert_eq!(entry.matches("REQUEST_ID=7\n").count(), 1, "{entry}");
+            assert_eq!(entry.matches("USER=alice\n").count(), 1, "{entry}");
+        }
+        let _res = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn large_entries_use_memfd() {
+        let path = socket_path("journald-memfd");
+        let listener = listen(&path);
+        let config = TestJournald { path: path.clone() };
+        let layer = journald_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+
+        let body = "x".repeat(4 * 1024 * 1024);
+        info!(body, "large entry");
+
+        let mut buf = [0; 16];
+        let mut space = [MaybeUninit::uninit(); cmsg_space!(ScmRights(1))];
+        let mut control = RecvAncillaryBuffer::new(&mut space);
+        let msg = recvmsg(
+            &listener,
+            &mut [IoSliceMut::new(&mut buf)],
+            &mut control,
+            RecvFlags::empty(),
+        )
+        .unwrap();
+        assert_eq!(msg.bytes, 0);
+
+        let fd = control
+            .drain()
+            .find_map(|message| match message {
+                RecvAncillaryMessage::ScmRights(mut fds) => fds.next(),
+                _ => None,
+            })
+            .unwrap();
+        let mut file = File::from(fd);
+        let _pos = file.seek(SeekFrom::Start(0)).unwrap();
+        let mut entry = String::new();
+        let _len = file.read_to_string(&mut entry).unwrap();
+        assert!(entry.contains("MESSAGE=large entry\n"));
+        assert!(entry.contains(&format!("BODY={body}\n")));
+        let _res = std::fs::remove_file(&path);
+    }
+}
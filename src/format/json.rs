@@ -6,17 +6,398 @@
 // option. All files in the project carrying such notice may not be copied,
 // modified, or distributed except according to those terms.
 
-use tracing::{Subscriber, metadata::LevelFilter};
+use std::fmt::{Error as FmtError, Result as FmtResult};
+
+use serde_json::{Map, Value};
+use tracing::{Event, Subscriber, metadata::LevelFilter};
 use tracing_subscriber::{
     Layer,
     filter::Filtered,
     fmt::{
-        self,
-        format::{Format, Json, JsonFields},
+        self, FmtContext, FormatEvent, FormatFields, FormattedFields,
+        format::{Format, Json, JsonFields, Writer},
+        time::FormatTime,
     },
+    registry::{LookupSpan, SpanRef},
 };
 
-use crate::{TracingConfig, utils::get_effective_level};
+use crate::{
+    Output, Timer, TracingConfig,
+    format::{
+        ids::SpanIds,
+        visitor::{JsonVisitor, span_fields},
+    },
+    limits::Limits,
+    utils::get_effective_level,
+};
+
+/// The keys used by the [`JsonFormat`] formatter.
+///
+/// The defaults match the output of the [`tracing-subscriber`](tracing_subscriber::fmt::format::Json) JSON format.
+/// Override only the keys you need, i.e.
+///
+/// ```rust
+/// # use tracing_subscriber_init::JsonKeys;
+/// let keys = JsonKeys {
+///     timestamp: "@timestamp".to_string(),
+///     level: "severity".to_string(),
+///     ..JsonKeys::default()
+/// };
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct JsonKeys {
+    /// The key of the event timestamp, defaults to `timestamp`
+    pub timestamp: String,
+    /// The key of the event level, defaults to `level`
+    pub level: String,
+    /// The key of the event message, defaults to `message`
+    pub message: String,
+    /// The key of the object holding the event fields, defaults to `fields`.
+    /// When the event is flattened, it holds only the fields whose names collide with the other keys
+    pub fields: String,
+    /// The key of the event target, defaults to `target`
+    pub target: String,
+    /// The key of the event source file, defaults to `filename`
+    pub filename: String,
+    /// The key of the event source line number, defaults to `line_number`
+    pub line_number: String,
+    /// The key of the current span, defaults to `span`
+    pub span: String,
+    /// The key of the span list, defaults to `spans`
+    pub spans: String,
+    /// The key of the current span ID, defaults to `span_id`
+    pub span_id: String,
+    /// The key of the trace ID, defaults to `trace_id`
+    pub trace_id: String,
+    /// The key of the thread name, defaults to `threadName`
+    pub thread_name: String,
+    /// The key of the thread ID, defaults to `threadId`
+    pub thread_id: String,
+}
+
+impl Default for JsonKeys {
+    fn default() -> Self {
+        Self {
+            timestamp: "timestamp".to_string(),
+            level: "level".to_string(),
+            message: "message".to_string(),
+            fields: "fields".to_string(),
+            target: "target".to_string(),
+            filename: "filename".to_string(),
+            line_number: "line_number".to_string(),
+            span: "span".to_string(),
+            spans: "spans".to_string(),
+            span_id: "span_id".to_string(),
+            trace_id: "trace_id".to_string(),
+            thread_name: "threadName".to_string(),
+            thread_id: "threadId".to_string(),
+        }
+    }
+}
+
+/// A [`FormatEvent`] implementation that writes one JSON object per event, shaped by the JSON settings of a
+/// [`TracingConfig`].
+///
+/// With the default settings the output matches the [`tracing-subscriber`](tracing_subscriber::fmt::format::Json)
+/// JSON format.  On top of that the event can be flattened, every key can be renamed (see [`JsonKeys`]) and
+/// the fields of the spans in scope can be merged into the event fields, and the current span and trace IDs can be
+/// added.  The configured
+/// [`limits`](crate::TracingConfig::limits) apply to the event fields.
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug)]
+pub struct JsonFormat<T = Timer> {
+    timer: T,
+    keys: JsonKeys,
+    flatten_event: bool,
+    merge_span_fields: bool,
+    static_fields: Vec<(String, String)>,
+    limits: Limits,
+    display_level: bool,
+    display_target: bool,
+    display_filename: bool,
+    display_line_number: bool,
+    display_thread_id: bool,
+    display_thread_name: bool,
+    display_current_span: bool,
+    display_span_list: bool,
+    display_span_id: bool,
+    display_trace_id: bool,
+}
+
+impl JsonFormat {
+    /// Create a [`JsonFormat`] formatter configured from the given [`TracingConfig`].
+    pub fn new<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self {
+            timer: Timer::new(&config.timer()),
+            keys: config.json_keys(),
+            flatten_event: config.flatten_event(),
+            merge_span_fields: config.merge_span_fields(),
+            static_fields: config.static_fields(),
+            limits: config.limits(),
+            display_level: config.with_level(),
+            display_target: config.with_target(),
+            display_filename: config.with_file(),
+            display_line_number: config.with_line_number(),
+            display_thread_id: config.with_thread_ids(),
+            display_thread_name: config.with_thread_names(),
+            display_current_span: config.with_current_span(),
+            display_span_list: config.with_span_list(),
+            display_span_id: config.with_span_ids(),
+            display_trace_id: config.with_trace_id(),
+        }
+    }
+}
+
+impl<T> JsonFormat<T> {
+    /// Use the given [`FormatTime`] implementation for the event timestamp.
+    pub fn with_timer<T2>(self, timer: T2) -> JsonFormat<T2> {
+        JsonFormat {
+            timer,
+            keys: self.keys,
+            flatten_event: self.flatten_event,
+            merge_span_fields: self.merge_span_fields,
+            static_fields: self.static_fields,
+            limits: self.limits,
+            display_level: self.display_level,
+            display_target: self.display_target,
+            display_filename: self.display_filename,
+            display_line_number: self.display_line_number,
+            display_thread_id: self.display_thread_id,
+            display_thread_name: self.display_thread_name,
+            display_current_span: self.display_current_span,
+            display_span_list: self.display_span_list,
+            display_span_id: self.display_span_id,
+            display_trace_id: self.display_trace_id,
+        }
+    }
+
+    /// Assemble the record from its leading entries, the event fields and its context.
+    fn assemble(
+        &self,
+        head: &Map<String, Value>,
+        values: &Map<String, Value>,
+        context: &RecordContext,
+    ) -> Map<String, Value> {
+        let keys = &self.keys;
+        let tail = &context.tail;
+        let mut record = head.clone();
+        let mut fields = context.span_values.clone();
+        let mut visitor = JsonVisitor {
+            values: values.clone(),
+        };
+        if let Some(message) = visitor.take_message() {
+            let _old = visitor
+                .values
+                .insert(keys.message.clone(), Value::from(message));
+        }
+        fields.extend(visitor.values);
+        let static_fields = if context.static_fields {
+            self.static_fields.as_slice()
+        } else {
+            &[]
+        };
+        for (key, value) in static_fields {
+            let _value = fields
+                .entry(key.clone())
+                .or_insert_with(|| Value::from(value.as_str()));
+        }
+
+        if self.flatten_event {
+            // fields colliding with the record keys are kept under the fields key rather than dropped
+            let mut collisions = Map::new();
+            for (key, value) in fields {
+                if key == keys.fields || record.contains_key(&key) || tail.contains_key(&key) {
+                    let _old = collisions.insert(key, value);
+                } else {
+                    let _old = record.insert(key, value);
+                }
+            }
+            if !collisions.is_empty() {
+                let _old = record.insert(keys.fields.clone(), Value::Object(collisions));
+            }
+        } else {
+            let _old = record.insert(keys.fields.clone(), Value::Object(fields));
+        }
+        record.extend(tail.clone());
+        record
+    }
+}
+
+/// Serialize a span as an object of its fields plus its name.
+fn span_object<S, N>(span: &SpanRef<'_, S>) -> Value
+where
+    S: for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    let mut object = span
+        .extensions()
+        .get::<FormattedFields<N>>()
+        .map(|fields| span_fields(&fields.fields))
+        .unwrap_or_default();
+    let _old = object.insert("name".to_string(), Value::from(span.name()));
+    Value::Object(object)
+}
+
+/// The entries of a record beyond the event fields that may be shed to fit the maximum event size.
+struct RecordContext {
+    /// The merged span fields
+    span_values: Map<String, Value>,
+    /// The trailing entries, i.e. the target and the spans
+    tail: Map<String, Value>,
+    /// Whether or not the static fields are added
+    static_fields: bool,
+}
+
+impl RecordContext {
+    /// Shed the span list, the merged span fields, the current span and the static fields in turn, returning
+    /// whether or not anything was shed.
+    fn shed(&mut self, keys: &JsonKeys) -> bool {
+        if self.tail.shift_remove(&keys.spans).is_some() {
+            true
+        } else if !self.span_values.is_empty() {
+            self.span_values.clear();
+            true
+        } else if self.tail.shift_remove(&keys.span).is_some() {
+            true
+        } else {
+            std::mem::replace(&mut self.static_fields, false)
+        }
+    }
+}
+
+impl<S, N, T> FormatEvent<S, N> for JsonFormat<T>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    T: FormatTime,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        let meta = event.metadata();
+        let keys = &self.keys;
+        let mut head = Map::new();
+
+        let mut timestamp = String::new();
+        self.timer.format_time(&mut Writer::new(&mut timestamp))?;
+        if !timestamp.is_empty() {
+            let _old = head.insert(keys.timestamp.clone(), Value::from(timestamp));
+        }
+
+        if self.display_level {
+            let _old = head.insert(keys.level.clone(), Value::from(meta.level().as_str()));
+        }
+
+        let current_span = ctx.parent_span();
+        let mut span_values = Map::new();
+        if self.merge_span_fields
+            && let Some(span) = &current_span
+        {
+            // root to leaf, so the innermost span wins on duplicate keys
+            for span in span.scope().from_root() {
+                if let Some(formatted) = span.extensions().get::<FormattedFields<N>>() {
+                    span_values.extend(span_fields(&formatted.fields));
+                }
+            }
+        }
+        let mut limits = self.limits.event();
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        limits.apply(&mut visitor.values);
+
+        let mut tail = Map::new();
+        if self.display_target {
+            let _old = tail.insert(keys.target.clone(), Value::from(meta.target()));
+        }
+        if self.display_filename
+            && let Some(filename) = meta.file()
+        {
+            let _old = tail.insert(keys.filename.clone(), Value::from(filename));
+        }
+        if self.display_line_number
+            && let Some(line_number) = meta.line()
+        {
+            let _old = tail.insert(keys.line_number.clone(), Value::from(line_number));
+        }
+
+        if let Some(span) = &current_span {
+            if self.display_current_span {
+                let _old = tail.insert(keys.span.clone(), span_object::<S, N>(span));
+            }
+            if self.display_span_list {
+                let spans = span
+                    .scope()
+                    .from_root()
+                    .map(|span| span_object::<S, N>(&span))
+                    .collect();
+                let _old = tail.insert(keys.spans.clone(), Value::Array(spans));
+            }
+        }
+        let ids = SpanIds::new(
+            current_span.as_ref(),
+            self.display_span_id,
+            self.display_trace_id,
+        );
+        if let Some(span_id) = ids.span_id {
+            let _old = tail.insert(keys.span_id.clone(), Value::from(span_id));
+        }
+        if let Some(trace_id) = ids.trace_id {
+            let _old = tail.insert(keys.trace_id.clone(), Value::from(trace_id));
+        }
+
+        let current_thread = std::thread::current();
+        if self.display_thread_name {
+            match current_thread.name() {
+                Some(name) => {
+                    let _old = tail.insert(keys.thread_name.clone(), Value::from(name));
+                }
+                // fall-back to thread id when name is absent and ids are not enabled
+                None if !self.display_thread_id => {
+                    let _old = tail.insert(
+                        keys.thread_name.clone(),
+                        Value::from(format!("{:?}", current_thread.id())),
+                    );
+                }
+                None => {}
+            }
+        }
+        if self.display_thread_id {
+            let _old = tail.insert(
+                keys.thread_id.clone(),
+                Value::from(format!("{:?}", current_thread.id())),
+            );
+        }
+
+        let encode = |values: &Map<String, Value>, context: &RecordContext| {
+            serde_json::to_string(&self.assemble(&head, values, context))
+        };
+        let mut context = RecordContext {
+            span_values,
+            tail,
+            static_fields: true,
+        };
+        // the encoded size includes the line ending
+        let mut values = visitor.values;
+        let fitted = self.limits.fit_record(
+            &mut values,
+            &mut context,
+            |values, context| encode(values, context).map_or(0, |line| line.len() + 1),
+            |context| context.shed(keys),
+        );
+        limits.mark(fitted);
+        let line = encode(&values, &context).map_err(|_| FmtError)?;
+        limits.finish();
+        writeln!(writer, "{line}")
+    }
+}
 
 #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
 /// Create a [`Json`](tracing_subscriber::fmt::format::Json) format layer configured from the given [`TracingConfig`].
@@ -95,14 +476,242 @@ where
     layer.with_filter(level_filter)
 }
 
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+/// Create a [`JsonFormat`] format layer configured from the given [`TracingConfig`].
+///
+/// Unlike [`json`](crate::json), the layer writes to the configured [`Output`] and applies the timer, key names,
+/// static fields, limits and the other JSON settings of the [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{json_format, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = json_format(&config);
+/// let layer = layer.with_filter(level_filter);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn json_format<C, S>(config: &C) -> (fmt::Layer<S, JsonFields, JsonFormat, Output>, LevelFilter)
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let output = Output::new(config);
+    let layer = fmt::layer()
+        .with_ansi(config.with_ansi())
+        .fmt_fields(JsonFields::new());
+
+    // span events can only be configured while the layer still has a `Format` event formatter
+    let layer = if let Some(fmt_span) = config.with_span_events() {
+        layer.with_span_events(fmt_span)
+    } else {
+        layer
+    };
+    let layer = layer
+        .event_format(JsonFormat::new(config))
+        .with_writer(output);
+    let level = get_effective_level(config.quiet(), config.verbose());
+    let level_filter = LevelFilter::from(level);
+    (layer, level_filter)
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+/// Create a [`JsonFormat`] format filtered layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{json_format_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let layer = json_format_filtered(&config);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn format_filtered<C, S>(
+    config: &C,
+) -> Filtered<fmt::Layer<S, JsonFields, JsonFormat, Output>, LevelFilter, S>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = json_format(config);
+    layer.with_filter(level_filter)
+}
+
 #[cfg(test)]
 mod test {
     use tracing::{Level, debug, error, info, span, trace, warn};
     use tracing_subscriber::Layer;
 
-    use super::filtered as json_filtered;
+    use serde_json::{Value, json as json_value};
+
+    use super::{JsonKeys, filtered as json_filtered, json_format};
+
+    use crate::{
+        Limits, TRUNCATION_MARKER, TestAll, TracingConfig, set_default, truncated_events,
+        utils::test::{TestConfig, TestWriter},
+    };
+
+    #[derive(Clone, Debug)]
+    struct TestShaped;
+
+    impl TracingConfig for TestShaped {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn flatten_event(&self) -> bool {
+            true
+        }
+
+        fn json_keys(&self) -> JsonKeys {
+            JsonKeys {
+                timestamp: "@timestamp".to_string(),
+                level: "severity".to_string(),
+                message: "msg".to_string(),
+                ..JsonKeys::default()
+            }
+        }
+
+        fn merge_span_fields(&self) -> bool {
+            true
+        }
+
+        fn static_fields(&self) -> Vec<(String, String)> {
+            vec![
+                ("service".to_string(), "api".to_string()),
+                ("user".to_string(), "static".to_string()),
+            ]
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestNested;
+
+    impl TracingConfig for TestNested {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn json_keys(&self) -> JsonKeys {
+            JsonKeys {
+                fields: "data".to_string(),
+                ..JsonKeys::default()
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestIds;
 
-    use crate::{TestAll, set_default, utils::test::TestConfig};
+    impl TracingConfig for TestIds {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn json_keys(&self) -> JsonKeys {
+            JsonKeys {
+                trace_id: "traceId".to_string(),
+                ..JsonKeys::default()
+            }
+        }
+
+        fn with_span_ids(&self) -> bool {
+            true
+        }
+
+        fn with_trace_id(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestLimited(Limits);
+
+    impl TracingConfig for TestLimited {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn limits(&self) -> Limits {
+            self.0
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestLimitedContext;
+
+    impl TracingConfig for TestLimitedContext {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn limits(&self) -> Limits {
+            Limits {
+                max_event_size: Some(200),
+                ..Limits::default()
+            }
+        }
+
+        fn merge_span_fields(&self) -> bool {
+            true
+        }
+
+        fn with_current_span(&self) -> bool {
+            true
+        }
+
+        fn with_span_list(&self) -> bool {
+            true
+        }
+
+        fn static_fields(&self) -> Vec<(String, String)> {
+            vec![("service".to_string(), "s".repeat(60))]
+        }
+    }
+
+    fn records(writer: &TestWriter) -> Vec<Value> {
+        writer
+            .lines()
+            .iter()
+            .map(|line| serde_json::from_str(line).expect("valid JSON"))
+            .collect()
+    }
 
     #[test]
     fn json_filtered_works() {
@@ -153,4 +762,225 @@ mod test {
         debug!("debug level");
         trace!("trace level");
     }
+
+    #[test]
+    fn default_shape_matches_tracing_subscriber() {
+        let writer = TestWriter::default();
+        let config = TestConfig;
+        let (layer, level_filter) = json_format(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        info!(count = 2, "default shape");
+
+        let record = &records(&writer)[0];
+        let keys: Vec<&str> = record
+            .as_object()
+            .expect("object")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(keys, vec!["timestamp", "level", "fields"]);
+        assert_eq!(record["level"], "INFO");
+        assert_eq!(
+            record["fields"],
+            json_value!({ "count": 2, "message": "default shape" })
+        );
+    }
+
+    #[test]
+    fn flattened_and_renamed() {
+        let writer = TestWriter::default();
+        let config = TestShaped;
+        let (layer, level_filter) = json_format(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let outer = span!(Level::INFO, "outer", request_id = 7, user = "root");
+        outer.in_scope(|| {
+            let inner = span!(Level::INFO, "inner", user = "alice");
+            inner.in_scope(|| info!(user = "bob", "shaped"));
+            inner.in_scope(|| info!("inherited"));
+        });
+
+        let records = records(&writer);
+        assert!(records[0]["@timestamp"].is_string());
+        assert_eq!(records[0]["severity"], "INFO");
+        assert_eq!(records[0]["msg"], "shaped");
+        assert_eq!(records[0]["user"], "bob");
+        assert_eq!(records[0]["request_id"], 7);
+        assert_eq!(records[0]["service"], "api");
+        assert!(records[0].get("fields").is_none());
+        assert!(records[0].get("timestamp").is_none());
+        assert_eq!(records[1]["user"], "alice");
+    }
+
+    #[test]
+    fn flattened_collisions_are_nested() {
+        let writer = TestWriter::default();
+        let config = TestShaped;
+        let (layer, level_filter) = json_format(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "colliding", severity = "span");
+        span.in_scope(|| info!(fields = 3, user = "bob", "collide"));
+
+        let record = &records(&writer)[0];
+        assert_eq!(record["severity"], "INFO");
+        assert_eq!(record["msg"], "collide");
+        assert_eq!(record["user"], "bob");
+        assert_eq!(
+            record["fields"],
+            json_value!({ "severity": "span", "fields": 3 })
+        );
+    }
+
+    #[test]
+    fn custom_fields_key() {
+        let writer = TestWriter::default();
+        let config = TestNested;
+        let (layer, level_filter) = json_format(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "not_merged", request_id = 7);
+        span.in_scope(|| warn!(count = 3, "nested"));
+
+        let record = &records(&writer)[0];
+        assert_eq!(
+            record["data"],
+            json_value!({ "count": 3, "message": "nested" })
+        );
+    }
+
+    #[cfg(feature = "tstime")]
+    #[test]
+    fn timer_from_config() {
+        use crate::{TimeFormat, TimerConfig};
+
+        #[derive(Clone, Debug)]
+        struct TestTimer;
+
+        impl TracingConfig for TestTimer {
+            fn quiet(&self) -> u8 {
+                0
+            }
+
+            fn verbose(&self) -> u8 {
+                1
+            }
+
+            fn timer(&self) -> TimerConfig {
+                TimerConfig::Utc(TimeFormat::custom("[year]/[month]/[day]").expect("format"))
+            }
+        }
+
+        let writer = TestWriter::default();
+        let (layer, level_filter) = json_format(&TestTimer);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        info!("custom timestamp");
+
+        let record = &records(&writer)[0];
+        let timestamp = record["timestamp"].as_str().expect("timestamp");
+        assert_eq!(timestamp.len(), 10, "{timestamp}");
+        assert_eq!(&timestamp[4..5], "/");
+    }
+
+    #[test]
+    fn limits_are_applied() {
+        let writer = TestWriter::default();
+        let config = TestLimited(Limits {
+            max_message_len: Some(8),
+            max_field_len: Some(4),
+            max_fields: Some(2),
+            ..Limits::default()
+        });
+        let (layer, level_filter) = json_format(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let before = truncated_events();
+        info!(a = "abcdefgh", b = 1, c = 2, "a long message");
+        info!(a = "abcd", "short");
+
+        let records = records(&writer);
+        assert_eq!(
+            records[0]["fields"],
+            json_value!({
+                "a": format!("abcd{TRUNCATION_MARKER}"),
+                "b": 1,
+                "message": format!("a long m{TRUNCATION_MARKER}"),
+            })
+        );
+        assert_eq!(
+            records[1]["fields"],
+            json_value!({ "a": "abcd", "message": "short" })
+        );
+        assert!(truncated_events() > before);
+    }
+
+    #[test]
+    fn max_event_size_keeps_records_valid() {
+        let writer = TestWriter::default();
+        let config = TestLimited(Limits {
+            max_event_size: Some(160),
+            ..Limits::default()
+        });
+        let (layer, level_filter) = json_format(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let body = "x".repeat(100);
+        info!(id = 1, body = body.as_str(), "{}", "y".repeat(100));
+        info!(id = 2, "fits");
+
+        let lines = writer.lines();
+        assert!(lines[0].len() < 160, "{}", lines[0]);
+        let records = records(&writer);
+        let fields = &records[0]["fields"];
+        assert_eq!(fields["truncated"], true);
+        assert!(fields.get("body").is_none());
+        let message = fields["message"].as_str().expect("message");
+        assert!(message.ends_with(TRUNCATION_MARKER), "{message}");
+        assert!(records[1]["fields"].get("truncated").is_none());
+    }
+
+    #[test]
+    fn max_event_size_covers_the_record_context() {
+        let writer = TestWriter::default();
+        let (layer, level_filter) = json_format(&TestLimitedContext);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let context = "c".repeat(80);
+        span!(Level::INFO, "outer", context = context.as_str()).in_scope(|| {
+            span!(Level::INFO, "inner", more = context.as_str()).in_scope(|| {
+                info!(id = 1, "message");
+            });
+        });
+
+        let lines = writer.lines();
+        assert!(lines[0].len() <= 200, "{}", lines[0]);
+        let records = records(&writer);
+        assert!(records[0].get("spans").is_none());
+        let fields = &records[0]["fields"];
+        assert_eq!(fields["truncated"], true);
+        assert!(fields.get("context").is_none());
+        assert!(fields.get("more").is_none());
+        // the message is kept whole once the context is shed
+        assert_eq!(fields["message"], "message");
+    }
+
+    #[test]
+    fn span_and_trace_ids_are_added() {
+        let writer = TestWriter::default();
+        let (layer, _) = json_format(&TestIds);
+        let _unused = set_default(vec![layer.with_writer(writer.clone()).boxed()]);
+        info!("outside");
+        let root = span!(Level::INFO, "root");
+        root.in_scope(|| span!(Level::INFO, "child").in_scope(|| info!("in child")));
+
+        let records = records(&writer);
+        assert!(records[0].get("span_id").is_none());
+        assert!(records[0].get("traceId").is_none());
+        let span_id = records[1]["span_id"].as_str().unwrap();
+        assert_eq!(span_id.len(), 16);
+        assert_ne!(span_id, format!("{:016x}", root.id().unwrap().into_u64()));
+        assert_eq!(records[1]["traceId"].as_str().unwrap().len(), 32);
+    }
 }
@@ -6,8 +6,33 @@
 // option. All files in the project carrying such notice may not be copied,
 // modified, or distributed except according to those terms.
 
+#[cfg(feature = "bunyan")]
+pub(crate) mod bunyan;
+#[cfg(feature = "cef")]
+pub(crate) mod cef;
 pub(crate) mod compact;
+#[cfg(any(
+    all(feature = "syslog", unix),
+    all(feature = "journald", target_os = "linux")
+))]
+mod datagram;
+#[cfg(feature = "delimited")]
+pub(crate) mod delimited;
 pub(crate) mod full;
+pub(crate) mod ids;
+#[cfg(all(feature = "journald", target_os = "linux"))]
+pub(crate) mod journald;
 #[cfg(feature = "json")]
 pub(crate) mod json;
+#[cfg(feature = "msgpack")]
+pub(crate) mod msgpack;
 pub(crate) mod pretty;
+pub(crate) mod recorder;
+pub(crate) mod route;
+#[cfg(all(feature = "syslog", unix))]
+pub(crate) mod syslog;
+#[cfg(feature = "template")]
+pub(crate) mod template;
+pub(crate) mod text;
+#[cfg(any(feature = "delimited", feature = "json", feature = "msgpack"))]
+mod visitor;
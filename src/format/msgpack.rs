@@ -0,0 +1,564 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::io::{self, ErrorKind, Read, Stdout, Write};
+
+use anyhow::{Context as _, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::{
+    Event, Subscriber,
+    metadata::LevelFilter,
+    span::{Attributes, Id, Record},
+};
+use tracing_subscriber::{
+    Layer,
+    filter::Filtered,
+    fmt::{MakeWriter, format::Writer, time::FormatTime},
+    layer::Context,
+    registry::LookupSpan,
+};
+
+use crate::{
+    Output, Timer, TracingConfig,
+    format::{ids::SpanIds, visitor::JsonVisitor},
+    limits::{EventLimits, Limits},
+    utils::get_effective_level,
+};
+
+/// A single record of the [`MsgPack`] format.
+///
+/// Carries the same information as the `json` format: the optional entries are [`None`] when disabled in
+/// the [`TracingConfig`].
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct MsgPackRecord {
+    /// The event timestamp, empty if the [`timer`](crate::TracingConfig::timer) writes no timestamp
+    pub timestamp: String,
+    /// The event level
+    pub level: Option<String>,
+    /// The event target
+    pub target: Option<String>,
+    /// The source code file path of the event
+    pub filename: Option<String>,
+    /// The source code line number of the event
+    pub line_number: Option<u32>,
+    /// The name of the thread the event was emitted on
+    pub thread_name: Option<String>,
+    /// The ID of the thread the event was emitted on
+    pub thread_id: Option<String>,
+    /// The ID of the current span
+    pub span_id: Option<String>,
+    /// The trace ID of the current span
+    pub trace_id: Option<String>,
+    /// The spans in scope, from root to leaf
+    pub spans: Vec<MsgPackSpan>,
+    /// The event fields, including the `message`, with their native types
+    pub fields: Map<String, Value>,
+}
+
+/// A span of a [`MsgPackRecord`].
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct MsgPackSpan {
+    /// The span name
+    pub name: String,
+    /// The span fields, with their native types
+    pub fields: Map<String, Value>,
+}
+
+/// A [`Layer`] that writes each event as a length-delimited [MessagePack](https://msgpack.org) record.
+///
+/// Each record is a [`MsgPackRecord`] encoded as a `MessagePack` map, prefixed with its length as a 4 byte
+/// big-endian integer.  Use [`MsgPackReader`] to read the records back.  The configured
+/// [`limits`](crate::TracingConfig::limits) apply to the event fields.
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug)]
+pub struct MsgPack<W = fn() -> Stdout> {
+    make_writer: W,
+    timer: Timer,
+    static_fields: Vec<(String, String)>,
+    limits: Limits,
+    display_level: bool,
+    display_target: bool,
+    display_filename: bool,
+    display_line_number: bool,
+    display_thread_id: bool,
+    display_thread_name: bool,
+    display_span_id: bool,
+    display_trace_id: bool,
+}
+
+impl MsgPack {
+    /// Create a [`MsgPack`] layer writing to standard output, configured from the given [`TracingConfig`].
+    pub fn new<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self {
+            make_writer: io::stdout,
+            timer: Timer::new(&config.timer()),
+            static_fields: config.static_fields(),
+            limits: config.limits(),
+            display_level: config.with_level(),
+            display_target: config.with_target(),
+            display_filename: config.with_file(),
+            display_line_number: config.with_line_number(),
+            display_thread_id: config.with_thread_ids(),
+            display_thread_name: config.with_thread_names(),
+            display_span_id: config.with_span_ids(),
+            display_trace_id: config.with_trace_id(),
+        }
+    }
+}
+
+impl<W> MsgPack<W> {
+    /// Write the records to the given [`MakeWriter`].
+    pub fn with_writer<W2>(self, make_writer: W2) -> MsgPack<W2>
+    where
+        W2: for<'writer> MakeWriter<'writer> + 'static,
+    {
+        MsgPack {
+            make_writer,
+            timer: self.timer,
+            static_fields: self.static_fields,
+            limits: self.limits,
+            display_level: self.display_level,
+            display_target: self.display_target,
+            display_filename: self.display_filename,
+            display_line_number: self.display_line_number,
+            display_thread_id: self.display_thread_id,
+            display_thread_name: self.display_thread_name,
+            display_span_id: self.display_span_id,
+            display_trace_id: self.display_trace_id,
+        }
+    }
+
+    fn record<S>(
+        &self,
+        event: &Event<'_>,
+        ctx: &Context<'_, S>,
+        limits: &mut EventLimits,
+    ) -> MsgPackRecord
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let meta = event.metadata();
+        let mut timestamp = String::new();
+        // a failure to get the time leaves the timestamp empty
+        let _res = self.timer.format_time(&mut Writer::new(&mut timestamp));
+
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .from_root()
+                    .map(|span| MsgPackSpan {
+                        name: span.name().to_string(),
+                        fields: span
+                            .extensions()
+                            .get::<SpanFields>()
+                            .map(|fields| fields.0.clone())
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let ids = SpanIds::new(
+            ctx.event_span(event).as_ref(),
+            self.display_span_id,
+            self.display_trace_id,
+        );
+
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        limits.apply(&mut visitor.values);
+
+        let current_thread = std::thread::current();
+        MsgPackRecord {
+            timestamp,
+            level: self.display_level.then(|| meta.level().to_string()),
+            target: self.display_target.then(|| meta.target().to_string()),
+            filename: meta
+                .file()
+                .filter(|_| self.display_filename)
+                .map(str::to_string),
+            line_number: meta.line().filter(|_| self.display_line_number),
+            thread_name: current_thread
+                .name()
+                .filter(|_| self.display_thread_name)
+                .map(str::to_string),
+            thread_id: self
+                .display_thread_id
+                .then(|| format!("{:?}", current_thread.id())),
+            span_id: ids.span_id,
+            trace_id: ids.trace_id,
+            spans,
+            fields: visitor.values,
+        }
+    }
+
+    /// Add the static fields to the given event fields, event fields take precedence.
+    fn add_static_fields(&self, fields: &mut Map<String, Value>) {
+        for (key, value) in &self.static_fields {
+            let _value = fields
+                .entry(key.clone())
+                .or_insert_with(|| Value::from(value.as_str()));
+        }
+    }
+}
+
+/// The fields recorded on a span.
+///
+/// The span extensions are shared by every layer of the subscriber, so the fields are recorded once by the first
+/// [`MsgPack`] layer.
+#[derive(Debug)]
+struct SpanFields(Map<String, Value>);
+
+impl<S, W> Layer<S> for MsgPack<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if extensions.get_mut::<SpanFields>().is_none() {
+                let mut visitor = JsonVisitor::default();
+                attrs.record(&mut visitor);
+                extensions.insert(SpanFields(visitor.values));
+            }
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(fields) = span.extensions_mut().get_mut::<SpanFields>()
+        {
+            let mut visitor = JsonVisitor::default();
+            values.record(&mut visitor);
+            fields.0.extend(visitor.values);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut limits = self.limits.event();
+        let mut record = self.record(event, &ctx, &mut limits);
+        let mut fields = std::mem::take(&mut record.fields);
+        // the encoded size includes the length prefix, the spans then the static fields are shed if the record
+        // does not fit without its own fields
+        let mut context = (record, true);
+        let fitted = self.limits.fit_record(
+            &mut fields,
+            &mut context,
+            |fields, (record, static_fields)| {
+                let mut record = record.clone();
+                record.fields.clone_from(fields);
+                if *static_fields {
+                    self.add_static_fields(&mut record.fields);
+                }
+                encode(&record).map_or(0, |frame| frame.len())
+            },
+            |(record, static_fields)| {
+                !std::mem::take(&mut record.spans).is_empty()
+                    || std::mem::replace(static_fields, false)
+            },
+        );
+        limits.mark(fitted);
+        let (mut record, static_fields) = context;
+        record.fields = fields;
+        if static_fields {
+            self.add_static_fields(&mut record.fields);
+        }
+        limits.finish();
+        if let Ok(frame) = encode(&record) {
+            let mut writer = self.make_writer.make_writer_for(event.metadata());
+            // write failures are ignored, as with the text formats
+            let _res = writer.write_all(&frame);
+        }
+    }
+}
+
+/// Encode a record as a length-delimited `MessagePack` frame.
+fn encode(record: &MsgPackRecord) -> Result<Vec<u8>> {
+    let payload = rmp_serde::to_vec_named(record)?;
+    let len = u32::try_from(payload.len())?;
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Reads the length-delimited records written by the [`MsgPack`] layer.
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing_subscriber_init::MsgPackReader;
+/// #
+/// # pub fn main() -> Result<()> {
+/// let log: &[u8] = &[];
+/// for record in MsgPackReader::new(log) {
+///     let record = record?;
+///     println!("{:?} {:?}", record.level, record.fields.get("message"));
+/// }
+/// #   Ok(())
+/// # }
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+#[derive(Debug)]
+pub struct MsgPackReader<R> {
+    reader: R,
+    max_record_len: usize,
+}
+
+impl<R> MsgPackReader<R>
+where
+    R: Read,
+{
+    /// The default maximum length of a record, 16 MiB.
+    pub const DEFAULT_MAX_RECORD_LEN: usize = 16 * 1024 * 1024;
+
+    /// Create a [`MsgPackReader`] reading from the given reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            max_record_len: Self::DEFAULT_MAX_RECORD_LEN,
+        }
+    }
+
+    /// Set the maximum length of a record, longer records are rejected before they are read.
+    /// This defaults to [`DEFAULT_MAX_RECORD_LEN`](Self::DEFAULT_MAX_RECORD_LEN)
+    #[must_use]
+    pub fn with_max_record_len(mut self, max_record_len: usize) -> Self {
+        self.max_record_len = max_record_len;
+        self
+    }
+
+    /// Read the next record, or [`None`] at the end of the input.
+    ///
+    /// # Errors
+    /// * An error is returned if the input cannot be read, ends within a record, announces a record longer than
+    ///   the maximum record length, or a record cannot be decoded.
+    ///
+    pub fn read_record(&mut self) -> Result<Option<MsgPackRecord>> {
+        let mut len = [0; 4];
+        let mut filled = 0;
+        while filled < len.len() {
+            match self.reader.read(&mut len[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => bail!("the input ends within the length of a record"),
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let len = usize::try_from(u32::from_be_bytes(len))?;
+        if len > self.max_record_len {
+            bail!(
+                "record length {len} exceeds the maximum record length {}",
+                self.max_record_len
+            );
+        }
+        let mut payload = vec![0; len];
+        self.reader
+            .read_exact(&mut payload)
+            .context("the input ends within a record")?;
+        Ok(Some(rmp_serde::from_slice(&payload)?))
+    }
+}
+
+impl<R> Iterator for MsgPackReader<R>
+where
+    R: Read,
+{
+    type Item = Result<MsgPackRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+/// Create a [`MsgPack`] layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{msgpack, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = msgpack(&config);
+/// let layer = layer.with_writer(std::io::sink).with_filter(level_filter);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn msgpack<C>(config: &C) -> (MsgPack<Output>, LevelFilter)
+where
+    C: TracingConfig,
+{
+    let layer = MsgPack::new(config).with_writer(Output::new(config));
+    let level = get_effective_level(config.quiet(), config.verbose());
+    let level_filter = LevelFilter::from(level);
+    (layer, level_filter)
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+/// Create a [`MsgPack`] filtered layer configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{msgpack_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let layer = msgpack_filtered(&config);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn filtered<C, S>(config: &C) -> Filtered<MsgPack<Output>, LevelFilter, S>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = msgpack(config);
+    layer.with_filter(level_filter)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use tracing::{Level, debug, error, info, span, trace, warn};
+    use tracing_subscriber::Layer;
+
+    use super::{MsgPackReader, filtered as msgpack_filtered, msgpack};
+
+    use crate::{
+        TestAll, set_default,
+        utils::test::{TestConfig, TestWriter},
+    };
+
+    #[test]
+    fn msgpack_filtered_works() {
+        let config = TestConfig;
+        let layer = msgpack_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "msgpack_filtered_works");
+        let _enter = span.enter();
+        error!("error level");
+        warn!("warn level");
+        info!("info level");
+        debug!("debug level");
+        trace!("trace level");
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let writer = TestWriter::default();
+        let config = TestAll;
+        let (layer, level_filter) = msgpack(&config);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "outer", request_id = 7_u64);
+        span.in_scope(|| {
+            info!(count = -3, ratio = 0.5, ok = true, "first");
+            warn!("second");
+        });
+
+        let output = writer.bytes();
+        let records = MsgPackReader::new(output.as_slice())
+            .collect::<anyhow::Result<Vec<_>>>()
+            .expect("records");
+        assert_eq!(records.len(), 2);
+        let first = &records[0];
+        assert_eq!(first.level.as_deref(), Some("INFO"));
+        assert_eq!(
+            first.target.as_deref(),
+            Some("tracing_subscriber_init::format::msgpack::test")
+        );
+        assert_eq!(first.filename.as_deref(), Some("src/format/msgpack.rs"));
+        assert!(first.line_number.is_some());
+        assert!(first.thread_id.is_some());
+        assert!(!first.timestamp.is_empty());
+        assert_eq!(first.spans.len(), 1);
+        assert_eq!(first.spans[0].name, "outer");
+        assert_eq!(first.spans[0].fields["request_id"], json!(7));
+        assert_eq!(first.fields["message"], json!("first"));
+        assert_eq!(first.fields["count"], json!(-3));
+        assert_eq!(first.fields["ratio"], json!(0.5));
+        assert_eq!(first.fields["ok"], json!(true));
+        assert_eq!(
+            first.span_id,
+            Some(format!("{:016x}", span.id().unwrap().into_u64()))
+        );
+        assert_eq!(first.trace_id.as_ref().map(String::len), Some(32));
+        assert_eq!(records[1].trace_id, first.trace_id);
+        assert_eq!(records[1].fields["message"], json!("second"));
+    }
+
+    #[test]
+    fn layers_share_span_fields() {
+        let first = TestWriter::default();
+        let second = TestWriter::default();
+        let config = TestAll;
+        let (layer, _) = msgpack(&config);
+        let (other, _) = msgpack(&config);
+        let _unused = set_default(vec![
+            layer.with_writer(first.clone()).boxed(),
+            other.with_writer(second.clone()).boxed(),
+        ]);
+        let span = span!(
+            Level::INFO,
+            "outer",
+            request_id = 7_u64,
+            user = tracing::field::Empty
+        );
+        span.record("user", "alice");
+        span.in_scope(|| info!("shared"));
+
+        for writer in [first, second] {
+            let records = MsgPackReader::new(writer.bytes().as_slice())
+                .collect::<anyhow::Result<Vec<_>>>()
+                .expect("records");
+            assert_eq!(records.len(), 1);
+            assert_eq!(records[0].spans[0].fields["request_id"], json!(7));
+            assert_eq!(records[0].spans[0].fields["user"], json!("alice"));
+        }
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let mut reader = MsgPackReader::new(&[0_u8, 0, 0, 9, 1][..]);
+        assert!(reader.read_record().is_err());
+        let mut reader = MsgPackReader::new(&[0_u8, 0][..]);
+        assert!(reader.read_record().is_err());
+        let mut reader = MsgPackReader::new(&[][..]);
+        assert!(reader.read_record().expect("empty input").is_none());
+    }
+
+    #[test]
+    fn oversized_records_are_an_error() {
+        let mut reader = MsgPackReader::new(&[0xff_u8, 0xff, 0xff, 0xff][..]);
+        assert!(reader.read_record().is_err());
+        let mut reader = MsgPackReader::new(&[0_u8, 0, 0, 9][..]).with_max_record_len(8);
+        assert!(reader.read_record().is_err());
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use tracing::{Subscriber, metadata::LevelFilter};
+use tracing_subscriber::{
+    Layer,
+    filter::Filtered,
+    fmt::{self, format::DefaultFields},
+    registry::LookupSpan,
+};
+
+use crate::{FlightRecorderWriter, TracingConfig, format::text::TextFormat};
+
+/// Create a flight recorder layer configured from the given [`TracingConfig`], keeping the most recent events
+/// in the [`Full`](tracing_subscriber::fmt::format::Full) style at the level of the
+/// [`flight_recorder`](crate::TracingConfig::flight_recorder), see [`FlightRecorder`](crate::FlightRecorder).
+///
+/// The level filter is the recorder level rather than the verbosity, so filter each layer on its own to record
+/// events the other layers do not write.
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::{debug, info};
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{dump_flight_recorders, flight_recorder, full, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = full(&config);
+/// let (recorder, recorder_filter) = flight_recorder(&config);
+/// let _unused = set_default(vec![
+///     layer.with_filter(level_filter).boxed(),
+///     recorder.with_filter(recorder_filter).boxed(),
+/// ]);
+/// info!("info level");
+/// debug!("recorded");
+/// dump_flight_recorders()?;
+/// #   Ok(())
+/// # }
+/// ```
+pub fn flight_recorder<C, S>(
+    config: &C,
+) -> (
+    fmt::Layer<S, DefaultFields, TextFormat, FlightRecorderWriter>,
+    LevelFilter,
+)
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let recorder = config.flight_recorder();
+    let level_filter = LevelFilter::from(recorder.level);
+    let layer = fmt::layer().with_ansi(false);
+    let layer = if let Some(fmt_span) = config.with_span_events() {
+        layer.with_span_events(fmt_span)
+    } else {
+        layer
+    };
+    let layer = layer
+        .event_format(TextFormat::full(config))
+        .with_writer(FlightRecorderWriter::new(recorder));
+    (layer, level_filter)
+}
+
+/// Create a filtered flight recorder layer configured from the given [`TracingConfig`], see
+/// [`flight_recorder`](crate::flight_recorder).
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::debug;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{flight_recorder_filtered, full_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let _unused = set_default(vec![
+///     full_filtered(&config).boxed(),
+///     flight_recorder_filtered(&config).boxed(),
+/// ]);
+/// debug!("recorded");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn filtered<C, S>(
+    config: &C,
+) -> Filtered<fmt::Layer<S, DefaultFields, TextFormat, FlightRecorderWriter>, LevelFilter, S>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = flight_recorder(config);
+    layer.with_filter(level_filter)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use tracing::{debug, info, trace};
+    use tracing_subscriber::Layer;
+
+    use super::flight_recorder;
+    use crate::{
+        FlightRecorder, TimerConfig, TracingConfig, compact_text, set_default,
+        utils::test::TestWriter,
+    };
+
+    #[derive(Clone, Debug)]
+    struct Recorded(FlightRecorder);
+
+    impl TracingConfig for Recorded {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            0
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_target(&self) -> bool {
+            false
+        }
+
+        fn flight_recorder(&self) -> FlightRecorder {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn records_below_the_verbosity() {
+        let path = env::temp_dir().join(format!("flight-layer-{}.log", process::id()));
+        let config = Recorded(FlightRecorder {
+            level: tracing::Level::DEBUG,
+            dump_path: Some(path.clone()),
+            dump_on_panic: false,
+            ..FlightRecorder::default()
+        });
+        let output = TestWriter::default();
+        let (layer, level_filter) = compact_text(&config);
+        let layer = layer.with_writer(output.clone()).with_filter(level_filter);
+        let (recorder, recorder_filter) = flight_recorder(&config);
+        let writer = recorder.writer().clone();
+        {
+            let _unused = set_default(vec![
+                layer.boxed(),
+                recorder.with_filter(recorder_filter).boxed(),
+            ]);
+            info!("written");
+            debug!("recorded");
+            trace!("dropped");
+        }
+        writer.dump().unwrap();
+        let dump = fs::read_to_string(&path).unwrap();
+        let _removed = fs::remove_file(&path);
+        assert!(dump.contains("INFO written"));
+        assert!(dump.contains("DEBUG recorded"));
+        assert!(!dump.contains("dropped"));
+        assert_eq!(output.output(), " INFO written\n");
+    }
+}
@@ -0,0 +1,523 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use anyhow::Result;
+use tracing::{Subscriber, metadata::LevelFilter};
+use tracing_subscriber::{Layer, filter::filter_fn, fmt::format::FmtSpan, registry::LookupSpan};
+
+use crate::{FileOutput, TracingConfig, utils::get_effective_level};
+
+/// The format of the events written by a [`Route`], one per format constructor writing to an
+/// [`Output`](crate::Output).
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum RouteFormat {
+    /// The [`full_text`](crate::full_text) format
+    #[default]
+    Full,
+    /// The [`compact_text`](crate::compact_text) format
+    Compact,
+    /// The [`pretty_text`](crate::pretty_text) format
+    Pretty,
+    /// The [`bunyan`](crate::bunyan) format
+    #[cfg(feature = "bunyan")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "bunyan")))]
+    Bunyan,
+    /// The [`cef`](crate::cef) format
+    #[cfg(feature = "cef")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cef")))]
+    Cef,
+    /// The [`delimited`](crate::delimited) format
+    #[cfg(feature = "delimited")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "delimited")))]
+    Delimited,
+    /// The [`json_format`](crate::json_format) format
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    Json,
+    /// The [`msgpack`](crate::msgpack) format
+    #[cfg(feature = "msgpack")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+    MsgPack,
+    /// The [`template`](crate::template) format
+    #[cfg(feature = "template")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "template")))]
+    Template,
+}
+
+/// A route of the [`routing`](crate::routing) layer, see [`routes`](crate::TracingConfig::routes).
+///
+/// The events whose target is the route target or one of its modules, i.e. `http::access` for `http::access`
+/// and `http::access::tls` but not `http::accesslog`, are written to the route file in the route format.  The route layer is built by the
+/// format constructor from the [`TracingConfig`], with the route file as its only output.  An event matching
+/// several routes is written by each of them.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Route {
+    /// The target of the routed events, also matching its modules
+    pub target: String,
+    /// The format of the routed events
+    pub format: RouteFormat,
+    /// The log file of the routed events
+    pub file_output: FileOutput,
+    /// Whether or not the routed events are also written by the default layer
+    pub also_default: bool,
+}
+
+impl Route {
+    /// Create a [`Route`] writing the events of the given target to the given log file in the
+    /// [`Full`](RouteFormat::Full) format, and not to the default layer.
+    #[must_use]
+    pub fn new(target: &str, file_output: FileOutput) -> Self {
+        Self {
+            target: target.to_string(),
+            format: RouteFormat::default(),
+            file_output,
+            also_default: false,
+        }
+    }
+}
+
+/// Create a layer writing the events of each of the [`routes`](crate::TracingConfig::routes) of the given
+/// [`TracingConfig`] to its own log file, and the other events to the given default layer.
+///
+/// The default layer is passed without its level filter, as every layer is filtered by the verbosity of the
+/// configuration here.
+///
+/// # Errors
+/// * An error is returned if a route has the `template` format and the [`template`](crate::TracingConfig::template)
+///   is invalid.
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber_init::{FileOutput, Route, RouteFormat, TracingConfig, full, routing, set_default};
+/// #
+/// struct Config;
+///
+/// impl TracingConfig for Config {
+///     fn quiet(&self) -> u8 {
+///         0
+///     }
+///
+///     fn verbose(&self) -> u8 {
+///         0
+///     }
+///
+///     fn routes(&self) -> Vec<Route> {
+///         let audit = FileOutput {
+///             prefix: "audit".to_string(),
+///             ..FileOutput::default()
+///         };
+///         vec![Route {
+///             format: RouteFormat::Compact,
+///             ..Route::new("audit", audit)
+///         }]
+///     }
+/// }
+///
+/// # pub fn main() -> Result<()> {
+/// let (layer, _level_filter) = full(&Config);
+/// let _unused = set_default(vec![routing(&Config, layer)?]);
+/// info!(target: "audit", "to ./audit.log");
+/// info!("to standard output");
+/// #   std::fs::remove_file("audit.log")?;
+/// #   Ok(())
+/// # }
+/// ```
+pub fn routing<C, S, L>(config: &C, default: L) -> Result<Box<dyn Layer<S> + Send + Sync + 'static>>
+where
+    C: TracingConfig,
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    L: Layer<S> + Send + Sync + 'static,
+{
+    let level_filter = LevelFilter::from(get_effective_level(config.quiet(), config.verbose()));
+    let mut excluded = Vec::new();
+    let mut layers = Vec::new();
+    for route in config.routes() {
+        if !route.also_default {
+            excluded.push(route.target.clone());
+        }
+        let route_config = RouteConfig {
+            config,
+            route: &route,
+        };
+        let (layer, route_level_filter) = route_layer(&route_config, route.format)?;
+        let target = route.target.clone();
+        let filter = filter_fn(move |meta| {
+            *meta.level() <= route_level_filter && routed(meta.target(), &target)
+        })
+        .with_max_level_hint(route_level_filter);
+        layers.push(layer.with_filter(filter).boxed());
+    }
+    let filter = filter_fn(move |meta| {
+        *meta.level() <= level_filter
+            && !excluded.iter().any(|target| routed(meta.target(), target))
+    })
+    .with_max_level_hint(level_filter);
+    layers.push(default.with_filter(filter).boxed());
+    Ok(layers.boxed())
+}
+
+/// Whether or not an event target is the given route target or one of its modules, i.e. `audit` routes
+/// `audit` and `audit::login` but not `auditor`.
+fn routed(target: &str, route: &str) -> bool {
+    target
+        .strip_prefix(route)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// Build the layer of a route with the format constructor of its format.
+fn route_layer<C, S>(
+    config: &C,
+    format: RouteFormat,
+) -> Result<(Box<dyn Layer<S> + Send + Sync + 'static>, LevelFilter)>
+where
+    C: TracingConfig,
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    match format {
+        RouteFormat::Full => Ok(boxed(crate::full_text(config))),
+        RouteFormat::Compact => Ok(boxed(crate::compact_text(config))),
+        RouteFormat::Pretty => Ok(boxed(crate::pretty_text(config))),
+        #[cfg(feature = "bunyan")]
+        RouteFormat::Bunyan => Ok(boxed(crate::bunyan(config))),
+        #[cfg(feature = "cef")]
+        RouteFormat::Cef => Ok(boxed(crate::cef(config))),
+        #[cfg(feature = "delimited")]
+        RouteFormat::Delimited => Ok(boxed(crate::delimited(config))),
+        #[cfg(feature = "json")]
+        RouteFormat::Json => Ok(boxed(crate::json_format(config))),
+        #[cfg(feature = "msgpack")]
+        RouteFormat::MsgPack => Ok(boxed(crate::msgpack(config))),
+        #[cfg(feature = "template")]
+        RouteFormat::Template => crate::template(config).map(boxed),
+    }
+}
+
+fn boxed<S, L>(
+    (layer, level_filter): (L, LevelFilter),
+) -> (Box<dyn Layer<S> + Send + Sync + 'static>, LevelFilter)
+where
+    S: Subscriber,
+    L: Layer<S> + Send + Sync + 'static,
+{
+    (layer.boxed(), level_filter)
+}
+
+/// The configuration of a route layer, the configuration of the [`routing`] layer writing only to the route file.
+#[derive(Debug)]
+struct RouteConfig<'a, C> {
+    config: &'a C,
+    route: &'a Route,
+}
+
+impl<C> TracingConfig for RouteConfig<'_, C>
+where
+    C: TracingConfig,
+{
+    fn quiet(&self) -> u8 {
+        self.config.quiet()
+    }
+
+    fn verbose(&self) -> u8 {
+        self.config.verbose()
+    }
+
+    #[cfg(any(
+        feature = "bunyan",
+        feature = "cef",
+        feature = "http",
+        all(feature = "syslog", unix),
+        all(feature = "journald", target_os = "linux")
+    ))]
+    fn app_name(&self) -> String {
+        self.config.app_name()
+    }
+
+    #[cfg(all(feature = "journald", target_os = "linux"))]
+    fn journald_path(&self) -> std::path::PathBuf {
+        self.config.journald_path()
+    }
+
+    #[cfg(all(feature = "syslog", unix))]
+    fn proc_id(&self) -> String {
+        self.config.proc_id()
+    }
+
+    #[cfg(feature = "cef")]
+    fn cef_vendor(&self) -> String {
+        self.config.cef_vendor()
+    }
+
+    #[cfg(feature = "cef")]
+    fn cef_product(&self) -> String {
+        self.config.cef_product()
+    }
+
+    #[cfg(feature = "cef")]
+    fn cef_version(&self) -> String {
+        self.config.cef_version()
+    }
+
+    #[cfg(feature = "delimited")]
+    fn columns(&self) -> Vec<crate::Column> {
+        self.config.columns()
+    }
+
+    #[cfg(feature = "delimited")]
+    fn delimiter(&self) -> crate::Delimiter {
+        self.config.delimiter()
+    }
+
+    #[cfg(feature = "json")]
+    fn flatten_event(&self) -> bool {
+        self.config.flatten_event()
+    }
+
+    #[cfg(feature = "json")]
+    fn json_keys(&self) -> crate::JsonKeys {
+        self.config.json_keys()
+    }
+
+    #[cfg(feature = "json")]
+    fn merge_span_fields(&self) -> bool {
+        self.config.merge_span_fields()
+    }
+
+    #[cfg(all(feature = "syslog", unix))]
+    fn syslog_facility(&self) -> crate::Facility {
+        self.config.syslog_facility()
+    }
+
+    #[cfg(all(feature = "syslog", unix))]
+    fn syslog_format(&self) -> crate::SyslogFormat {
+        self.config.syslog_format()
+    }
+
+    #[cfg(all(feature = "syslog", unix))]
+    fn syslog_path(&self) -> std::path::PathBuf {
+        self.config.syslog_path()
+    }
+
+    #[cfg(feature = "template")]
+    fn template(&self) -> String {
+        self.config.template()
+    }
+
+    fn limits(&self) -> crate::Limits {
+        self.config.limits()
+    }
+
+    fn newline_policy(&self) -> crate::NewlinePolicy {
+        self.config.newline_policy()
+    }
+
+    fn target_abbreviation(&self) -> crate::TargetAbbreviation {
+        self.config.target_abbreviation()
+    }
+
+    fn file_output(&self) -> Option<FileOutput> {
+        Some(self.route.file_output.clone())
+    }
+
+    fn network_output(&self) -> Option<crate::NetworkOutput> {
+        None
+    }
+
+    #[cfg(feature = "http")]
+    fn http_output(&self) -> Option<crate::HttpOutput> {
+        None
+    }
+
+    fn tee_stdout(&self) -> bool {
+        false
+    }
+
+    fn stderr_level(&self) -> Option<tracing::Level> {
+        None
+    }
+
+    fn non_blocking(&self) -> Option<crate::NonBlocking> {
+        self.config.non_blocking()
+    }
+
+    fn flight_recorder(&self) -> crate::FlightRecorder {
+        self.config.flight_recorder()
+    }
+
+    fn static_fields(&self) -> Vec<(String, String)> {
+        self.config.static_fields()
+    }
+
+    fn color(&self) -> crate::ColorMode {
+        self.config.color()
+    }
+
+    fn theme(&self) -> crate::Theme {
+        self.config.theme()
+    }
+
+    fn timer(&self) -> crate::TimerConfig {
+        self.config.timer()
+    }
+
+    fn with_ansi(&self) -> bool {
+        self.config.with_ansi()
+    }
+
+    #[cfg(feature = "json")]
+    fn with_current_span(&self) -> bool {
+        self.config.with_current_span()
+    }
+
+    fn with_file(&self) -> bool {
+        self.config.with_file()
+    }
+
+    fn with_line_number(&self) -> bool {
+        self.config.with_line_number()
+    }
+
+    fn with_level(&self) -> bool {
+        self.config.with_level()
+    }
+
+    fn with_span_events(&self) -> Option<FmtSpan> {
+        self.config.with_span_events()
+    }
+
+    #[cfg(feature = "json")]
+    fn with_span_list(&self) -> bool {
+        self.config.with_span_list()
+    }
+
+    fn with_span_ids(&self) -> bool {
+        self.config.with_span_ids()
+    }
+
+    fn with_target(&self) -> bool {
+        self.config.with_target()
+    }
+
+    fn with_thread_ids(&self) -> bool {
+        self.config.with_thread_ids()
+    }
+
+    fn with_thread_names(&self) -> bool {
+        self.config.with_thread_names()
+    }
+
+    fn with_trace_id(&self) -> bool {
+        self.config.with_trace_id()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::PathBuf, process};
+
+    use tracing::{info, warn};
+
+    use super::{Route, RouteFormat, routed, routing};
+    use crate::{
+        FileOutput, TimerConfig, TracingConfig, compact_text, set_default, utils::test::TestWriter,
+    };
+
+    #[derive(Clone, Debug)]
+    struct Routed {
+        directory: PathBuf,
+    }
+
+    impl Routed {
+        fn file(&self, prefix: &str) -> FileOutput {
+            FileOutput {
+                directory: self.directory.clone(),
+                prefix: prefix.to_string(),
+                ..FileOutput::default()
+            }
+        }
+
+        fn contents(&self, prefix: &str) -> String {
+            fs::read_to_string(self.directory.join(format!("{prefix}.log"))).unwrap_or_default()
+        }
+    }
+
+    impl TracingConfig for Routed {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            0
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_ansi(&self) -> bool {
+            false
+        }
+
+        fn with_target(&self) -> bool {
+            true
+        }
+
+        fn routes(&self) -> Vec<Route> {
+            vec![
+                Route {
+                    format: RouteFormat::Compact,
+                    ..Route::new("audit", self.file("audit"))
+                },
+                Route {
+                    also_default: true,
+                    ..Route::new("http::access", self.file("access"))
+                },
+            ]
+        }
+    }
+
+    #[test]
+    fn routes_match_whole_module_paths() {
+        assert!(routed("audit", "audit"));
+        assert!(routed("audit::login", "audit"));
+        assert!(!routed("auditor", "audit"));
+        assert!(!routed("auditor::login", "audit"));
+        assert!(!routed("app", "audit"));
+    }
+
+    #[test]
+    fn routes_by_target_prefix() {
+        let config = Routed {
+            directory: env::temp_dir().join(format!("tsi-route-{}", process::id())),
+        };
+        let output = TestWriter::default();
+        let (layer, _) = compact_text(&config);
+        let layer = layer.with_writer(output.clone());
+        {
+            let _unused = set_default(vec![routing(&config, layer).unwrap()]);
+            info!(target: "audit", "audited");
+            info!(target: "audit::login", "logged in");
+            info!(target: "auditor", "not audited");
+            warn!(target: "http::access", "accessed");
+            info!(target: "app", "main");
+        }
+        let audit = config.contents("audit");
+        let access = config.contents("access");
+        let _res = fs::remove_dir_all(&config.directory);
+        assert_eq!(
+            audit,
+            " INFO audit: audited\n INFO audit::login: logged in\n"
+        );
+        assert_eq!(access, " WARN http::access: accessed\n");
+        assert_eq!(
+            output.output(),
+            " INFO auditor: not audited\n WARN http::access: accessed\n INFO app: main\n"
+        );
+    }
+}
@@ -0,0 +1,508 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    fmt::Result as FmtResult,
+    io::{self, Write},
+    path::Path,
+};
+
+use nu_ansi_term::Style;
+use time::OffsetDateTime;
+use tracing::{Event, Level, Subscriber, metadata::LevelFilter};
+use tracing_subscriber::{
+    Layer,
+    filter::Filtered,
+    fmt::{
+        self, FmtContext, FormatEvent, FormatFields, MakeWriter,
+        format::{DefaultFields, Writer},
+    },
+    registry::LookupSpan,
+};
+
+use crate::{
+    TracingConfig,
+    format::{
+        datagram::Datagram,
+        ids::SpanIds,
+        text::{EventState, NewlinePolicy, TargetAbbreviation, TextVisitor},
+    },
+    limits::Limits,
+    utils::{get_effective_level, syslog_severity, utc_timestamp},
+};
+
+/// The default path of the local syslog socket.
+pub(crate) const DEFAULT_SYSLOG_PATH: &str = "/dev/log";
+
+/// A syslog facility, as defined in [RFC 5424](https://www.rfc-editor.org/rfc/rfc5424#section-6.2.1).
+#[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Facility {
+    /// Kernel messages
+    Kern,
+    /// User-level messages
+    #[default]
+    User,
+    /// Mail system
+    Mail,
+    /// System daemons
+    Daemon,
+    /// Security/authentication messages
+    Auth,
+    /// Messages generated internally by syslogd
+    Syslog,
+    /// Line printer subsystem
+    Lpr,
+    /// Network news subsystem
+    News,
+    /// UUCP subsystem
+    Uucp,
+    /// Clock daemon
+    Cron,
+    /// Private security/authentication messages
+    AuthPriv,
+    /// FTP daemon
+    Ftp,
+    /// Local use 0
+    Local0,
+    /// Local use 1
+    Local1,
+    /// Local use 2
+    Local2,
+    /// Local use 3
+    Local3,
+    /// Local use 4
+    Local4,
+    /// Local use 5
+    Local5,
+    /// Local use 6
+    Local6,
+    /// Local use 7
+    Local7,
+}
+
+impl Facility {
+    /// The numerical code of this facility.
+    #[must_use]
+    pub fn code(self) -> u8 {
+        match self {
+            Facility::Kern => 0,
+            Facility::User => 1,
+            Facility::Mail => 2,
+            Facility::Daemon => 3,
+            Facility::Auth => 4,
+            Facility::Syslog => 5,
+            Facility::Lpr => 6,
+            Facility::News => 7,
+            Facility::Uucp => 8,
+            Facility::Cron => 9,
+            Facility::AuthPriv => 10,
+            Facility::Ftp => 11,
+            Facility::Local0 => 16,
+            Facility::Local1 => 17,
+            Facility::Local2 => 18,
+            Facility::Local3 => 19,
+            Facility::Local4 => 20,
+            Facility::Local5 => 21,
+            Facility::Local6 => 22,
+            Facility::Local7 => 23,
+        }
+    }
+}
+
+/// The syslog message format.
+#[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum SyslogFormat {
+    /// The structured format described in [RFC 5424](https://www.rfc-editor.org/rfc/rfc5424)
+    #[default]
+    Rfc5424,
+    /// The legacy BSD format described in [RFC 3164](https://www.rfc-editor.org/rfc/rfc3164)
+    Rfc3164,
+}
+
+/// A [`FormatEvent`] implementation that writes one syslog message per event.
+///
+/// The message text is the event target (if enabled, abbreviated as configured) followed by the event and static fields, and the current span
+/// and trace IDs if enabled.  No trailing newline is
+/// written, each message is expected to be sent as a single datagram by the [`SyslogWriter`].  The configured
+/// [`limits`](crate::TracingConfig::limits) apply to the event fields and the whole message, and line breaks are
+/// written according to the configured [`newline_policy`](crate::TracingConfig::newline_policy).
+#[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+#[derive(Clone, Debug)]
+pub struct Syslog {
+    format: SyslogFormat,
+    facility: Facility,
+    hostname: String,
+    app_name: String,
+    proc_id: String,
+    display_target: bool,
+    static_fields: Vec<(String, String)>,
+    limits: Limits,
+    newlines: NewlinePolicy,
+    target_abbreviation: TargetAbbreviation,
+    display_span_id: bool,
+    display_trace_id: bool,
+}
+
+impl Syslog {
+    /// Create a [`Syslog`] formatter configured from the given [`TracingConfig`].
+    pub fn new<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self {
+            format: config.syslog_format(),
+            facility: config.syslog_facility(),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            app_name: config.app_name(),
+            proc_id: config.proc_id(),
+            display_target: config.with_target(),
+            static_fields: config.static_fields(),
+            limits: config.limits(),
+            newlines: config.newline_policy(),
+            target_abbreviation: config.target_abbreviation(),
+            display_span_id: config.with_span_ids(),
+            display_trace_id: config.with_trace_id(),
+        }
+    }
+
+    fn priority(&self, level: Level) -> u8 {
+        self.facility.code() * 8 + syslog_severity(level)
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for Syslog
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        let meta = event.metadata();
+        let priority = self.priority(*meta.level());
+        let mut message = String::new();
+        let mut record = Writer::new(&mut message);
+        match self.format {
+            SyslogFormat::Rfc5424 => write!(
+                record,
+                "<{priority}>1 {} {} {} {} - - ",
+                utc_timestamp(),
+                header_value(&self.hostname),
+                header_value(&self.app_name),
+                header_value(&self.proc_id),
+            )?,
+            SyslogFormat::Rfc3164 => write!(
+                record,
+                "<{priority}>{} {}[{}]: ",
+                bsd_timestamp(),
+                self.app_name,
+                self.proc_id
+            )?,
+        }
+        if self.display_target {
+            write!(
+                record,
+                "{}: ",
+                self.target_abbreviation.apply(meta.target())
+            )?;
+        }
+        let mut state = EventState::new(false, false, self.newlines, self.limits.event());
+        let mut visitor = TextVisitor::new(record.by_ref(), &mut state, false, Style::new());
+        event.record(&mut visitor);
+        for (key, value) in &self.static_fields {
+            visitor.record_static(key, value);
+        }
+        let ids = SpanIds::new(
+            ctx.parent_span().as_ref(),
+            self.display_span_id,
+            self.display_trace_id,
+        );
+        for (name, value) in ids.pairs() {
+            visitor.write_value(name, value);
+        }
+        visitor.finish()?;
+        let truncated = self.limits.truncate_event(&mut message, "", "");
+        state.limits.mark(truncated);
+        state.limits.finish();
+        writer.write_str(&message)
+    }
+}
+
+/// RFC 5424 header values are printable US-ASCII without spaces, `-` stands in for an empty value.
+fn header_value(value: &str) -> String {
+    let value: String = value.chars().filter(char::is_ascii_graphic).collect();
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+/// The current UTC time in the RFC 3164 `Mmm dd hh:mm:ss` form.
+fn bsd_timestamp() -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let now = OffsetDateTime::now_utc();
+    format!(
+        "{} {:>2} {:02}:{:02}:{:02}",
+        MONTHS[usize::from(u8::from(now.month()) - 1)],
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+/// A [`MakeWriter`] that sends each formatted event as a datagram to a Unix syslog socket.
+///
+/// The socket is connected lazily.  If a send fails the connection is re-established and the send retried
+/// once, so a restarted syslog daemon is picked up without restarting the process.
+#[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+#[derive(Debug)]
+pub struct SyslogWriter {
+    socket: Datagram,
+}
+
+impl SyslogWriter {
+    /// Create a [`SyslogWriter`] that sends to the socket at the given path.
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            socket: Datagram::new(path.as_ref().to_path_buf()),
+        }
+    }
+}
+
+impl Write for &SyslogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket
+            .send_with(|socket| socket.send(buf).map(|_| ()))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for SyslogWriter {
+    type Writer = &'a SyslogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+#[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+/// Create a [`Syslog`] format layer, writing to the configured syslog socket, configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{syslog, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = syslog(&config);
+/// let layer = layer.with_filter(level_filter);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn syslog<C, S>(
+    config: &C,
+) -> (
+    fmt::Layer<S, DefaultFields, Syslog, SyslogWriter>,
+    LevelFilter,
+)
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let layer = fmt::layer().with_ansi(false);
+
+    // span events can only be configured while the layer still has a `Format` event formatter
+    let layer = if let Some(fmt_span) = config.with_span_events() {
+        layer.with_span_events(fmt_span)
+    } else {
+        layer
+    };
+    let layer = layer
+        .event_format(Syslog::new(config))
+        .with_writer(SyslogWriter::new(config.syslog_path()));
+    let level = get_effective_level(config.quiet(), config.verbose());
+    let level_filter = LevelFilter::from(level);
+    (layer, level_filter)
+}
+
+#[cfg_attr(docsrs, doc(cfg(all(feature = "syslog", unix))))]
+/// Create a [`Syslog`] format filtered layer, writing to the configured syslog socket, configured from the given [`TracingConfig`].
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{syslog_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let layer = syslog_filtered(&config);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn filtered<C, S>(
+    config: &C,
+) -> Filtered<fmt::Layer<S, DefaultFields, Syslog, SyslogWriter>, LevelFilter, S>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = syslog(config);
+    layer.with_filter(level_filter)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        os::unix::net::UnixDatagram,
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use tracing::{Level, debug, error, info, warn};
+    use tracing_subscriber::Layer;
+
+    use super::{Facility, SyslogFormat, filtered as syslog_filtered};
+
+    use crate::{TracingConfig, set_default, utils::syslog_severity};
+
+    #[derive(Clone, Debug)]
+    struct TestSyslog {
+        path: PathBuf,
+        format: SyslogFormat,
+    }
+
+    impl TracingConfig for TestSyslog {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn app_name(&self) -> String {
+            "syslog-test".to_string()
+        }
+
+        fn proc_id(&self) -> String {
+            "1234".to_string()
+        }
+
+        fn syslog_facility(&self) -> Facility {
+            Facility::Local3
+        }
+
+        fn syslog_format(&self) -> SyslogFormat {
+            self.format
+        }
+
+        fn syslog_path(&self) -> PathBuf {
+            self.path.clone()
+        }
+    }
+
+    fn listen(path: &Path) -> UnixDatagram {
+        let _res = std::fs::remove_file(path);
+        let socket = UnixDatagram::bind(path).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+    }
+
+    fn recv(socket: &UnixDatagram) -> String {
+        let mut buf = [0; 4096];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    }
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tsi-{name}-{}.sock", std::process::id()))
+    }
+
+    #[test]
+    fn severity_mapping() {
+        assert_eq!(syslog_severity(Level::ERROR), 3);
+        assert_eq!(syslog_severity(Level::WARN), 4);
+        assert_eq!(syslog_severity(Level::INFO), 6);
+        assert_eq!(syslog_severity(Level::DEBUG), 7);
+        assert_eq!(syslog_severity(Level::TRACE), 7);
+    }
+
+    #[test]
+    fn rfc5424_works_and_reconnects() {
+        let path = socket_path("rfc5424");
+        let listener = listen(&path);
+        let config = TestSyslog {
+            path: path.clone(),
+            format: SyslogFormat::Rfc5424,
+        };
+        let layer = syslog_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+
+        error!(answer = 42, "error level");
+        let message = recv(&listener);
+        assert!(message.starts_with("<155>1 "), "{message}");
+        assert!(message.contains(" syslog-test 1234 - - error level answer=42"));
+        assert!(!message.ends_with('\n'));
+
+        // restart the "daemon", the writer should reconnect
+        drop(listener);
+        let listener = listen(&path);
+        info!("info level");
+        assert!(recv(&listener).starts_with("<158>1 "));
+        debug!("debug level");
+        assert!(recv(&listener).starts_with("<159>1 "));
+        let _res = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn rfc3164_works() {
+        let path = socket_path("rfc3164");
+        let listener = listen(&path);
+        let config = TestSyslog {
+            path: path.clone(),
+            format: SyslogFormat::Rfc3164,
+        };
+        let layer = syslog_filtered(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+
+        warn!("warn level");
+        let message = recv(&listener);
+        assert!(message.starts_with("<156>"), "{message}");
+        assert!(message.ends_with(" syslog-test[1234]: warn level"));
+        let _res = std::fs::remove_file(&path);
+    }
+}
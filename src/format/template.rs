@@ -0,0 +1,653 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    fmt::{Result as FmtResult, Write as _},
+    str::FromStr,
+};
+
+use anyhow::{Error, Result, bail};
+use nu_ansi_term::Style;
+use tracing::{Event, Subscriber, metadata::LevelFilter};
+use tracing_subscriber::{
+    Layer,
+    filter::Filtered,
+    fmt::{
+        self, FmtContext, FormatEvent, FormatFields, FormattedFields,
+        format::{DefaultFields, Writer},
+        time::FormatTime,
+    },
+    registry::LookupSpan,
+};
+
+use crate::{
+    Output, Theme, Timer, TracingConfig,
+    format::{
+        ids::SpanIds,
+        text::{EventState, NewlinePolicy, TargetAbbreviation, TextVisitor, paint},
+    },
+    limits::Limits,
+    utils::get_effective_level,
+};
+
+/// The format layer created by [`template`].
+type TemplateLayer<S> = fmt::Layer<S, DefaultFields, TemplateFormat, Output>;
+
+/// The template used when the [`TracingConfig`] does not supply one.
+pub(crate) const DEFAULT_TEMPLATE: &str = "{timestamp} {level:>5} {target}: {message} {fields}";
+
+/// A piece of event metadata that can be written by a [`Template`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Placeholder {
+    Timestamp,
+    Level,
+    Target,
+    Module,
+    File,
+    Line,
+    Thread,
+    ThreadId,
+    ThreadName,
+    Span,
+    Spans,
+    SpanId,
+    TraceId,
+    Message,
+    Fields,
+}
+
+impl Placeholder {
+    const ALL: [(&'static str, Placeholder); 15] = [
+        ("timestamp", Placeholder::Timestamp),
+        ("level", Placeholder::Level),
+        ("target", Placeholder::Target),
+        ("module", Placeholder::Module),
+        ("file", Placeholder::File),
+        ("line", Placeholder::Line),
+        ("thread", Placeholder::Thread),
+        ("thread_id", Placeholder::ThreadId),
+        ("thread_name", Placeholder::ThreadName),
+        ("span", Placeholder::Span),
+        ("spans", Placeholder::Spans),
+        ("span_id", Placeholder::SpanId),
+        ("trace_id", Placeholder::TraceId),
+        ("message", Placeholder::Message),
+        ("fields", Placeholder::Fields),
+    ];
+}
+
+/// The alignment of a padded placeholder.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Segment {
+    Literal(String),
+    Placeholder {
+        placeholder: Placeholder,
+        align: Align,
+        width: usize,
+    },
+}
+
+/// A parsed line layout for the [`TemplateFormat`] formatter.
+///
+/// A template is literal text with `{placeholder}` or `{placeholder:spec}` substitutions, `{{` and `}}` are
+/// literal braces.  The spec is an optional alignment (`<` left, the default, `>` right or `^` center)
+/// followed by a minimum width, i.e. `{level:>5}`.
+///
+/// | Placeholder     | Value                                                          |
+/// |-----------------|----------------------------------------------------------------|
+/// | `timestamp`     | the event timestamp, as configured by the [`timer`](crate::TracingConfig::timer) |
+/// | `level`         | the event level                                                |
+/// | `target`        | the event target, abbreviated as configured by the [`target_abbreviation`](crate::TracingConfig::target_abbreviation) |
+/// | `module`        | the module path of the event                                   |
+/// | `file`          | the source code file path of the event                         |
+/// | `line`          | the source code line number of the event                       |
+/// | `thread`        | the thread name, or the thread ID for unnamed threads          |
+/// | `thread_id`     | the thread ID                                                  |
+/// | `thread_name`   | the thread name                                                |
+/// | `span`          | the name of the current span                                   |
+/// | `spans`         | the spans in scope with their fields, from root to leaf        |
+/// | `span_id`       | the ID of the current span, as 16 hex digits                   |
+/// | `trace_id`      | the trace ID of the current span, as 32 hex digits             |
+/// | `message`       | the event message                                              |
+/// | `fields`        | the event fields other than the message, and the static fields |
+///
+/// The `fields` placeholder also holds the `span_id` and `trace_id` of the current span if
+/// [`with_span_ids`](crate::TracingConfig::with_span_ids) or [`with_trace_id`](crate::TracingConfig::with_trace_id)
+/// are enabled.
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing_subscriber_init::Template;
+/// #
+/// # pub fn main() -> Result<()> {
+/// let template: Template = "{timestamp} {level:>5} [{thread}] {target}: {message} {fields}".parse()?;
+/// assert!("{timestamp} {levle}".parse::<Template>().is_err());
+/// #   Ok(())
+/// # }
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "template")))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parse a template.
+    ///
+    /// # Errors
+    /// * An error is returned if the template contains an unknown placeholder, an invalid spec or an
+    ///   unmatched brace.
+    ///
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.char_indices().peekable();
+        while let Some((idx, ch)) = chars.next() {
+            match ch {
+                '{' if chars.next_if(|(_, next)| *next == '{').is_some() => literal.push('{'),
+                '}' if chars.next_if(|(_, next)| *next == '}').is_some() => literal.push('}'),
+                '{' => {
+                    let Some(len) = template[idx + 1..].find('}') else {
+                        bail!("unclosed placeholder at offset {idx} of template '{template}'");
+                    };
+                    let body = &template[idx + 1..=idx + len];
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_placeholder(body, idx, template)?);
+                    while chars.next_if(|(next, _)| *next <= idx + len + 1).is_some() {}
+                }
+                '}' => bail!("unmatched '}}' at offset {idx} of template '{template}'"),
+                ch => literal.push(ch),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    fn contains(&self, wanted: Placeholder) -> bool {
+        self.segments.iter().any(|segment| {
+            matches!(segment, Segment::Placeholder { placeholder, .. } if *placeholder == wanted)
+        })
+    }
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        Self::parse(template)
+    }
+}
+
+fn parse_placeholder(body: &str, offset: usize, template: &str) -> Result<Segment> {
+    let (name, spec) = body.split_once(':').unwrap_or((body, ""));
+    let Some((_, placeholder)) = Placeholder::ALL.iter().find(|(known, _)| *known == name) else {
+        let known: Vec<&str> = Placeholder::ALL.iter().map(|(known, _)| *known).collect();
+        bail!(
+            "unknown placeholder '{{{name}}}' at offset {offset} of template '{template}', expected one of: {}",
+            known.join(", ")
+        );
+    };
+    let (align, width) = match spec.chars().next() {
+        Some('<') => (Align::Left, &spec[1..]),
+        Some('>') => (Align::Right, &spec[1..]),
+        Some('^') => (Align::Center, &spec[1..]),
+        _ => (Align::Left, spec),
+    };
+    let width = if width.is_empty() {
+        0
+    } else if let Ok(width) = width.parse() {
+        width
+    } else {
+        bail!(
+            "invalid spec '{spec}' for placeholder '{{{name}}}' at offset {offset} of template '{template}', expected an optional alignment ('<', '>' or '^') followed by a width"
+        );
+    };
+    Ok(Segment::Placeholder {
+        placeholder: *placeholder,
+        align,
+        width,
+    })
+}
+
+/// A [`FormatEvent`] implementation that writes each event as laid out by a [`Template`].
+///
+/// Colors follow the configured [`theme`](crate::TracingConfig::theme), and the
+/// [`static_fields`](crate::TracingConfig::static_fields), [`limits`](crate::TracingConfig::limits) and
+/// [`newline_policy`](crate::TracingConfig::newline_policy) apply as in the [`full_text`](crate::full_text) format.
+/// Trailing spaces, left by empty placeholders at the end of the line, are removed.
+#[cfg_attr(docsrs, doc(cfg(feature = "template")))]
+#[derive(Clone, Debug)]
+pub struct TemplateFormat<T = Timer> {
+    template: Template,
+    timer: T,
+    theme: Theme,
+    static_fields: Vec<(String, String)>,
+    limits: Limits,
+    newlines: NewlinePolicy,
+    target_abbreviation: TargetAbbreviation,
+    display_span_id: bool,
+    display_trace_id: bool,
+}
+
+impl TemplateFormat {
+    /// Create a [`TemplateFormat`] formatter, parsing the [`template`](crate::TracingConfig::template) of the
+    /// given [`TracingConfig`].
+    ///
+    /// # Errors
+    /// * An error is returned if the template is invalid, see [`Template::parse`].
+    ///
+    pub fn new<C>(config: &C) -> Result<Self>
+    where
+        C: TracingConfig,
+    {
+        Ok(Self {
+            template: Template::parse(&config.template())?,
+            timer: Timer::new(&config.timer()),
+            theme: config.theme(),
+            static_fields: config.static_fields(),
+            limits: config.limits(),
+            newlines: config.newline_policy(),
+            target_abbreviation: config.target_abbreviation(),
+            display_span_id: config.with_span_ids(),
+            display_trace_id: config.with_trace_id(),
+        })
+    }
+}
+
+impl<T> TemplateFormat<T> {
+    /// Use the given [`FormatTime`] implementation for the event timestamp.
+    pub fn with_timer<T2>(self, timer: T2) -> TemplateFormat<T2> {
+        TemplateFormat {
+            template: self.template,
+            timer,
+            theme: self.theme,
+            static_fields: self.static_fields,
+            limits: self.limits,
+            newlines: self.newlines,
+            target_abbreviation: self.target_abbreviation,
+            display_span_id: self.display_span_id,
+            display_trace_id: self.display_trace_id,
+        }
+    }
+}
+
+impl<T> TemplateFormat<T>
+where
+    T: FormatTime,
+{
+    /// The value of a placeholder and the style it is painted with.
+    fn value<S, N>(
+        &self,
+        placeholder: Placeholder,
+        ctx: &FmtContext<'_, S, N>,
+        event: &Event<'_>,
+        message: &str,
+        fields: &str,
+        state: &EventState,
+    ) -> (String, Style)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        let meta = event.metadata();
+        let current_thread = std::thread::current();
+        match placeholder {
+            Placeholder::Timestamp => {
+                let mut timestamp = String::new();
+                // a failure to get the time is not a formatting error
+                if self
+                    .timer
+                    .format_time(&mut Writer::new(&mut timestamp))
+                    .is_err()
+                {
+                    timestamp = "<unknown time>".to_string();
+                }
+                (timestamp, Style::new().dimmed())
+            }
+            Placeholder::Level => (meta.level().to_string(), self.theme.level(*meta.level())),
+            Placeholder::Target => (
+                self.target_abbreviation.apply(meta.target()).into_owned(),
+                self.theme.target.unwrap_or(Style::new().dimmed()),
+            ),
+            Placeholder::Module => (
+                meta.module_path().unwrap_or_default().to_string(),
+                Style::new(),
+            ),
+            Placeholder::File => (
+                meta.file().unwrap_or_default().to_string(),
+                Style::new().dimmed(),
+            ),
+            Placeholder::Line => (
+                meta.line().map(|line| line.to_string()).unwrap_or_default(),
+                Style::new().dimmed(),
+            ),
+            Placeholder::Thread => (
+                current_thread
+                    .name()
+                    .map_or_else(|| format!("{:?}", current_thread.id()), str::to_string),
+                Style::new(),
+            ),
+            Placeholder::ThreadId => (format!("{:?}", current_thread.id()), Style::new()),
+            Placeholder::ThreadName => (
+                current_thread.name().unwrap_or_default().to_string(),
+                Style::new(),
+            ),
+            Placeholder::Span => (
+                ctx.parent_span()
+                    .map(|span| span.name().to_string())
+                    .unwrap_or_default(),
+                self.theme.span,
+            ),
+            Placeholder::Spans => (spans(ctx, state), Style::new()),
+            Placeholder::SpanId => (
+                SpanIds::new(ctx.parent_span().as_ref(), true, false)
+                    .span_id
+                    .unwrap_or_default(),
+                Style::new().dimmed(),
+            ),
+            Placeholder::TraceId => (
+                SpanIds::new(ctx.parent_span().as_ref(), false, true)
+                    .trace_id
+                    .unwrap_or_default(),
+                Style::new().dimmed(),
+            ),
+            Placeholder::Message => (message.to_string(), Style::new()),
+            Placeholder::Fields => (fields.to_string(), Style::new()),
+        }
+    }
+}
+
+impl<S, N, T> FormatEvent<S, N> for TemplateFormat<T>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    T: FormatTime,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        let ansi = writer.has_ansi_escapes();
+        let mut state = EventState::new(
+            ansi,
+            writer.sanitizes_ansi_escapes(),
+            self.newlines,
+            self.limits.event(),
+        );
+
+        let mut message = String::new();
+        if self.template.contains(Placeholder::Message) {
+            let mut visitor =
+                TextVisitor::new(Writer::new(&mut message), &mut state, false, Style::new())
+                    .only(true);
+            event.record(&mut visitor);
+            visitor.finish()?;
+        }
+        let mut fields = String::new();
+        if self.template.contains(Placeholder::Fields) {
+            let mut visitor =
+                TextVisitor::new(Writer::new(&mut fields), &mut state, false, Style::new())
+                    .only(false);
+            event.record(&mut visitor);
+            for (name, value) in &self.static_fields {
+                visitor.record_static(name, value);
+            }
+            let ids = SpanIds::new(
+                ctx.parent_span().as_ref(),
+                self.display_span_id,
+                self.display_trace_id,
+            );
+            for (name, value) in ids.pairs() {
+                visitor.write_value(name, value);
+            }
+            visitor.finish()?;
+        }
+
+        let mut line = String::new();
+        for segment in &self.template.segments {
+            let (placeholder, align, width) = match segment {
+                Segment::Literal(literal) => {
+                    line.push_str(literal);
+                    continue;
+                }
+                Segment::Placeholder {
+                    placeholder,
+                    align,
+                    width,
+                } => (*placeholder, *align, *width),
+            };
+            let (value, style) = self.value(placeholder, ctx, event, &message, &fields, &state);
+            let padded = match align {
+                Align::Left => format!("{value:<width$}"),
+                Align::Right => format!("{value:>width$}"),
+                Align::Center => format!("{value:^width$}"),
+            };
+            write!(line, "{}", paint(ansi, style).paint(padded))?;
+        }
+        let len = line.trim_end_matches(' ').len();
+        line.truncate(len);
+        line.push('\n');
+
+        let reset = if ansi { "\x1b[0m" } else { "" };
+        let truncated = self.limits.truncate_event(&mut line, "\n", reset);
+        state.limits.mark(truncated);
+        state.limits.finish();
+        writer.write_str(&line)
+    }
+}
+
+/// The spans in scope with their escaped fields, from root to leaf, i.e. `outer{id=1}:inner`.
+fn spans<S, N>(ctx: &FmtContext<'_, S, N>, state: &EventState) -> String
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    let mut spans = String::new();
+    if let Some(scope) = ctx.event_scope() {
+        for span in scope.from_root() {
+            if !spans.is_empty() {
+                spans.push(':');
+            }
+            spans.push_str(span.name());
+            if let Some(fields) = span.extensions().get::<FormattedFields<N>>()
+                && !fields.is_empty()
+            {
+                let _res = write!(spans, "{{{}}}", state.span_fields(&fields.fields));
+            }
+        }
+    }
+    spans
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "template")))]
+/// Create a [`TemplateFormat`] layer configured from the given [`TracingConfig`].
+///
+/// # Errors
+/// * An error is returned if the [`template`](crate::TracingConfig::template) is invalid.
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{template, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let (layer, level_filter) = template(&config)?;
+/// let layer = layer.with_filter(level_filter);
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn template<C, S>(
+    config: &C,
+) -> Result<(
+    fmt::Layer<S, DefaultFields, TemplateFormat, Output>,
+    LevelFilter,
+)>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let format = TemplateFormat::new(config)?;
+    let output = Output::new(config);
+    let layer = fmt::layer().with_ansi(config.color().enabled_for(&output));
+
+    // span events can only be configured while the layer still has a `Format` event formatter
+    let layer = if let Some(fmt_span) = config.with_span_events() {
+        layer.with_span_events(fmt_span)
+    } else {
+        layer
+    };
+    let layer = layer.event_format(format).with_writer(output);
+    let level = get_effective_level(config.quiet(), config.verbose());
+    let level_filter = LevelFilter::from(level);
+    Ok((layer, level_filter))
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "template")))]
+/// Create a [`TemplateFormat`] filtered layer configured from the given [`TracingConfig`].
+///
+/// # Errors
+/// * An error is returned if the [`template`](crate::TracingConfig::template) is invalid.
+///
+/// # Example
+/// ```rust
+/// # use anyhow::Result;
+/// # use tracing::info;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{template_filtered, set_default, TestAll, TracingConfig};
+/// #
+/// # pub fn main() -> Result<()> {
+/// let config = TestAll;
+/// let layer = template_filtered(&config)?;
+/// let _unused = set_default(vec![layer.boxed()]);
+/// info!("info level");
+/// #   Ok(())
+/// # }
+/// ```
+pub fn filtered<C, S>(config: &C) -> Result<Filtered<TemplateLayer<S>, LevelFilter, S>>
+where
+    C: TracingConfig,
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+{
+    let (layer, level_filter) = template(config)?;
+    Ok(layer.with_filter(level_filter))
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::{Level, info, span};
+    use tracing_subscriber::Layer;
+
+    use super::{Template, template};
+
+    use crate::{TimerConfig, TracingConfig, set_default, utils::test::TestWriter};
+
+    #[derive(Clone, Debug)]
+    struct TestTemplate(&'static str);
+
+    impl TracingConfig for TestTemplate {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn static_fields(&self) -> Vec<(String, String)> {
+            vec![("service".to_string(), "api".to_string())]
+        }
+
+        fn template(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_ansi(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn templates_parse() {
+        assert!(Template::parse("{timestamp} {level:>5} [{thread}] {target}: {message}").is_ok());
+        assert!(Template::parse("{{literal}} {line:^4}").is_ok());
+
+        let err = Template::parse("{level} {levle}").expect_err("unknown placeholder");
+        assert!(
+            err.to_string()
+                .starts_with("unknown placeholder '{levle}' at offset 8"),
+            "{err}"
+        );
+        assert!(Template::parse("{level:>x}").is_err());
+        assert!(Template::parse("{level").is_err());
+        assert!(Template::parse("level}").is_err());
+    }
+
+    #[test]
+    fn template_layout_is_applied() {
+        let writer = TestWriter::default();
+        let config =
+            TestTemplate("{{{level:>5}}} <{span:^7}> {spans} {message} | {fields} {timestamp}");
+        let (layer, level_filter) = template(&config).expect("template");
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "outer", id = 1);
+        span.in_scope(|| info!(count = 3, "hello"));
+
+        assert_eq!(
+            writer.lines(),
+            vec![r#"{ INFO} < outer > outer{id=1} hello | count=3 service="api""#]
+        );
+    }
+
+    #[test]
+    fn invalid_template_is_an_error() {
+        assert!(template::<_, tracing_subscriber::Registry>(&TestTemplate("{nope}")).is_err());
+    }
+
+    #[test]
+    fn span_and_trace_id_placeholders() {
+        let writer = TestWriter::default();
+        let (layer, _) =
+            template(&TestTemplate("{message} {span_id} {trace_id}")).expect("template");
+        let _unused = set_default(vec![layer.with_writer(writer.clone()).boxed()]);
+        info!("outside");
+        let span = span!(Level::INFO, "outer");
+        span.in_scope(|| info!("inside"));
+
+        let lines = writer.lines();
+        assert_eq!(lines[0], "outside");
+        let parts = lines[1].split(' ').collect::<Vec<_>>();
+        assert_eq!(parts[1], format!("{:016x}", span.id().unwrap().into_u64()));
+        assert_eq!(parts[2].len(), 32);
+    }
+}
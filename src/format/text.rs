@@ -0,0 +1,1542 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt::{self as stdfmt, Debug, Display, Result as FmtResult, Write as _},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use nu_ansi_term::Style;
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{
+    fmt::{
+        FmtContext, FormatEvent, FormatFields, FormattedFields, format::Writer, time::FormatTime,
+    },
+    registry::LookupSpan,
+};
+
+use crate::{
+    Theme, Timer, TracingConfig,
+    format::ids::SpanIds,
+    limits::{EventLimits, Limits},
+};
+
+/// How the [`full_text`](crate::full_text), [`compact_text`](crate::compact_text), [`pretty_text`](crate::pretty_text) and
+/// `syslog` formats write line breaks in event messages and field values, see
+/// [`newline_policy`](crate::TracingConfig::newline_policy).
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum NewlinePolicy {
+    /// Write line breaks as is
+    #[default]
+    Verbatim,
+    /// Write line breaks as `\n` and `\r`, so every event is a single line
+    Escape,
+    /// Indent the continuation lines of multi-line values, so they cannot be mistaken for a new event
+    Indent,
+}
+
+/// How the text formats write the event target, see [`target_abbreviation`](crate::TracingConfig::target_abbreviation).
+///
+/// Abbreviated segments are shortened to their first character followed by a `.`, in the style of logback,
+/// i.e. `my_service::handlers::billing::invoices::pdf` becomes `m.h.b.invoices::pdf`.  The last segment is never
+/// abbreviated.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum TargetAbbreviation {
+    /// Write the full target
+    #[default]
+    Full,
+    /// Abbreviate all but the given number of trailing segments
+    Abbreviate(usize),
+    /// Only write the given number of trailing segments, i.e. `invoices::pdf`
+    KeepLast(usize),
+    /// Abbreviate leading segments, one at a time, until the target fits the given width
+    MaxWidth(usize),
+}
+
+impl TargetAbbreviation {
+    /// The given target, abbreviated.
+    pub(crate) fn apply(self, target: &str) -> Cow<'_, str> {
+        if self == TargetAbbreviation::Full || !target.contains("::") {
+            return Cow::Borrowed(target);
+        }
+        let segments: Vec<&str> = target.split("::").collect();
+        let count = segments.len();
+        match self {
+            TargetAbbreviation::Full => Cow::Borrowed(target),
+            TargetAbbreviation::Abbreviate(keep) => {
+                Cow::Owned(abbreviate(&segments, count.saturating_sub(keep.max(1))))
+            }
+            TargetAbbreviation::KeepLast(keep) => {
+                Cow::Owned(segments[count.saturating_sub(keep.max(1))..].join("::"))
+            }
+            TargetAbbreviation::MaxWidth(width) => {
+                let mut abbreviated = target.to_string();
+                for leading in 1..count {
+                    if abbreviated.len() <= width {
+                        break;
+                    }
+                    abbreviated = abbreviate(&segments, leading);
+                }
+                Cow::Owned(abbreviated)
+            }
+        }
+    }
+}
+
+/// Join the target segments, abbreviating the given number of leading segments.
+fn abbreviate(segments: &[&str], leading: usize) -> String {
+    let (abbreviated, kept) = segments.split_at(leading.min(segments.len() - 1));
+    let mut target = String::new();
+    for segment in abbreviated {
+        if let Some(first) = segment.chars().next() {
+            target.push(first);
+            target.push('.');
+        }
+    }
+    target.push_str(&kept.join("::"));
+    target
+}
+
+/// The layout used by a [`TextFormat`] formatter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Layout {
+    Full,
+    Compact,
+    Pretty,
+}
+
+/// A human-readable [`FormatEvent`] implementation used by the [`full_text`](crate::full_text),
+/// [`compact_text`](crate::compact_text) and [`pretty_text`](crate::pretty_text) formats.
+///
+/// The output matches the corresponding [`tracing-subscriber`](mod@tracing_subscriber::fmt::format) formats,
+/// with the [`static_fields`](crate::TracingConfig::static_fields) of the [`TracingConfig`] rendered after
+/// the event fields, followed by the `span_id` and `trace_id` of the current span if
+/// [`with_span_ids`](crate::TracingConfig::with_span_ids) or [`with_trace_id`](crate::TracingConfig::with_trace_id)
+/// are enabled, the timestamp written by the configured [`timer`](crate::TracingConfig::timer) and
+/// the colors of the configured [`theme`](crate::TracingConfig::theme).  The configured
+/// [`limits`](crate::TracingConfig::limits) are applied to every event, and line breaks are written according to
+/// the configured [`newline_policy`](crate::TracingConfig::newline_policy).  Targets are abbreviated as configured
+/// by the [`target_abbreviation`](crate::TracingConfig::target_abbreviation).
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug)]
+pub struct TextFormat<T = Timer> {
+    timer: T,
+    display_timestamp: bool,
+    layout: Layout,
+    theme: Theme,
+    static_fields: Vec<(String, String)>,
+    limits: Limits,
+    newlines: NewlinePolicy,
+    target_abbreviation: TargetAbbreviation,
+    display_level: bool,
+    display_target: bool,
+    display_filename: bool,
+    display_line_number: bool,
+    display_thread_id: bool,
+    display_thread_name: bool,
+    display_span_id: bool,
+    display_trace_id: bool,
+}
+
+impl TextFormat {
+    /// Create a [`TextFormat`] formatter with the [`Full`](tracing_subscriber::fmt::format::Full) layout
+    /// configured from the given [`TracingConfig`].
+    pub fn full<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self::new(config, Layout::Full)
+    }
+
+    /// Create a [`TextFormat`] formatter with the [`Compact`](tracing_subscriber::fmt::format::Compact) layout
+    /// configured from the given [`TracingConfig`].
+    pub fn compact<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self::new(config, Layout::Compact)
+    }
+
+    /// Create a [`TextFormat`] formatter with the [`Pretty`](tracing_subscriber::fmt::format::Pretty) layout
+    /// configured from the given [`TracingConfig`].
+    pub fn pretty<C>(config: &C) -> Self
+    where
+        C: TracingConfig,
+    {
+        Self::new(config, Layout::Pretty)
+    }
+
+    fn new<C>(config: &C, layout: Layout) -> Self
+    where
+        C: TracingConfig,
+    {
+        let timer = Timer::new(&config.timer());
+        Self {
+            display_timestamp: timer.is_enabled(),
+            timer,
+            layout,
+            theme: config.theme(),
+            static_fields: config.static_fields(),
+            limits: config.limits(),
+            newlines: config.newline_policy(),
+            target_abbreviation: config.target_abbreviation(),
+            display_level: config.with_level(),
+            display_target: config.with_target(),
+            display_filename: config.with_file(),
+            display_line_number: config.with_line_number(),
+            display_thread_id: config.with_thread_ids(),
+            display_thread_name: config.with_thread_names(),
+            display_span_id: config.with_span_ids(),
+            display_trace_id: config.with_trace_id(),
+        }
+    }
+}
+
+impl<T> TextFormat<T> {
+    /// Use the given [`FormatTime`] implementation for the event timestamp.
+    pub fn with_timer<T2>(self, timer: T2) -> TextFormat<T2> {
+        TextFormat {
+            timer,
+            display_timestamp: true,
+            layout: self.layout,
+            theme: self.theme,
+            static_fields: self.static_fields,
+            limits: self.limits,
+            newlines: self.newlines,
+            target_abbreviation: self.target_abbreviation,
+            display_level: self.display_level,
+            display_target: self.display_target,
+            display_filename: self.display_filename,
+            display_line_number: self.display_line_number,
+            display_thread_id: self.display_thread_id,
+            display_thread_name: self.display_thread_name,
+            display_span_id: self.display_span_id,
+            display_trace_id: self.display_trace_id,
+        }
+    }
+
+    fn format_timestamp(&self, writer: &mut Writer<'_>, ansi: bool) -> FmtResult
+    where
+        T: FormatTime,
+    {
+        if !self.display_timestamp {
+            return Ok(());
+        }
+        let dimmed = paint(ansi, Style::new().dimmed());
+        write!(writer, "{}", dimmed.prefix())?;
+        // a failure to get the time is not a formatting error
+        if self.timer.format_time(writer).is_err() {
+            writer.write_str("<unknown time>")?;
+        }
+        write!(writer, "{} ", dimmed.suffix())
+    }
+
+    fn format_level(&self, writer: &mut Writer<'_>, level: Level, ansi: bool) -> FmtResult {
+        if self.display_level {
+            let style = paint(ansi, self.theme.level(level));
+            write!(writer, "{} ", style.paint(level_str(level)))?;
+        }
+        Ok(())
+    }
+
+    fn format_thread(&self, writer: &mut Writer<'_>) -> FmtResult {
+        let current_thread = std::thread::current();
+        if self.display_thread_name {
+            match current_thread.name() {
+                Some(name) => write!(writer, "{} ", ThreadName(name))?,
+                // fall-back to thread id when name is absent and ids are not enabled
+                None if !self.display_thread_id => write!(writer, "{:0>2?} ", current_thread.id())?,
+                None => {}
+            }
+        }
+        if self.display_thread_id {
+            write!(writer, "{:0>2?} ", current_thread.id())?;
+        }
+        Ok(())
+    }
+
+    fn format_fields<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: &mut Writer<'_>,
+        event: &Event<'_>,
+        style: Style,
+        state: &mut EventState,
+    ) -> FmtResult
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        let mut visitor =
+            TextVisitor::new(writer.by_ref(), state, self.layout == Layout::Pretty, style);
+        event.record(&mut visitor);
+        for (name, value) in &self.static_fields {
+            visitor.record_static(name, value);
+        }
+        let ids = SpanIds::new(
+            ctx.parent_span().as_ref(),
+            self.display_span_id,
+            self.display_trace_id,
+        );
+        for (name, value) in ids.pairs() {
+            visitor.write_value(name, value);
+        }
+        visitor.finish()
+    }
+
+    fn format<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: &mut Writer<'_>,
+        event: &Event<'_>,
+        state: &mut EventState,
+    ) -> FmtResult
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+        T: FormatTime,
+    {
+        if self.layout == Layout::Pretty {
+            writer.write_str("  ")?;
+        }
+        self.format_timestamp(writer, state.ansi)?;
+        match self.layout {
+            Layout::Full => self.format_full(ctx, writer, event, state),
+            Layout::Compact => self.format_compact(ctx, writer, event, state),
+            Layout::Pretty => self.format_pretty(ctx, writer, event, state),
+        }
+    }
+
+    fn format_full<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: &mut Writer<'_>,
+        event: &Event<'_>,
+        state: &mut EventState,
+    ) -> FmtResult
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        let ansi = state.ansi;
+        let meta = event.metadata();
+        self.format_level(writer, *meta.level(), ansi)?;
+        self.format_thread(writer)?;
+
+        let span_style = paint(ansi, self.theme.span);
+        let target_style = paint(ansi, self.theme.target.unwrap_or(Style::new().dimmed()));
+        let dimmed = paint(ansi, Style::new().dimmed());
+        if let Some(scope) = ctx.event_scope() {
+            let mut seen = false;
+            for span in scope.from_root() {
+                write!(writer, "{}", span_style.paint(span.metadata().name()))?;
+                seen = true;
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>()
+                    && !fields.is_empty()
+                {
+                    write!(
+                        writer,
+                        "{}{}{}",
+                        span_style.paint("{"),
+                        state.span_fields(&fields.fields),
+                        span_style.paint("}")
+                    )?;
+                }
+                write!(writer, "{}", dimmed.paint(":"))?;
+            }
+            if seen {
+                writer.write_char(' ')?;
+            }
+        }
+
+        if self.display_target {
+            write!(
+                writer,
+                "{}{} ",
+                target_style.paint(self.target_abbreviation.apply(meta.target())),
+                dimmed.paint(":")
+            )?;
+        }
+        let line_number = if self.display_line_number {
+            meta.line()
+        } else {
+            None
+        };
+        if self.display_filename
+            && let Some(filename) = meta.file()
+        {
+            write!(
+                writer,
+                "{}{}{}",
+                dimmed.paint(filename),
+                dimmed.paint(":"),
+                if line_number.is_some() { "" } else { " " }
+            )?;
+        }
+        if let Some(line_number) = line_number {
+            write!(
+                writer,
+                "{}{}:{} ",
+                dimmed.prefix(),
+                line_number,
+                dimmed.suffix()
+            )?;
+        }
+
+        self.format_fields(ctx, writer, event, Style::new(), state)?;
+        writeln!(writer)
+    }
+
+    fn format_compact<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: &mut Writer<'_>,
+        event: &Event<'_>,
+        state: &mut EventState,
+    ) -> FmtResult
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        let ansi = state.ansi;
+        let meta = event.metadata();
+        self.format_level(writer, *meta.level(), ansi)?;
+        self.format_thread(writer)?;
+
+        let span_style = paint(ansi, self.theme.span);
+        let target_style = paint(ansi, self.theme.target.unwrap_or(Style::new().dimmed()));
+        let dimmed = paint(ansi, Style::new().dimmed());
+        if let Some(span) = ctx.parent_span() {
+            for span in span.scope().from_root() {
+                write!(writer, "{}:", span_style.paint(span.metadata().name()))?;
+            }
+            writer.write_char(' ')?;
+        }
+
+        let mut needs_space = false;
+        if self.display_target {
+            write!(
+                writer,
+                "{}{}",
+                target_style.paint(self.target_abbreviation.apply(meta.target())),
+                dimmed.paint(":")
+            )?;
+            needs_space = true;
+        }
+        if self.display_filename
+            && let Some(filename) = meta.file()
+        {
+            if self.display_target {
+                writer.write_char(' ')?;
+            }
+            write!(writer, "{}{}", dimmed.paint(filename), dimmed.paint(":"))?;
+            needs_space = true;
+        }
+        if self.display_line_number
+            && let Some(line_number) = meta.line()
+        {
+            write!(
+                writer,
+                "{}{}{}{}",
+                dimmed.prefix(),
+                line_number,
+                dimmed.suffix(),
+                dimmed.paint(":")
+            )?;
+            needs_space = true;
+        }
+        if needs_space {
+            writer.write_char(' ')?;
+        }
+
+        self.format_fields(ctx, writer, event, Style::new(), state)?;
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>()
+                    && !fields.is_empty()
+                {
+                    write!(
+                        writer,
+                        " {}{}{}",
+                        dimmed.prefix(),
+                        state.span_fields(&fields.fields),
+                        dimmed.suffix()
+                    )?;
+                }
+            }
+        }
+        writeln!(writer)
+    }
+
+    fn format_pretty<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: &mut Writer<'_>,
+        event: &Event<'_>,
+        state: &mut EventState,
+    ) -> FmtResult
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        let ansi = state.ansi;
+        let meta = event.metadata();
+        let style = if self.display_level {
+            paint(ansi, self.theme.level(*meta.level()))
+        } else {
+            Style::new()
+        };
+        self.format_level(writer, *meta.level(), ansi)?;
+
+        if self.display_target {
+            let target_style = paint(ansi, self.theme.target.unwrap_or(style.bold()));
+            write!(
+                writer,
+                "{}{}{}:",
+                target_style.prefix(),
+                self.target_abbreviation.apply(meta.target()),
+                target_style.infix(style)
+            )?;
+        }
+        let line_number = if self.display_line_number {
+            meta.line()
+        } else {
+            None
+        };
+        // without the file name the line number goes right after the target, otherwise it goes
+        // on the location line below
+        if let Some(line_number) = line_number
+            && !self.display_filename
+        {
+            write!(
+                writer,
+                "{}{}{}:",
+                style.prefix(),
+                line_number,
+                style.infix(style)
+            )?;
+        }
+        writer.write_char(' ')?;
+        self.format_fields(ctx, writer, event, style, state)?;
+        writer.write_char('\n')?;
+
+        let dimmed = paint(ansi, Style::new().dimmed().italic());
+        let thread = self.display_thread_name || self.display_thread_id;
+        if self.display_filename
+            && let Some(file) = meta.file()
+        {
+            write!(writer, "    {} {file}", dimmed.paint("at"))?;
+            if let Some(line) = line_number {
+                write!(writer, ":{line}")?;
+            }
+            writer.write_char(if thread { ' ' } else { '\n' })?;
+        } else if thread {
+            write!(writer, "    ")?;
+        }
+        if thread {
+            write!(writer, "{} ", dimmed.paint("on"))?;
+            let current_thread = std::thread::current();
+            if self.display_thread_name
+                && let Some(name) = current_thread.name()
+            {
+                write!(writer, "{name}")?;
+                if self.display_thread_id {
+                    writer.write_char(' ')?;
+                }
+            }
+            if self.display_thread_id {
+                write!(writer, "{:?}", current_thread.id())?;
+            }
+            writer.write_char('\n')?;
+        }
+
+        let span_style = paint(ansi, self.theme.span);
+        if let Some(span) = ctx.parent_span() {
+            for span in span.scope() {
+                let span_meta = span.metadata();
+                if self.display_target {
+                    write!(
+                        writer,
+                        "    {} {}::{}",
+                        dimmed.paint("in"),
+                        self.target_abbreviation.apply(span_meta.target()),
+                        span_style.paint(span_meta.name())
+                    )?;
+                } else {
+                    write!(
+                        writer,
+                        "    {} {}",
+                        dimmed.paint("in"),
+                        span_style.paint(span_meta.name())
+                    )?;
+                }
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>()
+                    && !fields.is_empty()
+                {
+                    write!(
+                        writer,
+                        " {} {}",
+                        dimmed.paint("with"),
+                        state.span_fields(&fields.fields)
+                    )?;
+                }
+                writer.write_char('\n')?;
+            }
+        }
+        writer.write_char('\n')
+    }
+}
+
+impl<S, N, T> FormatEvent<S, N> for TextFormat<T>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    T: FormatTime,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        let mut state = EventState {
+            ansi: writer.has_ansi_escapes(),
+            escapes: Escapes {
+                sanitize: writer.sanitizes_ansi_escapes(),
+                newlines: self.newlines,
+            },
+            limits: self.limits.event(),
+        };
+        if self.limits.max_event_size.is_none() {
+            self.format(ctx, &mut writer, event, &mut state)?;
+        } else {
+            // the event is buffered so it can be cut short, the escape settings of the real writer are
+            // carried by the state
+            let mut buffer = String::new();
+            self.format(ctx, &mut Writer::new(&mut buffer), event, &mut state)?;
+            let reset = if state.ansi { "\x1b[0m" } else { "" };
+            let truncated = self.limits.truncate_event(&mut buffer, "\n", reset);
+            state.limits.mark(truncated);
+            writer.write_str(&buffer)?;
+        }
+        state.limits.finish();
+        Ok(())
+    }
+}
+
+/// The escape settings of the underlying writer and the limits of the event being formatted.
+#[derive(Debug)]
+pub(crate) struct EventState {
+    ansi: bool,
+    escapes: Escapes,
+    pub(crate) limits: EventLimits,
+}
+
+impl EventState {
+    /// The state of an event written to a writer with the given escape settings.
+    #[cfg(any(feature = "template", all(feature = "syslog", unix)))]
+    pub(crate) fn new(
+        ansi: bool,
+        sanitize: bool,
+        newlines: NewlinePolicy,
+        limits: EventLimits,
+    ) -> Self {
+        Self {
+            ansi,
+            escapes: Escapes { sanitize, newlines },
+            limits,
+        }
+    }
+
+    /// The fields of a span rendered by the field formatter, escaped like the event field values.
+    pub(crate) fn span_fields<'a>(&self, fields: &'a str) -> SpanFields<'a> {
+        SpanFields {
+            fields,
+            ansi: self.ansi,
+            escapes: self.escapes.fields(),
+        }
+    }
+}
+
+/// The rendered fields of a span, escaped while keeping the styles the field formatter applied to the names.
+pub(crate) struct SpanFields<'a> {
+    fields: &'a str,
+    ansi: bool,
+    escapes: Escapes,
+}
+
+impl Display for SpanFields<'_> {
+    fn fmt(&self, f: &mut stdfmt::Formatter<'_>) -> FmtResult {
+        let mut rest = self.fields;
+        while let Some(start) = rest.find('\x1b').filter(|_| self.ansi) {
+            let sequence = &rest[start..];
+            // only the SGR sequences, i.e. `\x1b[3m`, of the field formatter are kept
+            let len = sequence
+                .strip_prefix("\x1b[")
+                .and_then(|params| params.find(|ch: char| !ch.is_ascii_digit() && ch != ';'))
+                .filter(|&end| sequence.as_bytes().get(end + 2) == Some(&b'm'))
+                .map(|end| end + 3);
+            let (text, kept) = match len {
+                Some(len) => (&rest[..start], &sequence[..len]),
+                None => (&rest[..=start], ""),
+            };
+            write!(
+                f,
+                "{}{kept}",
+                Escaped {
+                    value: text,
+                    escapes: self.escapes,
+                }
+            )?;
+            rest = &rest[text.len() + kept.len()..];
+        }
+        write!(
+            f,
+            "{}",
+            Escaped {
+                value: rest,
+                escapes: self.escapes,
+            }
+        )
+    }
+}
+
+/// The given style if the writer emits ANSI escape codes, an empty style otherwise.
+pub(crate) fn paint(ansi: bool, style: Style) -> Style {
+    if ansi { style } else { Style::new() }
+}
+
+fn level_str(level: Level) -> &'static str {
+    match level {
+        Level::TRACE => "TRACE",
+        Level::DEBUG => "DEBUG",
+        Level::INFO => " INFO",
+        Level::WARN => " WARN",
+        Level::ERROR => "ERROR",
+    }
+}
+
+/// A thread name padded to the longest thread name seen so far.
+struct ThreadName<'a>(&'a str);
+
+impl Display for ThreadName<'_> {
+    fn fmt(&self, f: &mut stdfmt::Formatter<'_>) -> FmtResult {
+        static MAX_LEN: AtomicUsize = AtomicUsize::new(0);
+        let len = self.0.len();
+        let max_len = MAX_LEN.fetch_max(len, Ordering::AcqRel).max(len);
+        write!(f, "{:>width$}", self.0, width = max_len)
+    }
+}
+
+/// The indentation of continuation lines with the [`NewlinePolicy::Indent`] policy.
+const CONTINUATION_INDENT: &str = "    ";
+
+/// How control characters are escaped while formatting a value.
+#[derive(Clone, Copy, Debug)]
+struct Escapes {
+    /// Escape terminal control sequences, as requested by the writer
+    sanitize: bool,
+    /// Line breaks, and all other control characters unless verbatim
+    newlines: NewlinePolicy,
+}
+
+impl Escapes {
+    fn enabled(self) -> bool {
+        self.sanitize || self.newlines != NewlinePolicy::Verbatim
+    }
+
+    /// The escapes of field values other than the message, which the writer does not sanitize.
+    fn fields(self) -> Self {
+        Self {
+            sanitize: false,
+            newlines: self.newlines,
+        }
+    }
+}
+
+/// Escapes terminal control sequences while formatting the wrapped value.
+struct Escaped<T> {
+    value: T,
+    escapes: Escapes,
+}
+
+struct EscapingWriter<'a, 'b> {
+    f: &'a mut stdfmt::Formatter<'b>,
+    newlines: NewlinePolicy,
+}
+
+impl stdfmt::Write for EscapingWriter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> FmtResult {
+        let all = self.newlines != NewlinePolicy::Verbatim;
+        for ch in s.chars() {
+            match ch {
+                '\n' if self.newlines == NewlinePolicy::Escape => self.f.write_str("\\n")?,
+                '\n' if self.newlines == NewlinePolicy::Indent => {
+                    self.f.write_char('\n')?;
+                    self.f.write_str(CONTINUATION_INDENT)?;
+                }
+                '\r' if all => self.f.write_str("\\r")?,
+                '\x1b' => self.f.write_str("\\x1b")?,
+                '\x07' => self.f.write_str("\\x07")?,
+                '\x08' => self.f.write_str("\\x08")?,
+                '\x0c' => self.f.write_str("\\x0c")?,
+                '\x7f' => self.f.write_str("\\x7f")?,
+                '\u{80}'..='\u{9f}' => write!(self.f, "\\u{{{:x}}}", u32::from(ch))?,
+                '\0'..='\x08' | '\x0b'..='\x1f' if all => {
+                    write!(self.f, "\\x{:02x}", u32::from(ch))?;
+                }
+                ch => self.f.write_char(ch)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Debug> Debug for Escaped<T> {
+    fn fmt(&self, f: &mut stdfmt::Formatter<'_>) -> FmtResult {
+        if self.escapes.enabled() {
+            let newlines = self.escapes.newlines;
+            write!(EscapingWriter { f, newlines }, "{:?}", self.value)
+        } else {
+            write!(f, "{:?}", self.value)
+        }
+    }
+}
+
+impl<T: Display> Display for Escaped<T> {
+    fn fmt(&self, f: &mut stdfmt::Formatter<'_>) -> FmtResult {
+        if self.escapes.enabled() {
+            let newlines = self.escapes.newlines;
+            write!(EscapingWriter { f, newlines }, "{}", self.value)
+        } else {
+            write!(f, "{}", self.value)
+        }
+    }
+}
+
+/// Renders an error and its sources as a list.
+struct ErrorSources<'a> {
+    error: &'a (dyn Error + 'static),
+    escapes: Escapes,
+}
+
+impl Display for ErrorSources<'_> {
+    fn fmt(&self, f: &mut stdfmt::Formatter<'_>) -> FmtResult {
+        let mut list = f.debug_list();
+        let mut current = Some(self.error);
+        while let Some(error) = current {
+            let _list = list.entry(&Escaped {
+                value: format_args!("{error}"),
+                escapes: self.escapes,
+            });
+            current = error.source();
+        }
+        list.finish()
+    }
+}
+
+/// Writes event fields as `name=value` pairs, or as `name: value` pairs for the pretty layout.
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct TextVisitor<'a> {
+    writer: Writer<'a>,
+    state: &'a mut EventState,
+    message: bool,
+    fields: bool,
+    pretty: bool,
+    style: Style,
+    is_empty: bool,
+    result: FmtResult,
+}
+
+impl<'a> TextVisitor<'a> {
+    pub(crate) fn new(
+        writer: Writer<'a>,
+        state: &'a mut EventState,
+        pretty: bool,
+        style: Style,
+    ) -> Self {
+        Self {
+            writer,
+            state,
+            message: true,
+            fields: true,
+            pretty,
+            style,
+            is_empty: true,
+            result: Ok(()),
+        }
+    }
+
+    /// Only write the message, or only the fields other than the message.
+    #[cfg(feature = "template")]
+    pub(crate) fn only(mut self, message: bool) -> Self {
+        self.message = message;
+        self.fields = !message;
+        self
+    }
+
+    fn pad(&mut self) -> FmtResult {
+        if self.is_empty {
+            self.is_empty = false;
+            Ok(())
+        } else if self.pretty {
+            self.writer.write_str(", ")
+        } else {
+            self.writer.write_char(' ')
+        }
+    }
+
+    fn record_value(&mut self, name: &str, value: &dyn Debug) {
+        let escapes = if name == "message" {
+            self.state.escapes
+        } else {
+            self.state.escapes.fields()
+        };
+        self.record_rendered(name, &format!("{:?}", Escaped { value, escapes }));
+    }
+
+    /// Write a field whose value is already rendered and escaped, applying the limits.
+    fn record_rendered(&mut self, name: &str, value: &str) {
+        let name = name.strip_prefix("r#").unwrap_or(name);
+        let selected = if name == "message" {
+            self.message
+        } else {
+            self.fields
+        };
+        if self.result.is_err() || !selected || !self.state.limits.admit(name) {
+            return;
+        }
+        let value = self.state.limits.value(name, value).into_owned();
+        self.write_value(name, &value);
+    }
+
+    /// Write a field configured rather than recorded, escaping its value as for an event field.  Static fields
+    /// are not counted against the field limit.
+    pub(crate) fn record_static(&mut self, name: &str, value: &str) {
+        let escapes = self.state.escapes.fields();
+        let value: &dyn Debug = &value;
+        self.write_value(name, &format!("{:?}", Escaped { value, escapes }));
+    }
+
+    /// Write a field whose value is already rendered, bypassing the limits.
+    pub(crate) fn write_value(&mut self, name: &str, value: &str) {
+        if self.result.is_err() {
+            return;
+        }
+        let ansi = self.state.ansi;
+        self.result = self.pad().and_then(|()| {
+            if name == "message" {
+                if self.pretty {
+                    write!(self.writer, "{}{value}", self.style.prefix())
+                } else {
+                    write!(self.writer, "{value}")
+                }
+            } else if self.pretty {
+                let bold = paint(ansi, self.style.bold());
+                write!(
+                    self.writer,
+                    "{}{name}{}: {value}",
+                    bold.prefix(),
+                    bold.infix(self.style)
+                )
+            } else {
+                let italic = paint(ansi, Style::new().italic());
+                let dimmed = paint(ansi, Style::new().dimmed());
+                write!(
+                    self.writer,
+                    "{}{}{value}",
+                    italic.paint(name),
+                    dimmed.paint("=")
+                )
+            }
+        });
+    }
+
+    pub(crate) fn finish(mut self) -> FmtResult {
+        if self.pretty {
+            write!(self.writer, "{}", self.style.suffix())?;
+        }
+        self.result
+    }
+}
+
+impl Visit for TextVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.record_value(field.name(), &format_args!("{value}"));
+        } else {
+            self.record_value(field.name(), &value);
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+        let escapes = self.state.escapes;
+        let error = Escaped {
+            value: format_args!("{value}"),
+            escapes,
+        };
+        if let Some(source) = value.source() {
+            let sources = ErrorSources {
+                error: source,
+                escapes,
+            };
+            if self.pretty {
+                let bold = paint(self.state.ansi, self.style.bold());
+                self.record_rendered(
+                    field.name(),
+                    &format!(
+                        "{error}, {}{}.sources{}: {sources}",
+                        bold.prefix(),
+                        field,
+                        bold.infix(self.style)
+                    ),
+                );
+            } else {
+                let italic = paint(self.state.ansi, Style::new().italic());
+                let dimmed = paint(self.state.ansi, Style::new().dimmed());
+                self.record_rendered(
+                    field.name(),
+                    &format!(
+                        "{error} {}{}{}{sources}",
+                        italic.paint(field.name()),
+                        italic.paint(".sources"),
+                        dimmed.paint("=")
+                    ),
+                );
+            }
+        } else {
+            self.record_rendered(field.name(), &error.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record_value(field.name(), value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::{Level, info, span, warn};
+    use tracing_subscriber::{
+        Layer,
+        fmt::{self, format::Pretty},
+    };
+
+    use super::{Escapes, EventState, TargetAbbreviation, TextFormat};
+
+    use crate::{
+        Color, ColorMode, Limits, NewlinePolicy, Style, TRUNCATION_MARKER, Theme, TimerConfig,
+        TracingConfig, compact_text, full_text, pretty_text, set_default, truncated_events,
+        utils::test::TestWriter,
+    };
+
+    #[derive(Clone, Debug)]
+    struct TestTheme;
+
+    impl TracingConfig for TestTheme {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn color(&self) -> ColorMode {
+            ColorMode::Always
+        }
+
+        fn theme(&self) -> Theme {
+            Theme {
+                info: Style::new().fg(Color::Cyan),
+                target: Some(Style::new().fg(Color::White)),
+                span: Style::new().underline(),
+                ..Theme::default()
+            }
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_target(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestStatic;
+
+    impl TracingConfig for TestStatic {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn static_fields(&self) -> Vec<(String, String)> {
+            vec![
+                ("service".to_string(), "api".to_string()),
+                ("version".to_string(), "1.2.3".to_string()),
+            ]
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_ansi(&self) -> bool {
+            false
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestLimited(Limits);
+
+    impl TracingConfig for TestLimited {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn limits(&self) -> Limits {
+            self.0
+        }
+
+        fn static_fields(&self) -> Vec<(String, String)> {
+            vec![("service".to_string(), "api".to_string())]
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_ansi(&self) -> bool {
+            false
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestNewlines(NewlinePolicy);
+
+    impl TracingConfig for TestNewlines {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn newline_policy(&self) -> NewlinePolicy {
+            self.0
+        }
+
+        fn static_fields(&self) -> Vec<(String, String)> {
+            vec![("host".to_string(), "web\n01".to_string())]
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_ansi(&self) -> bool {
+            false
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestIds;
+
+    impl TracingConfig for TestIds {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_ansi(&self) -> bool {
+            false
+        }
+
+        fn with_span_ids(&self) -> bool {
+            true
+        }
+
+        fn with_trace_id(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestAbbreviated;
+
+    impl TracingConfig for TestAbbreviated {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn target_abbreviation(&self) -> TargetAbbreviation {
+            TargetAbbreviation::Abbreviate(1)
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+
+        fn with_ansi(&self) -> bool {
+            false
+        }
+
+        fn with_target(&self) -> bool {
+            true
+        }
+    }
+
+    /// A value whose `Debug` output spans several lines.
+    struct MultiLine;
+
+    impl std::fmt::Debug for MultiLine {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("first\nsecond\x1b[31m")
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestText;
+
+    impl TracingConfig for TestText {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn with_ansi(&self) -> bool {
+            false
+        }
+
+        fn with_file(&self) -> bool {
+            true
+        }
+
+        fn with_line_number(&self) -> bool {
+            true
+        }
+
+        fn with_target(&self) -> bool {
+            true
+        }
+
+        fn with_thread_ids(&self) -> bool {
+            true
+        }
+    }
+
+    fn emit() {
+        let span = span!(Level::INFO, "outer", id = 1);
+        span.in_scope(|| {
+            let span = span!(Level::INFO, "inner", name = "a b");
+            span.in_scope(|| {
+                info!(count = 3, flag = true, "hello");
+                warn!(r#type = "raw", "\x1b[31mred\x1b[0m");
+            });
+        });
+    }
+
+    /// Format the test events with both the given `TextFormat` and the `tracing-subscriber` formatter.
+    fn render(ansi: bool, layout: &str) -> (Vec<String>, Vec<String>) {
+        let ours = TestWriter::default();
+        let theirs = TestWriter::default();
+        let config = TestText;
+        let format = match layout {
+            "compact" => TextFormat::compact(&config),
+            "pretty" => TextFormat::pretty(&config),
+            _ => TextFormat::full(&config),
+        }
+        .with_timer(());
+        let base = || {
+            fmt::layer()
+                .with_ansi(ansi)
+                .with_file(true)
+                .with_line_number(true)
+                .with_thread_ids(true)
+                .with_timer(())
+        };
+        let layers = match layout {
+            "compact" => vec![
+                base()
+                    .event_format(format)
+                    .with_writer(ours.clone())
+                    .boxed(),
+                base().compact().with_writer(theirs.clone()).boxed(),
+            ],
+            "pretty" => vec![
+                base()
+                    .fmt_fields(Pretty::default())
+                    .event_format(format)
+                    .with_writer(ours.clone())
+                    .boxed(),
+                base().pretty().with_writer(theirs.clone()).boxed(),
+            ],
+            _ => vec![
+                base()
+                    .event_format(format)
+                    .with_writer(ours.clone())
+                    .boxed(),
+                base().with_writer(theirs.clone()).boxed(),
+            ],
+        };
+        let _unused = set_default(layers);
+        emit();
+        (ours.lines(), theirs.lines())
+    }
+
+    #[test]
+    fn output_matches_tracing_subscriber() {
+        for layout in ["full", "compact", "pretty"] {
+            for ansi in [false, true] {
+                let (ours, theirs) = render(ansi, layout);
+                assert!(!ours.is_empty());
+                assert_eq!(ours, theirs, "{layout} (ansi: {ansi})");
+            }
+        }
+    }
+
+    #[test]
+    fn static_fields_follow_event_fields_without_timestamp() {
+        let writer = TestWriter::default();
+        let config = TestStatic;
+        let (full, _) = full_text(&config);
+        let (compact, _) = compact_text(&config);
+        let (pretty, _) = pretty_text(&config);
+        let _unused = set_default(vec![
+            full.with_writer(writer.clone()).boxed(),
+            compact.with_writer(writer.clone()).boxed(),
+            pretty.with_writer(writer.clone()).boxed(),
+        ]);
+        let span = span!(Level::INFO, "request", id = 1);
+        span.in_scope(|| info!(count = 3, "hello"));
+
+        let lines = writer.lines();
+        assert!(
+            lines[0]
+                .ends_with(r#" INFO request{id=1}: hello count=3 service="api" version="1.2.3""#),
+            "{}",
+            lines[0]
+        );
+        assert!(
+            lines[1]
+                .ends_with(r#" INFO request: hello count=3 service="api" version="1.2.3" id=1"#),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[2].ends_with(r#" INFO  hello, count: 3, service: "api", version: "1.2.3""#),
+            "{}",
+            lines[2]
+        );
+    }
+
+    #[test]
+    fn theme_is_applied() {
+        let writer = TestWriter::default();
+        let (layer, level_filter) = full_text(&TestTheme);
+        let layer = layer.with_writer(writer.clone()).with_filter(level_filter);
+        let _unused = set_default(vec![layer.boxed()]);
+        let span = span!(Level::INFO, "themed");
+        span.in_scope(|| info!("hello"));
+
+        let line = &writer.lines()[0];
+        assert!(
+            line.starts_with(&Color::Cyan.paint(" INFO").to_string()),
+            "{line:?}"
+        );
+        assert!(line.contains(&Style::new().underline().paint("themed").to_string()));
+        let target = Color::White.paint("tracing_subscriber_init::format::text::test");
+        assert!(line.contains(&target.to_string()), "{line:?}");
+    }
+
+    #[test]
+    fn limits_are_applied() {
+        let writer = TestWriter::default();
+        let limited = TestLimited(Limits {
+            max_message_len: Some(8),
+            max_field_len: Some(6),
+            max_fields: Some(1),
+            ..Limits::default()
+        });
+        let sized = TestLimited(Limits {
+            max_event_size: Some(120),
+            ..Limits::default()
+        });
+        let (limited, _) = full_text(&limited);
+        let (sized, _) = full_text(&sized);
+        let before = truncated_events();
+        {
+            let _unused = set_default(vec![limited.with_writer(writer.clone()).boxed()]);
+            info!(body = "abcdefgh", count = 3, "a long message");
+        }
+        {
+            let _unused = set_default(vec![sized.with_writer(writer.clone()).boxed()]);
+            info!("{}", "z".repeat(200));
+            info!("fits");
+        }
+
+        let lines = writer.lines();
+        assert_eq!(
+            lines[0],
+            format!(
+                r#" INFO a long m{TRUNCATION_MARKER} body="abcde{TRUNCATION_MARKER} service="api""#
+            )
+        );
+        assert!(lines[1].len() < 120, "{}", lines[1]);
+        assert!(lines[1].ends_with(TRUNCATION_MARKER), "{}", lines[1]);
+        assert_eq!(lines[2], r#" INFO fits service="api""#);
+        assert!(truncated_events() >= before + 2);
+    }
+
+    #[test]
+    fn newline_policy_is_applied() {
+        let render = |policy| {
+            let writer = TestWriter::default();
+            let (layer, _) = compact_text(&TestNewlines(policy));
+            let _unused = set_default(vec![layer.with_writer(writer.clone()).boxed()]);
+            info!(value = ?MultiLine, "line one\nline two\r\x00");
+            span!(Level::INFO, "request", query = %"select\n1\x1b[2J")
+                .in_scope(|| info!("in span"));
+            writer.output()
+        };
+
+        assert_eq!(
+            render(NewlinePolicy::Verbatim),
+            concat!(
+                " INFO line one\nline two\r\x00 value=first\nsecond\x1b[31m host=\"web\\n01\"\n",
+                " INFO request: in span host=\"web\\n01\" query=select\n1\x1b[2J\n"
+            )
+        );
+        assert_eq!(
+            render(NewlinePolicy::Escape),
+            concat!(
+                " INFO line one\\nline two\\r\\x00 value=first\\nsecond\\x1b[31m host=\"web\\n01\"\n",
+                " INFO request: in span host=\"web\\n01\" query=select\\n1\\x1b[2J\n"
+            )
+        );
+        assert_eq!(
+            render(NewlinePolicy::Indent),
+            concat!(
+                " INFO line one\n    line two\\r\\x00 value=first\n    second\\x1b[31m host=\"web\\n01\"\n",
+                " INFO request: in span host=\"web\\n01\" query=select\n    1\\x1b[2J\n"
+            )
+        );
+    }
+
+    #[test]
+    fn span_fields_keep_the_field_name_styles() {
+        let state = EventState {
+            ansi: true,
+            escapes: Escapes {
+                sanitize: false,
+                newlines: NewlinePolicy::Escape,
+            },
+            limits: Limits::default().event(),
+        };
+        assert_eq!(
+            state
+                .span_fields("\x1b[3mquery\x1b[0m\x1b[2m=\x1b[0mselect\n1\x1b[2J\x1b")
+                .to_string(),
+            "\x1b[3mquery\x1b[0m\x1b[2m=\x1b[0mselect\\n1\\x1b[2J\\x1b"
+        );
+    }
+
+    #[test]
+    fn span_and_trace_ids_are_rendered() {
+        let writer = TestWriter::default();
+        let (layer, _) = compact_text(&TestIds);
+        let _unused = set_default(vec![layer.with_writer(writer.clone()).boxed()]);
+        info!("outside");
+        let root = span!(Level::INFO, "root");
+        root.in_scope(|| {
+            info!("in root");
+            span!(Level::INFO, "child").in_scope(|| info!("in child"));
+        });
+        span!(Level::INFO, "other").in_scope(|| info!("in other"));
+
+        let lines = writer.lines();
+        assert_eq!(lines[0], " INFO outside");
+        let ids = |line: &str| {
+            let (_, ids) = line.split_once(" span_id=").unwrap();
+            let (span_id, trace_id) = ids.split_once(" trace_id=").unwrap();
+            assert_eq!(span_id.len(), 16, "{line}");
+            assert_eq!(trace_id.len(), 32, "{line}");
+            (span_id.to_string(), trace_id.to_string())
+        };
+        let (root_span, root_trace) = ids(&lines[1]);
+        let (child_span, child_trace) = ids(&lines[2]);
+        let (_, other_trace) = ids(&lines[3]);
+        assert_eq!(root_span, format!("{:016x}", root.id().unwrap().into_u64()));
+        assert_ne!(root_span, child_span);
+        assert_eq!(root_trace, child_trace);
+        assert_ne!(root_trace, other_trace);
+    }
+
+    #[test]
+    fn targets_are_abbreviated() {
+        let target = "my_service::handlers::billing::invoices::pdf";
+        assert_eq!(TargetAbbreviation::Full.apply(target), target);
+        assert_eq!(
+            TargetAbbreviation::Abbreviate(2).apply(target),
+            "m.h.b.invoices::pdf"
+        );
+        assert_eq!(
+            TargetAbbreviation::Abbreviate(0).apply(target),
+            "m.h.b.i.pdf"
+        );
+        assert_eq!(
+            TargetAbbreviation::KeepLast(2).apply(target),
+            "invoices::pdf"
+        );
+        assert_eq!(TargetAbbreviation::KeepLast(9).apply(target), target);
+        assert_eq!(
+            TargetAbbreviation::MaxWidth(30).apply(target),
+            "m.h.billing::invoices::pdf"
+        );
+        assert_eq!(TargetAbbreviation::MaxWidth(5).apply(target), "m.h.b.i.pdf");
+        assert_eq!(TargetAbbreviation::MaxWidth(50).apply(target), target);
+        assert_eq!(TargetAbbreviation::KeepLast(1).apply("single"), "single");
+
+        let writer = TestWriter::default();
+        let (layer, _) = compact_text(&TestAbbreviated);
+        let _unused = set_default(vec![layer.with_writer(writer.clone()).boxed()]);
+        info!("abbreviated");
+        assert_eq!(writer.lines(), vec![" INFO t.f.t.test: abbreviated"]);
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+
+/// Collects the fields of an event or span into a JSON object, keeping native types where possible.
+#[derive(Debug, Default)]
+pub(crate) struct JsonVisitor {
+    pub(crate) values: Map<String, Value>,
+}
+
+impl JsonVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        let name = field.name();
+        let name = name.strip_prefix("r#").unwrap_or(name);
+        let _old = self.values.insert(name.to_string(), value);
+    }
+
+    /// Remove the `message` field, rendering non-string values with their JSON representation.
+    #[cfg(any(feature = "delimited", feature = "json"))]
+    pub(crate) fn take_message(&mut self) -> Option<String> {
+        self.values.remove("message").map(|message| match message {
+            Value::String(message) => message,
+            other => other.to_string(),
+        })
+    }
+}
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::from(format!("{value:?}")));
+    }
+}
+
+/// Parse the formatted fields stored on a span back into a JSON object.
+///
+/// Spans formatted with [`JsonFields`](tracing_subscriber::fmt::format::JsonFields) parse directly,
+/// anything else is kept as a single `fields` string.
+#[cfg(feature = "json")]
+pub(crate) fn span_fields(formatted: &str) -> Map<String, Value> {
+    if formatted.is_empty() {
+        return Map::new();
+    }
+    if let Ok(Value::Object(fields)) = serde_json::from_str(formatted) {
+        fields
+    } else {
+        let mut fields = Map::new();
+        let _old = fields.insert("fields".to_string(), Value::from(formatted));
+        fields
+    }
+}
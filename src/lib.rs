@@ -24,11 +24,13 @@
 //! [tracing-subscriber]: https://docs.rs/tracing-subscriber/latest/tracing_subscriber/
 //! # Example
 //! ```rust
+//! # use std::env;
 //! # use anyhow::Result;
-//! # use std::fs::File;
 //! # use tracing::{info, Level, span};
 //! # use tracing_subscriber::{Layer, fmt::format::FmtSpan};
-//! # use tracing_subscriber_init::{TracingConfig, full, full_filtered, set_default};
+//! # use tracing_subscriber_init::{
+//! #     FileOutput, Rotation, RotationInterval, TracingConfig, full_filtered, full_text, set_default,
+//! # };
 //! #
 //! # pub fn main() -> Result<()> {
 //! #[derive(Clone, Debug, Default)]
@@ -87,8 +89,18 @@
 //!         3
 //!     }
 //!
-//!     fn with_ansi(&self) -> bool {
-//!         false
+//!     // Write to trace.log in the temporary directory, starting a new file every day and keeping
+//!     // a week of files
+//!     fn file_output(&self) -> Option<FileOutput> {
+//!         Some(FileOutput {
+//!             directory: env::temp_dir(),
+//!             rotation: Rotation {
+//!                 interval: Some(RotationInterval::Daily),
+//!                 ..Rotation::default()
+//!             },
+//!             max_files: Some(7),
+//!             ..FileOutput::default()
+//!         })
 //!     }
 //! }
 //!
@@ -101,12 +113,12 @@
 //! // and verbose values from the configuration
 //! let layer = full_filtered(&tracing_config);
 //!
-//! // Setup a second full format layer to write to a file.  Use the non-filtered
-//! // version when you wish to modify items such as the writer, or the time format.
+//! // Setup a second full format layer to write to the configured file.  The `_text` constructors
+//! // write to the configured output and apply the text settings of the configuration.  Use the
+//! // non-filtered version when you wish to modify items such as the time format.
 //! // You can also chose to ignore the generated level filter and apply your own.
-//! let file = File::create("trace.log")?;
-//! let (file_layer, level_filter) = full(&tracing_file_config);
-//! let file_layer = file_layer.with_writer(file).with_filter(level_filter);
+//! let (file_layer, level_filter) = full_text(&tracing_file_config);
+//! let file_layer = file_layer.with_filter(level_filter);
 //!
 //! // Create a Registry, add the layers, and set this subscriber as the default
 //! // for this scope
@@ -344,28 +356,165 @@
 )]
 #![cfg_attr(all(docsrs, nightly), feature(doc_cfg))]
 
+mod color;
 mod config;
 mod format;
 mod initialize;
+mod limits;
+mod timer;
 mod utils;
+mod writer;
 
+pub use self::color::ColorMode;
+pub use self::color::Theme;
 pub use self::config::Config as TracingConfig;
+#[cfg(feature = "bunyan")]
+pub use self::format::bunyan::Bunyan;
+#[cfg(feature = "bunyan")]
+pub use self::format::bunyan::bunyan;
+#[cfg(feature = "bunyan")]
+pub use self::format::bunyan::filtered as bunyan_filtered;
+#[cfg(feature = "cef")]
+pub use self::format::cef::Cef;
+#[cfg(feature = "cef")]
+pub use self::format::cef::cef;
+#[cfg(feature = "cef")]
+pub use self::format::cef::filtered as cef_filtered;
 pub use self::format::compact::compact;
+pub use self::format::compact::compact_text;
 pub use self::format::compact::filtered as compact_filtered;
+pub use self::format::compact::text_filtered as compact_text_filtered;
+#[cfg(feature = "delimited")]
+pub use self::format::delimited::Column;
+#[cfg(feature = "delimited")]
+pub use self::format::delimited::Delimited;
+#[cfg(feature = "delimited")]
+pub use self::format::delimited::Delimiter;
+#[cfg(feature = "delimited")]
+pub use self::format::delimited::delimited;
+#[cfg(feature = "delimited")]
+pub use self::format::delimited::filtered as delimited_filtered;
 pub use self::format::full::filtered as full_filtered;
 pub use self::format::full::full;
+pub use self::format::full::full_text;
+pub use self::format::full::text_filtered as full_text_filtered;
+#[cfg(all(feature = "journald", target_os = "linux"))]
+pub use self::format::journald::Journald;
+#[cfg(all(feature = "journald", target_os = "linux"))]
+pub use self::format::journald::filtered as journald_filtered;
+#[cfg(all(feature = "journald", target_os = "linux"))]
+pub use self::format::journald::journald;
+#[cfg(feature = "json")]
+pub use self::format::json::JsonFormat;
+#[cfg(feature = "json")]
+pub use self::format::json::JsonKeys;
 #[cfg(feature = "json")]
 pub use self::format::json::filtered as json_filtered;
 #[cfg(feature = "json")]
+pub use self::format::json::format_filtered as json_format_filtered;
+#[cfg(feature = "json")]
 pub use self::format::json::json;
+#[cfg(feature = "json")]
+pub use self::format::json::json_format;
+#[cfg(feature = "msgpack")]
+pub use self::format::msgpack::MsgPack;
+#[cfg(feature = "msgpack")]
+pub use self::format::msgpack::MsgPackReader;
+#[cfg(feature = "msgpack")]
+pub use self::format::msgpack::MsgPackRecord;
+#[cfg(feature = "msgpack")]
+pub use self::format::msgpack::MsgPackSpan;
+#[cfg(feature = "msgpack")]
+pub use self::format::msgpack::filtered as msgpack_filtered;
+#[cfg(feature = "msgpack")]
+pub use self::format::msgpack::msgpack;
 pub use self::format::pretty::filtered as pretty_filtered;
 pub use self::format::pretty::pretty;
+pub use self::format::pretty::pretty_text;
+pub use self::format::pretty::text_filtered as pretty_text_filtered;
+pub use self::format::recorder::filtered as flight_recorder_filtered;
+pub use self::format::recorder::flight_recorder;
+pub use self::format::route::Route;
+pub use self::format::route::RouteFormat;
+pub use self::format::route::routing;
+#[cfg(all(feature = "syslog", unix))]
+pub use self::format::syslog::Facility;
+#[cfg(all(feature = "syslog", unix))]
+pub use self::format::syslog::Syslog;
+#[cfg(all(feature = "syslog", unix))]
+pub use self::format::syslog::SyslogFormat;
+#[cfg(all(feature = "syslog", unix))]
+pub use self::format::syslog::SyslogWriter;
+#[cfg(all(feature = "syslog", unix))]
+pub use self::format::syslog::filtered as syslog_filtered;
+#[cfg(all(feature = "syslog", unix))]
+pub use self::format::syslog::syslog;
+#[cfg(feature = "template")]
+pub use self::format::template::Template;
+#[cfg(feature = "template")]
+pub use self::format::template::TemplateFormat;
+#[cfg(feature = "template")]
+pub use self::format::template::filtered as template_filtered;
+#[cfg(feature = "template")]
+pub use self::format::template::template;
+pub use self::format::text::NewlinePolicy;
+pub use self::format::text::TargetAbbreviation;
+pub use self::format::text::TextFormat;
 pub use self::initialize::init;
 pub use self::initialize::set_default;
 pub use self::initialize::try_init;
+pub use self::limits::Limits;
+pub use self::limits::TRUNCATION_MARKER;
+pub use self::limits::truncated_events;
+#[cfg(feature = "tstime")]
+pub use self::timer::TimeFormat;
+pub use self::timer::Timer;
+pub use self::timer::TimerConfig;
 pub use self::utils::TestAll;
 pub use self::utils::get_effective_level;
+pub use self::writer::compress::Compression;
+pub use self::writer::error::set_error_handler;
+pub use self::writer::file::FileOutput;
+pub use self::writer::file::RollingFile;
+pub use self::writer::file::Rotation;
+pub use self::writer::file::RotationInterval;
+pub use self::writer::file::reopen_log_files;
+#[cfg(feature = "http")]
+pub use self::writer::http::HttpFormat;
+#[cfg(feature = "http")]
+pub use self::writer::http::HttpOutput;
+#[cfg(feature = "http")]
+pub use self::writer::http::HttpWriter;
+#[cfg(feature = "http")]
+pub use self::writer::http::flush_http;
+#[cfg(feature = "http")]
+pub use self::writer::http::http_dropped_events;
+pub use self::writer::network::Framing;
+pub use self::writer::network::NetworkOutput;
+pub use self::writer::network::NetworkWriter;
+pub use self::writer::network::Protocol;
+pub use self::writer::network::network_dropped_events;
+pub use self::writer::non_blocking::BackpressurePolicy;
+pub use self::writer::non_blocking::NonBlocking;
+pub use self::writer::non_blocking::dropped_events;
+pub use self::writer::non_blocking::flush_non_blocking;
+pub use self::writer::output::Output;
+pub use self::writer::output::OutputWriter;
+pub use self::writer::recorder::FlightRecorder;
+pub use self::writer::recorder::FlightRecorderWriter;
+pub use self::writer::recorder::dump_flight_recorders;
+pub use self::writer::split::AnsiWriter;
+pub use self::writer::split::SplitWriter;
+pub use self::writer::tee::Tee;
+pub use self::writer::tee::TeeWriter;
 
+#[doc(no_inline)]
+pub use nu_ansi_term::Color;
+#[doc(no_inline)]
+pub use nu_ansi_term::Style;
+#[cfg(feature = "tstime")]
+#[doc(no_inline)]
+pub use time::UtcOffset;
 #[cfg(feature = "time")]
 #[doc(no_inline)]
 pub use time::format_description::well_known::Iso8601;
@@ -0,0 +1,383 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    borrow::Cow,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(any(feature = "delimited", feature = "json", feature = "msgpack"))]
+use serde_json::{Map, Value};
+
+/// The marker appended to truncated values and events.
+pub const TRUNCATION_MARKER: &str = "...[truncated]";
+
+/// The name of the field added to structured records that were truncated to fit the maximum event size.
+#[cfg(any(
+    feature = "delimited",
+    feature = "json",
+    feature = "msgpack",
+    all(feature = "journald", target_os = "linux")
+))]
+pub(crate) const TRUNCATED_FIELD: &str = "truncated";
+
+static TRUNCATED_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of events truncated by any formatter since the process started.
+#[must_use]
+pub fn truncated_events() -> u64 {
+    TRUNCATED_EVENTS.load(Ordering::Relaxed)
+}
+
+/// Size limits applied to every event, see [`limits`](crate::TracingConfig::limits).
+///
+/// Truncated values end with the [`TRUNCATION_MARKER`], and every event that was truncated in any way is
+/// counted by [`truncated_events`].  Lengths are in bytes, truncation never splits a character.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Limits {
+    /// The maximum length of the event message
+    pub max_message_len: Option<usize>,
+    /// The maximum length of any other field value
+    pub max_field_len: Option<usize>,
+    /// The maximum number of fields, not counting the message.  Further fields are dropped
+    pub max_fields: Option<usize>,
+    /// The maximum size of the encoded event.  Text formats cut the event short, structured formats drop
+    /// fields, then the span fields and static fields, shorten the message and add a `truncated` field to stay
+    /// valid
+    pub max_event_size: Option<usize>,
+}
+
+impl Limits {
+    /// Start tracking the limits of a single event.
+    pub(crate) fn event(&self) -> EventLimits {
+        EventLimits {
+            limits: *self,
+            fields: 0,
+            truncated: false,
+        }
+    }
+
+    /// Cut the encoded text of an event short, appending the marker and the given line ending.
+    ///
+    /// Returns whether or not the text was truncated.
+    pub(crate) fn truncate_event(
+        &self,
+        event: &mut String,
+        line_ending: &str,
+        reset: &str,
+    ) -> bool {
+        self.cut_event(event, line_ending, reset, None)
+    }
+
+    /// Cut the encoded text of an event short like [`truncate_event`](Self::truncate_event), without splitting
+    /// the two character escape sequences started by the given escape character.
+    #[cfg(feature = "cef")]
+    pub(crate) fn truncate_escaped_event(
+        &self,
+        event: &mut String,
+        line_ending: &str,
+        escape: char,
+    ) -> bool {
+        self.cut_event(event, line_ending, "", Some(escape))
+    }
+
+    fn cut_event(
+        &self,
+        event: &mut String,
+        line_ending: &str,
+        reset: &str,
+        escape: Option<char>,
+    ) -> bool {
+        let Some(max) = self.max_event_size else {
+            return false;
+        };
+        if event.len() <= max {
+            return false;
+        }
+        let suffix_len = reset.len() + TRUNCATION_MARKER.len() + line_ending.len();
+        let mut cut = floor_char_boundary(event, max.saturating_sub(suffix_len));
+        if let Some(escape) = escape {
+            // an odd run of escape characters ends with the first half of a sequence
+            let run = event[..cut]
+                .chars()
+                .rev()
+                .take_while(|&ch| ch == escape)
+                .count();
+            if run % 2 == 1 {
+                cut -= escape.len_utf8();
+            }
+        }
+        // an ANSI sequence cut short would swallow the marker and garble the terminal
+        if let Some(start) = event[..cut].rfind('\x1b') {
+            let rest = &event.as_bytes()[start + 1..cut];
+            if rest.is_empty()
+                || (rest[0] == b'[' && !rest[1..].iter().any(|b| (0x40..=0x7e).contains(b)))
+            {
+                cut = start;
+            }
+        }
+        event.truncate(cut);
+        event.push_str(reset);
+        event.push_str(TRUNCATION_MARKER);
+        event.push_str(line_ending);
+        true
+    }
+
+    /// Shrink a structured record until it fits the maximum event size.
+    ///
+    /// Fields other than the message are dropped from the last to the first, then the record context (i.e. the
+    /// span fields) is shed one part at a time by `shed` until it returns `false`, then the message is shortened.
+    /// A `truncated` field marks the record.  Returns whether or not the record was changed.
+    #[cfg(any(feature = "delimited", feature = "json", feature = "msgpack"))]
+    pub(crate) fn fit_record<C, F, D>(
+        &self,
+        fields: &mut Map<String, Value>,
+        context: &mut C,
+        encoded_len: F,
+        mut shed: D,
+    ) -> bool
+    where
+        F: Fn(&Map<String, Value>, &C) -> usize,
+        D: FnMut(&mut C) -> bool,
+    {
+        let Some(max) = self.max_event_size else {
+            return false;
+        };
+        if encoded_len(fields, context) <= max {
+            return false;
+        }
+        let _old = fields.insert(TRUNCATED_FIELD.to_string(), Value::Bool(true));
+        while encoded_len(fields, context) > max {
+            let Some(key) = fields
+                .keys()
+                .rev()
+                .find(|key| *key != "message" && *key != TRUNCATED_FIELD)
+                .cloned()
+            else {
+                break;
+            };
+            let _old = fields.shift_remove(&key);
+        }
+        while encoded_len(fields, context) > max && shed(context) {}
+        let len = encoded_len(fields, context);
+        if len > max
+            && let Some(Value::String(message)) = fields.get_mut("message")
+        {
+            shorten(message, len - max);
+        }
+        true
+    }
+
+    /// Shrink the fields of an event, as name and value pairs, until the encoded event fits the maximum event
+    /// size, see [`fit_record`](Self::fit_record).  The pairs are the whole event.
+    #[cfg(all(feature = "journald", target_os = "linux"))]
+    pub(crate) fn fit_pairs<F>(&self, fields: &mut Vec<(String, String)>, encoded_len: F) -> bool
+    where
+        F: Fn(&[(String, String)]) -> usize,
+    {
+        let Some(max) = self.max_event_size else {
+            return false;
+        };
+        if encoded_len(fields) <= max {
+            return false;
+        }
+        fields.push((TRUNCATED_FIELD.to_string(), "true".to_string()));
+        while encoded_len(fields) > max {
+            let Some(idx) = fields
+                .iter()
+                .rposition(|(name, _)| name != "message" && name != TRUNCATED_FIELD)
+            else {
+                break;
+            };
+            let _removed = fields.remove(idx);
+        }
+        let len = encoded_len(fields);
+        if len > max
+            && let Some((_, message)) = fields.iter_mut().find(|(name, _)| name == "message")
+        {
+            shorten(message, len - max);
+        }
+        true
+    }
+}
+
+/// Tracks the limits of a single event.
+#[derive(Debug)]
+pub(crate) struct EventLimits {
+    limits: Limits,
+    fields: usize,
+    truncated: bool,
+}
+
+impl EventLimits {
+    /// Whether or not another field (other than the message) may be written.
+    pub(crate) fn admit(&mut self, name: &str) -> bool {
+        if name == "message" {
+            return true;
+        }
+        self.fields += 1;
+        let admitted = self.limits.max_fields.is_none_or(|max| self.fields <= max);
+        self.truncated |= !admitted;
+        admitted
+    }
+
+    /// Truncate the value of the given field to its maximum length.
+    pub(crate) fn value<'a>(&mut self, name: &str, value: &'a str) -> Cow<'a, str> {
+        let max = if name == "message" {
+            self.limits.max_message_len
+        } else {
+            self.limits.max_field_len
+        };
+        match max {
+            Some(max) if value.len() > max => {
+                self.truncated = true;
+                let mut truncated = value[..floor_char_boundary(value, max)].to_string();
+                truncated.push_str(TRUNCATION_MARKER);
+                Cow::Owned(truncated)
+            }
+            _ => Cow::Borrowed(value),
+        }
+    }
+
+    /// Apply the message, field length and field count limits to a structured record.
+    #[cfg(any(feature = "delimited", feature = "json", feature = "msgpack"))]
+    pub(crate) fn apply(&mut self, fields: &mut Map<String, Value>) {
+        let mut dropped = Vec::new();
+        for (name, value) in fields.iter_mut() {
+            if !self.admit(name) {
+                dropped.push(name.clone());
+            } else if let Value::String(string) = value
+                && let Cow::Owned(truncated) = self.value(name, string)
+            {
+                *string = truncated;
+            }
+        }
+        for name in dropped {
+            let _old = fields.shift_remove(&name);
+        }
+    }
+
+    /// Mark the event as truncated by a later stage, i.e. the maximum event size.
+    pub(crate) fn mark(&mut self, truncated: bool) {
+        self.truncated |= truncated;
+    }
+
+    /// Count the event if it was truncated.
+    pub(crate) fn finish(self) {
+        if self.truncated {
+            let _count = TRUNCATED_EVENTS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Shorten a value by at least `excess` bytes, making room for the marker.
+#[cfg(any(
+    feature = "delimited",
+    feature = "json",
+    feature = "msgpack",
+    all(feature = "journald", target_os = "linux")
+))]
+fn shorten(value: &mut String, excess: usize) {
+    let keep = value.len().saturating_sub(excess + TRUNCATION_MARKER.len());
+    value.truncate(floor_char_boundary(value, keep));
+    value.push_str(TRUNCATION_MARKER);
+}
+
+/// The largest char boundary of the given string that is not greater than `index`.
+fn floor_char_boundary(value: &str, index: usize) -> usize {
+    if index >= value.len() {
+        value.len()
+    } else {
+        (0..=index)
+            .rev()
+            .find(|idx| value.is_char_boundary(*idx))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Limits, TRUNCATION_MARKER, floor_char_boundary, truncated_events};
+
+    #[test]
+    fn values_are_truncated_on_char_boundaries() {
+        assert_eq!(floor_char_boundary("héllo", 2), 1);
+        assert_eq!(floor_char_boundary("héllo", 3), 3);
+        assert_eq!(floor_char_boundary("héllo", 10), 6);
+
+        let limits = Limits {
+            max_message_len: Some(3),
+            max_field_len: Some(5),
+            max_fields: Some(1),
+            ..Limits::default()
+        };
+        let before = truncated_events();
+        let mut event = limits.event();
+        assert_eq!(
+            event.value("message", "hello"),
+            format!("hel{TRUNCATION_MARKER}")
+        );
+        assert_eq!(event.value("body", "hello"), "hello");
+        assert!(event.admit("message"));
+        assert!(event.admit("body"));
+        assert!(!event.admit("other"));
+        event.finish();
+        assert!(truncated_events() > before);
+    }
+
+    #[test]
+    fn events_are_cut_short() {
+        let limits = Limits {
+            max_event_size: Some(20),
+            ..Limits::default()
+        };
+        let mut event = "a".repeat(30);
+        event.push('\n');
+        assert!(limits.truncate_event(&mut event, "\n", ""));
+        assert_eq!(event.len(), 20);
+        assert!(event.ends_with(&format!("{TRUNCATION_MARKER}\n")));
+        let mut short = "short\n".to_string();
+        assert!(!limits.truncate_event(&mut short, "\n", ""));
+    }
+
+    #[test]
+    fn ansi_sequences_are_not_split() {
+        let limits = Limits {
+            max_event_size: Some(24),
+            ..Limits::default()
+        };
+        let reset = "\x1b[0m";
+        let mut event = format!("ab\x1b[33mcolored{}\n", "x".repeat(20));
+        assert!(limits.truncate_event(&mut event, "\n", reset));
+        assert_eq!(event, format!("ab{reset}{TRUNCATION_MARKER}\n"));
+        let mut event = format!("abcd\x1b[3m{}\n", "x".repeat(20));
+        assert!(limits.truncate_event(&mut event, "\n", reset));
+        assert_eq!(event, format!("abcd{reset}{TRUNCATION_MARKER}\n"));
+        let mut event = format!("a\x1b[3mb{}\n", "x".repeat(20));
+        assert!(limits.truncate_event(&mut event, "\n", reset));
+        assert_eq!(event, format!("a\x1b[3m{reset}{TRUNCATION_MARKER}\n"));
+    }
+
+    #[cfg(feature = "cef")]
+    #[test]
+    fn escape_sequences_are_not_split() {
+        let limits = Limits {
+            max_event_size: Some(20),
+            ..Limits::default()
+        };
+        let mut split = format!("a={}\\=b\n", "\\\\".repeat(10));
+        assert!(limits.truncate_escaped_event(&mut split, "\n", '\\'));
+        assert_eq!(split, format!("a=\\\\{TRUNCATION_MARKER}\n"));
+        let mut split = format!("ab=c{}\n", "\\=".repeat(10));
+        assert!(limits.truncate_escaped_event(&mut split, "\n", '\\'));
+        assert_eq!(split, format!("ab=c{TRUNCATION_MARKER}\n"));
+        let mut kept = format!("a=b{}\n", "\\=".repeat(10));
+        assert!(limits.truncate_escaped_event(&mut kept, "\n", '\\'));
+        assert_eq!(kept, format!("a=b\\={TRUNCATION_MARKER}\n"));
+    }
+}
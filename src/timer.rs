@@ -0,0 +1,200 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+#[cfg(feature = "tstime")]
+use std::fmt::Error as FmtError;
+use std::fmt::Result as FmtResult;
+
+#[cfg(feature = "tstime")]
+use anyhow::Result;
+#[cfg(feature = "tstime")]
+use time::{
+    OffsetDateTime, UtcOffset,
+    format_description::{
+        self, OwnedFormatItem,
+        well_known::{Iso8601, Rfc2822, Rfc3339},
+    },
+};
+use tracing_subscriber::fmt::{
+    format::Writer,
+    time::{FormatTime, SystemTime, Uptime},
+};
+
+/// The event timestamp configuration, see [`timer`](crate::TracingConfig::timer).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum TimerConfig {
+    /// No timestamp
+    None,
+    /// The system time, as an RFC 3339 timestamp with microsecond precision in UTC
+    #[default]
+    System,
+    /// The time elapsed since the format layer was created, in seconds
+    Uptime,
+    /// The UTC time in the given format
+    #[cfg(feature = "tstime")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tstime")))]
+    Utc(TimeFormat),
+    /// The time at the given fixed offset from UTC in the given format
+    #[cfg(feature = "tstime")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tstime")))]
+    Offset(UtcOffset, TimeFormat),
+    /// The local time in the given format.  The local offset is captured once, when the format layer is created,
+    /// falling back to UTC if it cannot be determined.
+    #[cfg(feature = "tstime")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tstime")))]
+    Local(TimeFormat),
+}
+
+/// The format of a [`TimerConfig`] timestamp.
+#[cfg(feature = "tstime")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tstime")))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum TimeFormat {
+    /// [RFC 3339](https://www.rfc-editor.org/rfc/rfc3339), i.e. `2023-06-01T12:30:00.123456789Z`
+    #[default]
+    Rfc3339,
+    /// ISO 8601, i.e. `2023-06-01T12:30:00.123456789Z`
+    Iso8601,
+    /// [RFC 2822](https://www.rfc-editor.org/rfc/rfc2822), i.e. `Thu, 01 Jun 2023 12:30:00 +0000`
+    Rfc2822,
+    /// A custom format, see [`TimeFormat::custom`]
+    Custom(OwnedFormatItem),
+}
+
+#[cfg(feature = "tstime")]
+impl TimeFormat {
+    /// Parse a custom [format description](https://time-rs.github.io/book/api/format-description.html),
+    /// i.e. `[year]-[month]-[day] [hour]:[minute]:[second]`.
+    ///
+    /// # Errors
+    /// * An error is returned if the format description is invalid.
+    ///
+    pub fn custom(format: &str) -> Result<Self> {
+        Ok(TimeFormat::Custom(format_description::parse_owned::<2>(
+            format,
+        )?))
+    }
+
+    fn format(&self, time: OffsetDateTime) -> Result<String, time::error::Format> {
+        match self {
+            TimeFormat::Rfc3339 => time.format(&Rfc3339),
+            TimeFormat::Iso8601 => time.format(&Iso8601::DEFAULT),
+            TimeFormat::Rfc2822 => time.format(&Rfc2822),
+            TimeFormat::Custom(format) => time.format(format),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum TimerKind {
+    None,
+    System,
+    Uptime(Uptime),
+    #[cfg(feature = "tstime")]
+    Offset(UtcOffset, TimeFormat),
+}
+
+/// A [`FormatTime`] implementation built from a [`TimerConfig`].
+#[derive(Clone, Debug)]
+pub struct Timer {
+    kind: TimerKind,
+}
+
+impl Timer {
+    /// Create a [`Timer`] from the given [`TimerConfig`].
+    ///
+    /// The uptime starts, and the local offset is captured, when the timer is created.
+    #[must_use]
+    pub fn new(config: &TimerConfig) -> Self {
+        let kind = match config {
+            TimerConfig::None => TimerKind::None,
+            TimerConfig::System => TimerKind::System,
+            TimerConfig::Uptime => TimerKind::Uptime(Uptime::default()),
+            #[cfg(feature = "tstime")]
+            TimerConfig::Utc(format) => TimerKind::Offset(UtcOffset::UTC, format.clone()),
+            #[cfg(feature = "tstime")]
+            TimerConfig::Offset(offset, format) => TimerKind::Offset(*offset, format.clone()),
+            #[cfg(feature = "tstime")]
+            TimerConfig::Local(format) => TimerKind::Offset(
+                UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC),
+                format.clone(),
+            ),
+        };
+        Self { kind }
+    }
+
+    /// Whether or not this timer writes a timestamp at all.
+    pub(crate) fn is_enabled(&self) -> bool {
+        !matches!(self.kind, TimerKind::None)
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new(&TimerConfig::default())
+    }
+}
+
+impl FormatTime for Timer {
+    fn format_time(&self, w: &mut Writer<'_>) -> FmtResult {
+        match &self.kind {
+            TimerKind::None => Ok(()),
+            TimerKind::System => SystemTime.format_time(w),
+            TimerKind::Uptime(uptime) => uptime.format_time(w),
+            #[cfg(feature = "tstime")]
+            TimerKind::Offset(offset, format) => {
+                let now = OffsetDateTime::now_utc().to_offset(*offset);
+                let timestamp = format.format(now).map_err(|_| FmtError)?;
+                w.write_str(&timestamp)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tracing_subscriber::fmt::{format::Writer, time::FormatTime};
+
+    use super::{Timer, TimerConfig};
+
+    fn timestamp(config: &TimerConfig) -> String {
+        let mut timestamp = String::new();
+        Timer::new(config)
+            .format_time(&mut Writer::new(&mut timestamp))
+            .expect("timestamp");
+        timestamp
+    }
+
+    #[test]
+    fn builtin_timers_work() {
+        assert!(timestamp(&TimerConfig::None).is_empty());
+        assert!(timestamp(&TimerConfig::System).ends_with('Z'));
+        assert!(timestamp(&TimerConfig::Uptime).ends_with('s'));
+        assert!(!Timer::new(&TimerConfig::None).is_enabled());
+        assert!(Timer::default().is_enabled());
+    }
+
+    #[cfg(feature = "tstime")]
+    #[test]
+    fn formatted_timers_work() {
+        use time::UtcOffset;
+
+        use super::TimeFormat;
+
+        let rfc3339 = timestamp(&TimerConfig::Utc(TimeFormat::Rfc3339));
+        assert!(rfc3339.ends_with('Z'), "{rfc3339}");
+        let rfc2822 = timestamp(&TimerConfig::Utc(TimeFormat::Rfc2822));
+        assert!(rfc2822.ends_with("+0000"), "{rfc2822}");
+        let offset = UtcOffset::from_hms(5, 30, 0).expect("offset");
+        let custom =
+            TimeFormat::custom("[offset_hour sign:mandatory]:[offset_minute]").expect("format");
+        assert_eq!(timestamp(&TimerConfig::Offset(offset, custom)), "+05:30");
+        assert!(!timestamp(&TimerConfig::Local(TimeFormat::Iso8601)).is_empty());
+        assert!(TimeFormat::custom("[not_a_component]").is_err());
+    }
+}
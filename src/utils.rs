@@ -43,6 +43,54 @@ pub fn get_effective_level(_quiet: u8, verbosity: u8) -> Level {
     }
 }
 
+/// The file stem of the current executable, used when no application name is configured.
+#[cfg(any(
+    feature = "bunyan",
+    feature = "cef",
+    feature = "http",
+    all(feature = "syslog", unix),
+    all(feature = "journald", target_os = "linux")
+))]
+pub(crate) fn default_app_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| {
+            exe.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The current UTC time as an ISO 8601 timestamp with millisecond precision, i.e. `2023-01-02T03:04:05.678Z`.
+#[cfg(any(feature = "bunyan", all(feature = "syslog", unix)))]
+pub(crate) fn utc_timestamp() -> String {
+    let now = time::OffsetDateTime::now_utc();
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        now.millisecond()
+    )
+}
+
+/// Map a [`Level`] to a syslog severity, as used by syslog and the systemd journal `PRIORITY` field.
+#[cfg(any(
+    all(feature = "syslog", unix),
+    all(feature = "journald", target_os = "linux")
+))]
+pub(crate) fn syslog_severity(level: Level) -> u8 {
+    match level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
 #[doc(hidden)]
 #[derive(Clone, Copy, Debug)]
 pub struct TestAll;
@@ -73,6 +121,10 @@ impl TracingConfig for TestAll {
         Some(FmtSpan::FULL)
     }
 
+    fn with_span_ids(&self) -> bool {
+        true
+    }
+
     #[cfg(feature = "json")]
     fn with_span_list(&self) -> bool {
         true
@@ -89,13 +141,23 @@ impl TracingConfig for TestAll {
     fn with_thread_names(&self) -> bool {
         true
     }
+
+    fn with_trace_id(&self) -> bool {
+        true
+    }
 }
 
 #[cfg(test)]
 pub(crate) mod test {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
     use super::get_effective_level;
     use crate::TracingConfig;
     use tracing::Level;
+    use tracing_subscriber::fmt::MakeWriter;
 
     #[cfg(debug_assertions)]
     #[test]
@@ -161,4 +223,45 @@ pub(crate) mod test {
             true
         }
     }
+
+    /// A [`MakeWriter`] that captures everything written to it for later inspection.
+    #[derive(Clone, Debug, Default)]
+    #[allow(dead_code)]
+    pub(crate) struct TestWriter {
+        buf: Arc<Mutex<Vec<u8>>>,
+    }
+
+    #[allow(dead_code)]
+    impl TestWriter {
+        pub(crate) fn bytes(&self) -> Vec<u8> {
+            self.buf.lock().unwrap().clone()
+        }
+
+        pub(crate) fn output(&self) -> String {
+            String::from_utf8_lossy(&self.buf.lock().unwrap()).into_owned()
+        }
+
+        pub(crate) fn lines(&self) -> Vec<String> {
+            self.output().lines().map(str::to_string).collect()
+        }
+    }
+
+    impl io::Write for TestWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buf.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for TestWriter {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
 }
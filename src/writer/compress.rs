@@ -0,0 +1,188 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+};
+
+use crate::writer::{
+    error::report_error,
+    file::{FileOutput, prune, remove_file, rotated},
+};
+
+/// The extensions of every compression format, enabled or not, so files compressed by another build are
+/// still recognised as rotated files.
+pub(crate) const EXTENSIONS: [&str; 2] = ["gz", "zst"];
+
+/// The extension of a compressed file while it is being written.
+const PARTIAL: &str = "partial";
+
+/// How a [`RollingFile`](crate::RollingFile) compresses its rotated files, see
+/// [`FileOutput::compression`](crate::FileOutput::compression).
+///
+/// Rotated files are compressed by a background thread to `<rotated file>.gz` with the `gzip` feature, or
+/// `<rotated file>.zst` with the `zstd` feature.  The
+/// compressed data is written to a `.partial` file that is renamed once complete, and only then is the
+/// rotated file removed.  If the process dies mid-compression, the next [`RollingFile`](crate::RollingFile)
+/// removes the `.partial` file and compresses the rotated file again.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Compression {
+    /// gzip, written as `.gz`
+    #[cfg(feature = "gzip")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "gzip")))]
+    Gzip,
+    /// Zstandard, written as `.zst`
+    #[cfg(feature = "zstd")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+    Zstd,
+}
+
+impl Compression {
+    fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "zst",
+        }
+    }
+
+    // without a compression feature there is nothing to encode with
+    #[cfg_attr(not(any(feature = "gzip", feature = "zstd")), allow(unused_variables))]
+    fn encode(self, input: &mut File, output: File) -> io::Result<File> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(output, flate2::Compression::default());
+                let _len = io::copy(input, &mut encoder)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(output, 0)?;
+                let _len = io::copy(input, &mut encoder)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// The given path with an extension appended, i.e. `app.log` to `app.log.gz`.
+pub(crate) fn appended(path: &Path, extension: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// The background thread compressing the files rotated by a [`RollingFile`](crate::RollingFile).
+///
+/// Dropping the compressor waits for the queued files to be compressed.
+#[derive(Debug)]
+pub(crate) struct Compressor {
+    sender: Option<Sender<PathBuf>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Compressor {
+    /// Start the background thread, which first recovers from any compression interrupted by a crash.
+    pub(crate) fn new(config: FileOutput, compression: Compression) -> Self {
+        let (sender, receiver) = mpsc::channel::<PathBuf>();
+        let worker = thread::Builder::new()
+            .name("tracing-compression".to_string())
+            .spawn(move || {
+                for path in recover(&config) {
+                    compress_and_prune(&config, compression, &path);
+                }
+                while let Ok(path) = receiver.recv() {
+                    compress_and_prune(&config, compression, &path);
+                }
+            })
+            .ok();
+        Self {
+            sender: worker.is_some().then_some(sender),
+            worker,
+        }
+    }
+
+    /// Queue a rotated file for compression.  A file that cannot be queued is left uncompressed.
+    pub(crate) fn compress(&self, path: PathBuf) {
+        if let Some(sender) = &self.sender {
+            let _res = sender.send(path);
+        }
+    }
+}
+
+impl Drop for Compressor {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            let _res = worker.join();
+        }
+    }
+}
+
+/// Clean up after compressions interrupted by a crash, returning the rotated files still to compress.
+fn recover(config: &FileOutput) -> Vec<PathBuf> {
+    let Ok(rotated) = rotated(config) else {
+        return Vec::new();
+    };
+    let mut pending = Vec::new();
+    for (_, path) in rotated {
+        let compressed = path
+            .extension()
+            .is_some_and(|extension| EXTENSIONS.iter().any(|ext| extension == *ext));
+        if compressed {
+            continue;
+        }
+        for extension in EXTENSIONS {
+            let _res = remove_file(&appended(&appended(&path, extension), PARTIAL));
+        }
+        // a complete compressed file is only renamed into place after it has been synced
+        if EXTENSIONS
+            .iter()
+            .any(|extension| appended(&path, extension).exists())
+        {
+            let _res = remove_file(&path);
+        } else {
+            pending.push(path);
+        }
+    }
+    pending
+}
+
+fn compress_and_prune(config: &FileOutput, compression: Compression, path: &Path) {
+    if let Err(e) = compress(compression, path) {
+        report_error(
+            anyhow::Error::new(e).context(format!("unable to compress {}", path.display())),
+        );
+    }
+    if let Err(e) = prune(config) {
+        report_error(anyhow::Error::new(e).context("unable to remove old log files"));
+    }
+}
+
+fn compress(compression: Compression, path: &Path) -> io::Result<()> {
+    let mut input = match File::open(path) {
+        // removed by pruning or already compressed
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        input => input?,
+    };
+    let target = appended(path, compression.extension());
+    let partial = appended(&target, PARTIAL);
+    let output = compression.encode(&mut input, File::create(&partial)?)?;
+    output.sync_all()?;
+    fs::rename(&partial, &target)?;
+    remove_file(path)
+}
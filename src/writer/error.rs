@@ -0,0 +1,77 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::sync::RwLock;
+
+use anyhow::Error;
+
+type ErrorHandler = Box<dyn Fn(&Error) + Send + Sync>;
+
+/// The handler of the writer errors that have no caller to return to, see [`set_error_handler`].
+static ERROR_HANDLER: RwLock<Option<ErrorHandler>> = RwLock::new(None);
+
+/// Set the handler of the writer errors that have no caller to return to, replacing any previous handler.
+///
+/// These are the errors of the background work and the setup of the writers: a rotated log file that cannot be
+/// compressed or pruned, a log file that cannot be reopened, a signal handler that cannot be installed, a
+/// flight recorder dump failing on a panic or a signal, and the events dropped by a non-blocking writer.  They are
+/// ignored until a handler is set.
+///
+/// The handler may run on a background thread, a signal handling thread or in a panic hook.  It should not emit
+/// events to a subscriber writing through the writer reporting the error.
+///
+/// # Example
+/// ```rust
+/// # use tracing_subscriber_init::set_error_handler;
+/// #
+/// set_error_handler(|e| eprintln!("logging: {e:#}"));
+/// ```
+pub fn set_error_handler<F>(handler: F)
+where
+    F: Fn(&Error) + Send + Sync + 'static,
+{
+    if let Ok(mut current) = ERROR_HANDLER.write() {
+        *current = Some(Box::new(handler));
+    }
+}
+
+/// Pass an error to the error handler, if one is set.
+pub(crate) fn report_error(error: Error) {
+    if let Ok(handler) = ERROR_HANDLER.read()
+        && let Some(handler) = handler.as_ref()
+    {
+        handler(&error);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::sync::{Mutex, Once};
+
+    use anyhow::anyhow;
+
+    use super::{report_error, set_error_handler};
+
+    /// Install a handler collecting the reported errors, shared by the tests of every reporter, and get the errors
+    /// reported so far.
+    pub(crate) fn reported_errors() -> Vec<String> {
+        static REPORTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            set_error_handler(|e| REPORTED.lock().unwrap().push(format!("{e:#}")));
+        });
+        REPORTED.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn errors_reach_the_handler() {
+        let _reported = reported_errors();
+        report_error(anyhow!("cause").context("unable to compress app.log"));
+        assert!(reported_errors().contains(&"unable to compress app.log: cause".to_string()));
+    }
+}
@@ -0,0 +1,932 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::writer::{
+    compress::{self, Compression, Compressor},
+    error::report_error,
+};
+
+/// The extension of the active and rotated log files.
+const EXTENSION: &str = "log";
+
+/// The source of the current time, replaced by a fake clock in the tests.
+pub(crate) type Clock = Arc<dyn Fn() -> SystemTime + Send + Sync>;
+
+/// The reopen requests of all live rolling files, with their reopen signal, for [`reopen_log_files`].
+static REOPEN_REQUESTS: Mutex<Vec<(Option<i32>, Weak<AtomicBool>)>> = Mutex::new(Vec::new());
+
+/// Reopen the active file of every live [`RollingFile`] before its next event, i.e. after an external tool
+/// such as logrotate moved it away.
+pub fn reopen_log_files() {
+    request_reopen(|_| true);
+}
+
+/// Reopen the active file of every live [`RollingFile`] reopened on the given signal.
+#[cfg(all(feature = "signal", unix))]
+pub(crate) fn reopen_on_signal(signal: i32) {
+    request_reopen(|reopen_signal| reopen_signal == Some(signal));
+}
+
+fn request_reopen<F>(filter: F)
+where
+    F: Fn(Option<i32>) -> bool,
+{
+    if let Ok(requests) = REOPEN_REQUESTS.lock() {
+        for (signal, request) in requests.iter() {
+            if filter(*signal)
+                && let Some(request) = request.upgrade()
+            {
+                request.store(true, Ordering::Release);
+            }
+        }
+    }
+}
+
+/// The rolling files of the output layers, so layers writing to the same path share one writer.
+static SHARED_FILES: Mutex<Vec<Weak<RollingFile>>> = Mutex::new(Vec::new());
+
+/// The live [`RollingFile`] writing to the active file of the given configuration, or a new one started with
+/// the given header.  Layers sharing a path share the writer, so its files are rotated and pruned once; the
+/// configuration and header of the first writer apply.
+pub(crate) fn shared_file(config: FileOutput, header: Option<Arc<[u8]>>) -> Arc<RollingFile> {
+    let path = active_path(&config);
+    let path = std::path::absolute(&path).unwrap_or(path);
+    let Ok(mut files) = SHARED_FILES.lock() else {
+        return Arc::new(RollingFile::new(config).with_optional_header(header));
+    };
+    files.retain(|file| file.strong_count() > 0);
+    if let Some(file) = files.iter().filter_map(Weak::upgrade).find(|file| {
+        let active = file.path();
+        std::path::absolute(&active).unwrap_or(active) == path
+    }) {
+        return file;
+    }
+    let file = Arc::new(RollingFile::new(config).with_optional_header(header));
+    files.push(Arc::downgrade(&file));
+    file
+}
+
+/// The path of the active log file of the given configuration.
+fn active_path(config: &FileOutput) -> PathBuf {
+    config
+        .directory
+        .join(format!("{}.{EXTENSION}", config.prefix))
+}
+
+/// The interval of time based rotation, see [`Rotation`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RotationInterval {
+    /// Start a new file every minute
+    Minutely,
+    /// Start a new file every hour
+    Hourly,
+    /// Start a new file every day, at midnight UTC
+    Daily,
+}
+
+impl RotationInterval {
+    fn seconds(self) -> u64 {
+        match self {
+            RotationInterval::Minutely => 60,
+            RotationInterval::Hourly => 60 * 60,
+            RotationInterval::Daily => 24 * 60 * 60,
+        }
+    }
+}
+
+/// When a [`RollingFile`] starts a new file.  Either, both or neither of the limits may be set.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Rotation {
+    /// Start a new file when the next event would grow the current file past this size, in bytes
+    pub max_size: Option<u64>,
+    /// Start a new file with every new interval
+    pub interval: Option<RotationInterval>,
+}
+
+/// The file output configuration, see [`file_output`](crate::TracingConfig::file_output).
+///
+/// Events are written to `<directory>/<prefix>.log`.  On rotation the file is renamed to
+/// `<prefix>.<started>.log`, where `<started>` is the UTC time the file was started as `YYYYMMDDTHHMMSS`
+/// (with a `-<n>` suffix if several files were started within the same second), and a new file is started.
+/// With a [`Compression`], rotated files are then compressed in the background to `<prefix>.<started>.log.gz`
+/// or `<prefix>.<started>.log.zst`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct FileOutput {
+    /// The directory of the log files, created if missing
+    pub directory: PathBuf,
+    /// The file name prefix of the log files
+    pub prefix: String,
+    /// When to start a new file
+    pub rotation: Rotation,
+    /// The maximum number of rotated files to keep, the oldest files are removed first.  All files are kept
+    /// if [`None`]
+    pub max_files: Option<usize>,
+    /// How to compress the rotated files.  Rotated files are kept as written if [`None`]
+    pub compression: Option<Compression>,
+    /// The signal the active file is reopened on, i.e. `SIGUSR1`, see [`reopen_log_files`]
+    #[cfg(all(feature = "signal", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "signal", unix))))]
+    pub reopen_signal: Option<i32>,
+}
+
+impl Default for FileOutput {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("."),
+            prefix: "trace".to_string(),
+            rotation: Rotation::default(),
+            max_files: None,
+            compression: None,
+            #[cfg(all(feature = "signal", unix))]
+            reopen_signal: None,
+        }
+    }
+}
+
+/// A [`MakeWriter`] that appends events to a log file, rotating it as configured by a [`FileOutput`].
+///
+/// The file is opened with the first event, so a missing or read-only directory shows up as a write error of
+/// the format layer.  Every event is written with a single write, and rotation only happens between events.
+/// Compression runs on a background thread, and dropping the [`RollingFile`] waits for it to finish.
+///
+/// For an external rotation tool, [`reopen`](RollingFile::reopen) the file once it has been moved away, or
+/// request it with [`reopen_log_files`] or the [`reopen_signal`](FileOutput::reopen_signal).  The new file is
+/// opened between two events, and the old one is kept if it cannot be opened.
+///
+/// With a [`header`](RollingFile::with_header), every file is started with the header, whether it is created,
+/// found empty or started by a rotation.
+pub struct RollingFile {
+    config: FileOutput,
+    clock: Clock,
+    header: Option<Arc<[u8]>>,
+    state: Mutex<State>,
+    compressor: Option<Compressor>,
+    /// Set to reopen the active file before the next event
+    reopen: Arc<AtomicBool>,
+}
+
+/// The active file, its size and the time it was started, in seconds since the Unix epoch.
+#[derive(Debug, Default)]
+struct State {
+    file: Option<File>,
+    size: u64,
+    started: u64,
+}
+
+impl fmt::Debug for RollingFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RollingFile")
+            .field("config", &self.config)
+            .field("header", &self.header)
+            .field("state", &self.state)
+            .field("compressor", &self.compressor)
+            .field("reopen", &self.reopen)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RollingFile {
+    /// Create a [`RollingFile`] writing as configured by the given [`FileOutput`].
+    #[must_use]
+    pub fn new(config: FileOutput) -> Self {
+        Self::with_clock(config, Arc::new(SystemTime::now))
+    }
+
+    pub(crate) fn with_clock(config: FileOutput, clock: Clock) -> Self {
+        let compressor = config
+            .compression
+            .map(|compression| Compressor::new(config.clone(), compression));
+        #[cfg(all(feature = "signal", unix))]
+        if let Some(signal) = config.reopen_signal
+            && let Err(e) = super::signal::on_signal(signal, super::signal::Action::ReopenLogFiles)
+        {
+            report_error(
+                anyhow::Error::new(e)
+                    .context(format!("unable to reopen log files on signal {signal}")),
+            );
+        }
+        #[cfg(all(feature = "signal", unix))]
+        let reopen_signal = config.reopen_signal;
+        #[cfg(not(all(feature = "signal", unix)))]
+        let reopen_signal = None;
+        let reopen = Arc::new(AtomicBool::new(false));
+        if let Ok(mut requests) = REOPEN_REQUESTS.lock() {
+            requests.retain(|(_, request)| request.strong_count() > 0);
+            requests.push((reopen_signal, Arc::downgrade(&reopen)));
+        }
+        Self {
+            config,
+            clock,
+            header: None,
+            state: Mutex::new(State::default()),
+            compressor,
+            reopen,
+        }
+    }
+
+    /// Start every empty log file with the given header, i.e. the header row of a delimited format.
+    #[must_use]
+    pub fn with_header(mut self, header: impl Into<Arc<[u8]>>) -> Self {
+        self.header = Some(header.into());
+        self
+    }
+
+    fn with_optional_header(mut self, header: Option<Arc<[u8]>>) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// The path of the active log file.
+    #[must_use]
+    pub fn path(&self) -> PathBuf {
+        active_path(&self.config)
+    }
+
+    /// The paths of the rotated log files, compressed or not, from the oldest to the newest.
+    ///
+    /// # Errors
+    /// * An error is returned if the log directory cannot be read.
+    ///
+    pub fn rotated_files(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(rotated(&self.config)?
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect())
+    }
+
+    /// Close the active file and open its path again, waiting for an event being written to finish.  The old
+    /// file is kept if the path cannot be opened.
+    ///
+    /// # Errors
+    /// * An error is returned if the path cannot be opened.
+    ///
+    pub fn reopen(&self) -> io::Result<()> {
+        let now = self.now();
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io::Error::other("log file lock poisoned"))?;
+        self.reopen.store(false, Ordering::Release);
+        if state.file.is_some() {
+            self.open(&mut state, now)?;
+        }
+        Ok(())
+    }
+
+    fn now(&self) -> u64 {
+        (self.clock)()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default()
+    }
+
+    fn open(&self, state: &mut State, now: u64) -> io::Result<()> {
+        fs::create_dir_all(&self.config.directory)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path())?;
+        let metadata = file.metadata()?;
+        state.size = metadata.len();
+        // an existing file was started no later than its last write
+        state.started = if state.size == 0 {
+            if let Some(header) = &self.header {
+                file.write_all(header)?;
+                state.size = u64::try_from(header.len()).unwrap_or(u64::MAX);
+            }
+            now
+        } else {
+            metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(now, |since| since.as_secs().min(now))
+        };
+        state.file = Some(file);
+        Ok(())
+    }
+
+    fn needs_rotation(&self, state: &State, now: u64, len: usize) -> bool {
+        let rotation = self.config.rotation;
+        let len = u64::try_from(len).unwrap_or(u64::MAX);
+        // a file holding no more than its header is never rotated
+        let header = self
+            .header
+            .as_ref()
+            .map_or(0, |header| u64::try_from(header.len()).unwrap_or(u64::MAX));
+        state.size > header
+            && (rotation
+                .max_size
+                .is_some_and(|max_size| state.size.saturating_add(len) > max_size)
+                || rotation.interval.is_some_and(|interval| {
+                    now / interval.seconds() != state.started / interval.seconds()
+                }))
+    }
+
+    /// Rename the active file, start a new one and remove the rotated files beyond the maximum.
+    fn rotate(&self, state: &mut State, now: u64) -> io::Result<()> {
+        if let Some(mut file) = state.file.take() {
+            file.flush()?;
+        }
+        let started = timestamp(state.started);
+        let mut sequence = 0;
+        let rotated = loop {
+            let name = if sequence == 0 {
+                format!("{}.{started}.{EXTENSION}", self.config.prefix)
+            } else {
+                format!("{}.{started}-{sequence}.{EXTENSION}", self.config.prefix)
+            };
+            let rotated = self.config.directory.join(name);
+            let taken = rotated.exists()
+                || compress::EXTENSIONS
+                    .iter()
+                    .any(|extension| compress::appended(&rotated, extension).exists());
+            if !taken {
+                break rotated;
+            }
+            sequence += 1;
+        };
+        fs::rename(self.path(), &rotated)?;
+        self.open(state, now)?;
+        if let Some(compressor) = &self.compressor {
+            compressor.compress(rotated);
+        }
+        if let Err(e) = prune(&self.config) {
+            report_error(anyhow::Error::new(e).context("unable to remove old log files"));
+        }
+        Ok(())
+    }
+
+    fn write_event(&self, buf: &[u8]) -> io::Result<usize> {
+        let now = self.now();
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io::Error::other("log file lock poisoned"))?;
+        if state.file.is_none() {
+            self.open(&mut state, now)?;
+        } else if self.reopen.swap(false, Ordering::AcqRel)
+            && let Err(e) = self.open(&mut state, now)
+        {
+            report_error(
+                anyhow::Error::new(e)
+                    .context(format!("unable to reopen {}", self.path().display())),
+            );
+        }
+        if self.needs_rotation(&state, now, buf.len()) {
+            self.rotate(&mut state, now)?;
+        }
+        let Some(file) = state.file.as_mut() else {
+            return Err(io::Error::other("log file not open"));
+        };
+        file.write_all(buf)?;
+        state.size += u64::try_from(buf.len()).unwrap_or(u64::MAX);
+        Ok(buf.len())
+    }
+}
+
+/// The sort key of a rotated file, the start time and the sequence number.
+type RotatedKey = (String, u32);
+
+/// The rotated files of the given file output with their sort keys, from the oldest to the newest.  A file being
+/// compressed is listed both as written and compressed.
+pub(crate) fn rotated(config: &FileOutput) -> io::Result<Vec<(RotatedKey, PathBuf)>> {
+    let mut rotated = Vec::new();
+    for entry in fs::read_dir(&config.directory)? {
+        let entry = entry?;
+        if let Some(key) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| rotated_key(&config.prefix, name))
+        {
+            rotated.push((key, entry.path()));
+        }
+    }
+    rotated.sort();
+    Ok(rotated)
+}
+
+/// The sort key of a rotated file name.
+fn rotated_key(prefix: &str, name: &str) -> Option<RotatedKey> {
+    let name = name.strip_prefix(prefix)?.strip_prefix('.')?;
+    let name = compress::EXTENSIONS
+        .iter()
+        .find_map(|extension| name.strip_suffix(extension)?.strip_suffix('.'))
+        .unwrap_or(name);
+    let started = name.strip_suffix(EXTENSION)?.strip_suffix('.')?;
+    let (started, sequence) = match started.split_once('-') {
+        Some((started, sequence)) => (started, sequence.parse().ok()?),
+        None => (started, 0),
+    };
+    let valid = started.len() == 15
+        && started.char_indices().all(|(idx, c)| {
+            if idx == 8 {
+                c == 'T'
+            } else {
+                c.is_ascii_digit()
+            }
+        });
+    valid.then(|| (started.to_string(), sequence))
+}
+
+/// Remove the oldest rotated files beyond the maximum number of files.
+pub(crate) fn prune(config: &FileOutput) -> io::Result<()> {
+    let Some(max_files) = config.max_files else {
+        return Ok(());
+    };
+    let rotated = rotated(config)?;
+    let mut keys: Vec<&RotatedKey> = rotated.iter().map(|(key, _)| key).collect();
+    keys.dedup();
+    let excess = keys.len().saturating_sub(max_files);
+    let Some(&newest_excess) = excess.checked_sub(1).and_then(|idx| keys.get(idx)) else {
+        return Ok(());
+    };
+    for (key, path) in &rotated {
+        if key <= newest_excess {
+            remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove a file, a file removed concurrently is not an error.
+pub(crate) fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Seconds since the Unix epoch as a UTC `YYYYMMDDTHHMMSS` timestamp.
+fn timestamp(secs: u64) -> String {
+    let days = secs / 86_400;
+    let secs = secs % 86_400;
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+impl Write for &RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_event(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io::Error::other("log file lock poisoned"))?;
+        state.file.as_mut().map_or(Ok(()), File::flush)
+    }
+}
+
+impl<'a> MakeWriter<'a> for RollingFile {
+    type Writer = &'a RollingFile;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        io::Write,
+        path::PathBuf,
+        sync::{
+            Arc,
+            atomic::{AtomicU64, Ordering},
+        },
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{
+        FileOutput, RollingFile, Rotation, RotationInterval, reopen_log_files, shared_file,
+        timestamp,
+    };
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    use crate::Compression;
+    use crate::writer::error::test::reported_errors;
+
+    /// A clock that only moves when told to.
+    #[derive(Clone, Debug, Default)]
+    struct FakeClock(Arc<AtomicU64>);
+
+    impl FakeClock {
+        fn at(secs: u64) -> Self {
+            Self(Arc::new(AtomicU64::new(secs)))
+        }
+
+        fn advance(&self, secs: u64) {
+            let _old = self.0.fetch_add(secs, Ordering::Relaxed);
+        }
+
+        fn file(&self, config: FileOutput) -> RollingFile {
+            let secs = Arc::clone(&self.0);
+            RollingFile::with_clock(
+                config,
+                Arc::new(move || UNIX_EPOCH + Duration::from_secs(secs.load(Ordering::Relaxed))),
+            )
+        }
+    }
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("tsi-{name}-{}", std::process::id()));
+        let _res = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn names(file: &RollingFile) -> Vec<String> {
+        file.rotated_files()
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(timestamp(0), "19700101T000000");
+        assert_eq!(timestamp(951_782_400), "20000229T000000");
+        assert_eq!(timestamp(1_700_000_000), "20231114T221320");
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let directory = directory("rotate-size");
+        let clock = FakeClock::at(1_700_000_000);
+        let file = clock.file(FileOutput {
+            directory: directory.clone(),
+            prefix: "app".to_string(),
+            rotation: Rotation {
+                max_size: Some(10),
+                interval: None,
+            },
+            max_files: None,
+            compression: None,
+            #[cfg(all(feature = "signal", unix))]
+            reopen_signal: None,
+        });
+        let mut writer = &file;
+        writer.write_all(b"123456\n").unwrap();
+        writer.write_all(b"abc\n").unwrap();
+        writer.write_all(b"oversized line\n").unwrap();
+
+        assert_eq!(
+            names(&file),
+            vec!["app.20231114T221320.log", "app.20231114T221320-1.log"]
+        );
+        let rotated = file.rotated_files().unwrap();
+        assert_eq!(fs::read_to_string(&rotated[0]).unwrap(), "123456\n");
+        assert_eq!(fs::read_to_string(&rotated[1]).unwrap(), "abc\n");
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "oversized line\n");
+        let _res = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn rotates_by_interval_and_prunes() {
+        let directory = directory("rotate-interval");
+        let clock = FakeClock::at(1_700_000_000);
+        let file = clock.file(FileOutput {
+            directory: directory.clone(),
+            prefix: "app".to_string(),
+            rotation: Rotation {
+                max_size: None,
+                interval: Some(RotationInterval::Hourly),
+            },
+            max_files: Some(2),
+            compression: None,
+            #[cfg(all(feature = "signal", unix))]
+            reopen_signal: None,
+        });
+        let mut writer = &file;
+        writer.write_all(b"first\n").unwrap();
+        clock.advance(60);
+        writer.write_all(b"same hour\n").unwrap();
+        assert!(names(&file).is_empty());
+
+        for line in ["second\n", "third\n", "fourth\n"] {
+            clock.advance(3600);
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        // the file started in the first hour was pruned
+        assert_eq!(
+            names(&file),
+            vec!["app.20231114T231420.log", "app.20231115T001420.log"]
+        );
+        let rotated = file.rotated_files().unwrap();
+        assert_eq!(fs::read_to_string(&rotated[0]).unwrap(), "second\n");
+        assert_eq!(fs::read_to_string(&rotated[1]).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "fourth\n");
+        let _res = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn other_files_are_ignored() {
+        let directory = directory("rotate-ignored");
+        fs::create_dir_all(&directory).unwrap();
+        for name in ["app.notes.log", "other.20231114T221320.log", "app.log.bak"] {
+            fs::write(directory.join(name), "keep").unwrap();
+        }
+        let clock = FakeClock::at(1_700_000_000);
+        let file = clock.file(FileOutput {
+            directory: directory.clone(),
+            prefix: "app".to_string(),
+            rotation: Rotation {
+                max_size: Some(1),
+                interval: None,
+            },
+            max_files: Some(0),
+            compression: None,
+            #[cfg(all(feature = "signal", unix))]
+            reopen_signal: None,
+        });
+        let mut writer = &file;
+        writer.write_all(b"one\n").unwrap();
+        writer.write_all(b"two\n").unwrap();
+        assert!(names(&file).is_empty());
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 4);
+        let _res = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn prune_errors_keep_the_event() {
+        let _errors = reported_errors();
+        let directory = directory("rotate-prune-error");
+        // a directory named like the oldest rotated file cannot be removed as a file
+        fs::create_dir_all(directory.join("app.20000101T000000.log").join("inner")).unwrap();
+        let clock = FakeClock::at(1_700_000_000);
+        let file = clock.file(FileOutput {
+            directory: directory.clone(),
+            prefix: "app".to_string(),
+            rotation: Rotation {
+                max_size: Some(4),
+                interval: None,
+            },
+            max_files: Some(1),
+            compression: None,
+            #[cfg(all(feature = "signal", unix))]
+            reopen_signal: None,
+        });
+        let mut writer = &file;
+        writer.write_all(b"one\n").unwrap();
+        writer.write_all(b"two\n").unwrap();
+
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "two\n");
+        assert!(
+            reported_errors()
+                .iter()
+                .any(|e| e.starts_with("unable to remove old log files"))
+        );
+        let _res = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn files_are_shared_per_path() {
+        let directory = directory("shared");
+        let config = FileOutput {
+            directory: directory.clone(),
+            prefix: "app".to_string(),
+            ..FileOutput::default()
+        };
+        let first = shared_file(config.clone(), None);
+        let second = shared_file(config.clone(), Some(Arc::from(&b"header\n"[..])));
+        let other = shared_file(
+            FileOutput {
+                prefix: "other".to_string(),
+                ..config.clone()
+            },
+            None,
+        );
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &other));
+
+        drop((first, second));
+        let file = shared_file(config, Some(Arc::from(&b"header\n"[..])));
+        let mut writer = &*file;
+        writer.write_all(b"event\n").unwrap();
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "header\nevent\n");
+        let _res = fs::remove_dir_all(&directory);
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gunzip(path: &std::path::Path) -> String {
+        use std::io::Read;
+
+        let mut contents = String::new();
+        let _len = flate2::read::GzDecoder::new(fs::File::open(path).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn compressed(directory: &std::path::Path, compression: Compression) -> FileOutput {
+        FileOutput {
+            directory: directory.to_path_buf(),
+            prefix: "app".to_string(),
+            rotation: Rotation {
+                max_size: Some(4),
+                interval: None,
+            },
+            max_files: Some(2),
+            compression: Some(compression),
+            #[cfg(all(feature = "signal", unix))]
+            reopen_signal: None,
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn compresses_rotated_files() {
+        let directory = directory("compress-gzip");
+        let clock = FakeClock::at(1_700_000_000);
+        let file = clock.file(compressed(&directory, Compression::Gzip));
+        let mut writer = &file;
+        for line in ["one\n", "two\n", "six\n", "ten\n"] {
+            clock.advance(1);
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        let path = file.path();
+        // dropping the file waits for the compression
+        drop(file);
+
+        let mut names: Vec<String> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        // the oldest file was pruned
+        assert_eq!(
+            names,
+            vec![
+                "app.20231114T221322.log.gz",
+                "app.20231114T221323.log.gz",
+                "app.log"
+            ]
+        );
+        assert_eq!(
+            gunzip(&directory.join("app.20231114T221322.log.gz")),
+            "two\n"
+        );
+        assert_eq!(fs::read_to_string(path).unwrap(), "ten\n");
+        let _res = fs::remove_dir_all(&directory);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn recovers_interrupted_compression() {
+        let directory = directory("compress-recover");
+        fs::create_dir_all(&directory).unwrap();
+        // died mid-compression
+        fs::write(directory.join("app.20231114T221320.log"), "first\n").unwrap();
+        fs::write(
+            directory.join("app.20231114T221320.log.gz.partial"),
+            "trunc",
+        )
+        .unwrap();
+        // died between the rename and the removal of the rotated file
+        fs::write(directory.join("app.20231114T231420.log"), "second\n").unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(
+            fs::File::create(directory.join("app.20231114T231420.log.gz")).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b"second\n").unwrap();
+        let _file = encoder.finish().unwrap();
+
+        let file = RollingFile::new(compressed(&directory, Compression::Gzip));
+        drop(file);
+
+        let mut names: Vec<String> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["app.20231114T221320.log.gz", "app.20231114T231420.log.gz"]
+        );
+        assert_eq!(
+            gunzip(&directory.join("app.20231114T221320.log.gz")),
+            "first\n"
+        );
+        assert_eq!(
+            gunzip(&directory.join("app.20231114T231420.log.gz")),
+            "second\n"
+        );
+        let _res = fs::remove_dir_all(&directory);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compresses_with_zstd() {
+        let directory = directory("compress-zstd");
+        let clock = FakeClock::at(1_700_000_000);
+        let file = clock.file(compressed(&directory, Compression::Zstd));
+        let mut writer = &file;
+        writer.write_all(b"one\n").unwrap();
+        writer.write_all(b"two\n").unwrap();
+        drop(file);
+
+        let rotated = directory.join("app.20231114T221320.log.zst");
+        let contents = zstd::decode_all(fs::File::open(rotated).unwrap()).unwrap();
+        assert_eq!(contents, b"one\n");
+        let _res = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn reopens_moved_files() {
+        let directory = directory("reopen");
+        let file = RollingFile::new(FileOutput {
+            directory: directory.clone(),
+            prefix: "app".to_string(),
+            ..FileOutput::default()
+        });
+        let mut writer = &file;
+        writer.write_all(b"one\n").unwrap();
+        let moved = directory.join("app.log.1");
+        fs::rename(file.path(), &moved).unwrap();
+        writer.write_all(b"two\n").unwrap();
+        reopen_log_files();
+        writer.write_all(b"three\n").unwrap();
+        fs::rename(file.path(), directory.join("app.log.2")).unwrap();
+        file.reopen().unwrap();
+        writer.write_all(b"four\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "one\ntwo\n");
+        assert_eq!(
+            fs::read_to_string(directory.join("app.log.2")).unwrap(),
+            "three\n"
+        );
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "four\n");
+        let _res = fs::remove_dir_all(&directory);
+    }
+
+    #[cfg(all(feature = "signal", unix))]
+    #[test]
+    fn reopens_on_signal() {
+        use std::thread;
+
+        use signal_hook::{consts::SIGUSR1, low_level::raise};
+
+        let directory = directory("reopen-signal");
+        let file = RollingFile::new(FileOutput {
+            directory: directory.clone(),
+            prefix: "app".to_string(),
+            reopen_signal: Some(SIGUSR1),
+            ..FileOutput::default()
+        });
+        let mut writer = &file;
+        writer.write_all(b"before\n").unwrap();
+        fs::rename(file.path(), directory.join("app.log.1")).unwrap();
+        raise(SIGUSR1).unwrap();
+        // the signal is handled on a background thread
+        for _ in 0..100 {
+            thread::sleep(Duration::from_millis(20));
+            writer.write_all(b"after\n").unwrap();
+            if file.path().exists() {
+                break;
+            }
+        }
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "after\n");
+        let _res = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,582 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Error, Result, anyhow, bail};
+use serde_json::{Map, Value, json};
+use tracing_subscriber::fmt::MakeWriter;
+use ureq::Agent;
+
+use crate::writer::error::report_error;
+
+/// The Loki stream label of the application name, used by default.
+pub(crate) const SERVICE_NAME_LABEL: &str = "service_name";
+
+static HTTP_DROPPED_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+/// The batches of all running HTTP writers, for [`flush_http`].
+static BATCHES: Mutex<Vec<Weak<Batches>>> = Mutex::new(Vec::new());
+
+/// The number of events dropped by any [`HttpWriter`] since the process started, because its buffer was full
+/// or its endpoint rejected them.  Rejected batches are also reported to the
+/// [`error handler`](crate::set_error_handler).
+#[must_use]
+pub fn http_dropped_events() -> u64 {
+    HTTP_DROPPED_EVENTS.load(Ordering::Relaxed)
+}
+
+/// Push every event buffered by an [`HttpWriter`], or give up when the timeout elapses.
+///
+/// HTTP writers push their buffered events when their format layer is dropped, but the layers of a global
+/// subscriber never are, so call this before the process exits.  Returns whether or not all events were pushed.
+#[must_use]
+pub fn flush_http(timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    let batches: Vec<Arc<Batches>> = BATCHES
+        .lock()
+        .map(|batches| batches.iter().filter_map(Weak::upgrade).collect())
+        .unwrap_or_default();
+    batches.iter().all(|batches| batches.flush(deadline))
+}
+
+/// The body of the requests of an [`HttpWriter`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum HttpFormat {
+    /// A Loki push API request, a single stream with the configured labels and the events as log lines
+    #[default]
+    Loki,
+    /// A JSON array of the events.  Events formatted as JSON are embedded as objects, others as strings
+    JsonArray,
+}
+
+/// The HTTP output configuration, see [`http_output`](crate::TracingConfig::http_output).
+///
+/// Events are buffered and pushed in batches, when a batch is full or its oldest event has waited for the batch
+/// interval.  A batch that fails with a connection error, a `429` or a `5xx` status is retried with an exponential
+/// backoff, and dropped after the last retry.  Dropped events, including those beyond a full buffer, are counted
+/// by [`http_dropped_events`], and every dropped batch is reported to the [`error handler`](crate::set_error_handler).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct HttpOutput {
+    /// The URL events are pushed to, i.e. `http://localhost:3100/loki/api/v1/push`
+    pub url: String,
+    /// The request body format
+    pub format: HttpFormat,
+    /// The names of the Loki stream labels, defaults to `service_name`.  The label values are taken from the
+    /// [`static_fields`](crate::TracingConfig::static_fields), and `service_name` falls back to the
+    /// [`app_name`](crate::TracingConfig::app_name).  Loki rejects a stream without labels, so a Loki output
+    /// needs at least one label, each with a value
+    pub labels: Vec<String>,
+    /// Extra request headers, i.e. `Authorization` or `X-Scope-OrgID`
+    pub headers: Vec<(String, String)>,
+    /// The maximum number of events per request
+    pub batch_size: usize,
+    /// The maximum time an event waits for its batch to fill up
+    pub batch_interval: Duration,
+    /// The maximum number of events waiting to be pushed
+    pub max_buffered: usize,
+    /// The number of times a failed request is retried
+    pub max_retries: u32,
+    /// The delay before the first retry, doubled after every failed retry
+    pub initial_backoff: Duration,
+    /// The maximum delay between retries
+    pub max_backoff: Duration,
+    /// The timeout of a request
+    pub timeout: Duration,
+}
+
+impl Default for HttpOutput {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:3100/loki/api/v1/push".to_string(),
+            format: HttpFormat::default(),
+            labels: vec![SERVICE_NAME_LABEL.to_string()],
+            headers: Vec::new(),
+            batch_size: 500,
+            batch_interval: Duration::from_secs(1),
+            max_buffered: 10_000,
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The events waiting to be pushed, shared by an [`HttpWriter`] and its background thread.
+#[derive(Debug)]
+struct Batches {
+    config: HttpOutput,
+    state: Mutex<BatchState>,
+    /// Signalled when an event is buffered, a flush is requested or the writer is closed
+    buffered: Condvar,
+    /// Signalled when a batch has been pushed or dropped
+    pushed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct BatchState {
+    /// The events, with the time they were written in nanoseconds since the Unix epoch
+    events: VecDeque<(u128, String)>,
+    /// The time the oldest buffered event was written
+    oldest: Option<Instant>,
+    /// Whether or not the background thread is pushing a batch it took from the buffer
+    pushing: bool,
+    /// The number of callers waiting for everything to be pushed
+    flushing: usize,
+    closed: bool,
+}
+
+impl Batches {
+    fn lock(&self) -> io::Result<MutexGuard<'_, BatchState>> {
+        self.state
+            .lock()
+            .map_err(|_| io::Error::other("http writer lock poisoned"))
+    }
+
+    fn push(&self, event: String) -> io::Result<()> {
+        let written = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos())
+            .unwrap_or_default();
+        let mut state = self.lock()?;
+        if state.events.len() >= self.config.max_buffered.max(1) {
+            let _count = HTTP_DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        state.events.push_back((written, event));
+        let _oldest = state.oldest.get_or_insert_with(Instant::now);
+        // the first event starts the batch interval
+        if state.events.len() == 1 || state.events.len() >= self.config.batch_size.max(1) {
+            self.buffered.notify_one();
+        }
+        Ok(())
+    }
+
+    fn close(&self) {
+        if let Ok(mut state) = self.lock() {
+            state.closed = true;
+        }
+        self.buffered.notify_all();
+    }
+
+    /// Wait until every buffered event has been pushed or dropped, or the deadline passes.
+    fn flush(&self, deadline: Instant) -> bool {
+        let Ok(mut state) = self.lock() else {
+            return false;
+        };
+        state.flushing += 1;
+        self.buffered.notify_all();
+        let mut flushed = true;
+        while !state.events.is_empty() || state.pushing {
+            let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                flushed = false;
+                break;
+            };
+            let Ok((next, _timeout)) = self.pushed.wait_timeout(state, timeout) else {
+                return false;
+            };
+            state = next;
+        }
+        state.flushing -= 1;
+        flushed
+    }
+
+    /// Take the next batch once it is full, has waited long enough or is flushed.
+    fn next_batch(&self) -> Option<Vec<(u128, String)>> {
+        let mut state = self.lock().ok()?;
+        state.pushing = false;
+        self.pushed.notify_all();
+        loop {
+            let full = state.events.len() >= self.config.batch_size.max(1);
+            let due = state
+                .oldest
+                .map(|oldest| self.config.batch_interval.saturating_sub(oldest.elapsed()));
+            if full || state.closed || state.flushing > 0 || due.is_some_and(|due| due.is_zero()) {
+                if state.events.is_empty() {
+                    if state.closed {
+                        return None;
+                    }
+                } else {
+                    let len = state.events.len().min(self.config.batch_size.max(1));
+                    let batch = state.events.drain(..len).collect();
+                    state.oldest = (!state.events.is_empty()).then(Instant::now);
+                    state.pushing = true;
+                    return Some(batch);
+                }
+            }
+            state = match due {
+                Some(due) if !due.is_zero() => self.buffered.wait_timeout(state, due).ok()?.0,
+                _ => self.buffered.wait(state).ok()?,
+            };
+        }
+    }
+
+    /// Push batches until the writer is closed and every event has been pushed.
+    fn run(&self, agent: &Agent, labels: &Map<String, Value>) {
+        while let Some(batch) = self.next_batch() {
+            let body = self.body(labels, &batch);
+            if let Err(e) = self.send(agent, &body) {
+                let dropped = u64::try_from(batch.len()).unwrap_or(u64::MAX);
+                let _count = HTTP_DROPPED_EVENTS.fetch_add(dropped, Ordering::Relaxed);
+                report_error(e.context(format!(
+                    "unable to push {dropped} events to {}",
+                    self.config.url
+                )));
+            }
+        }
+    }
+
+    fn body(&self, labels: &Map<String, Value>, batch: &[(u128, String)]) -> Value {
+        match self.config.format {
+            HttpFormat::Loki => {
+                let values: Vec<Value> = batch
+                    .iter()
+                    .map(|(written, event)| json!([written.to_string(), event]))
+                    .collect();
+                json!({ "streams": [{ "stream": labels, "values": values }] })
+            }
+            HttpFormat::JsonArray => batch
+                .iter()
+                .map(|(_, event)| {
+                    serde_json::from_str::<Value>(event)
+                        .ok()
+                        .filter(Value::is_object)
+                        .unwrap_or_else(|| Value::String(event.clone()))
+                })
+                .collect(),
+        }
+    }
+
+    /// Send a request, retrying as configured.  Returns the last failure if the endpoint did not accept it.
+    fn send(&self, agent: &Agent, body: &Value) -> Result<()> {
+        let body = body.to_string();
+        let mut backoff = self.config.initial_backoff;
+        let mut failure = anyhow!("no request was sent");
+        for attempt in 0..=self.config.max_retries {
+            if attempt > 0 {
+                thread::sleep(backoff);
+                backoff = backoff.saturating_mul(2).min(self.config.max_backoff);
+            }
+            let mut request = agent
+                .post(&self.config.url)
+                .header("Content-Type", "application/json");
+            for (name, value) in &self.config.headers {
+                request = request.header(name, value);
+            }
+            // connection errors are retried
+            match request.send(&body) {
+                Ok(response) => {
+                    let status = response.status();
+                    if status.is_success() {
+                        return Ok(());
+                    }
+                    failure = anyhow!("the endpoint answered {status}");
+                    if !status.is_server_error() && status.as_u16() != 429 {
+                        // a rejected request fails the same way again
+                        return Err(failure);
+                    }
+                }
+                Err(e) => failure = Error::new(e),
+            }
+        }
+        Err(failure)
+    }
+}
+
+/// A [`MakeWriter`] that pushes events to an HTTP endpoint in batches, as configured by an [`HttpOutput`].
+///
+/// Writes never block on the endpoint, the events are pushed by a background thread.  Dropping the writer
+/// pushes the buffered events, with retries, before returning.
+#[derive(Debug)]
+pub struct HttpWriter {
+    batches: Arc<Batches>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl HttpWriter {
+    /// Create an [`HttpWriter`] pushing as configured by the given [`HttpOutput`], taking the stream label values
+    /// from the given fields.
+    ///
+    /// # Errors
+    /// * An error is returned if a Loki output has no labels, or a label has no value in the given fields.
+    pub fn new(config: HttpOutput, fields: &[(String, String)]) -> Result<Self> {
+        let labels: Map<String, Value> = fields
+            .iter()
+            .filter(|(name, _)| config.labels.contains(name))
+            .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+            .collect();
+        if config.format == HttpFormat::Loki {
+            if config.labels.is_empty() {
+                bail!("a Loki output needs at least one stream label");
+            }
+            let missing: Vec<&str> = config
+                .labels
+                .iter()
+                .filter(|name| !labels.contains_key(*name))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                bail!("no value for the Loki stream labels {}", missing.join(", "));
+            }
+        }
+        let agent: Agent = Agent::config_builder()
+            .timeout_global(Some(config.timeout))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        let batches = Arc::new(Batches {
+            config,
+            state: Mutex::new(BatchState::default()),
+            buffered: Condvar::new(),
+            pushed: Condvar::new(),
+        });
+        if let Ok(mut all) = BATCHES.lock() {
+            all.retain(|batches| batches.strong_count() > 0);
+            all.push(Arc::downgrade(&batches));
+        }
+        let worker_batches = Arc::clone(&batches);
+        let worker = thread::Builder::new()
+            .name("tracing-http".to_string())
+            .spawn(move || worker_batches.run(&agent, &labels))
+            .ok();
+        Ok(Self { batches, worker })
+    }
+
+    fn write_event(&self, buf: &[u8]) -> io::Result<usize> {
+        if self.worker.is_none() {
+            return Err(io::Error::other("http writer thread not running"));
+        }
+        let event = String::from_utf8_lossy(buf);
+        let event = event.strip_suffix('\n').unwrap_or(&event);
+        self.batches.push(event.to_string())?;
+        Ok(buf.len())
+    }
+}
+
+impl Drop for HttpWriter {
+    fn drop(&mut self) {
+        self.batches.close();
+        if let Some(worker) = self.worker.take() {
+            let _res = worker.join();
+        }
+    }
+}
+
+impl Write for &HttpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_event(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for HttpWriter {
+    type Writer = &'a HttpWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use serde_json::{Value, json};
+
+    use super::{HttpFormat, HttpOutput, HttpWriter, flush_http, http_dropped_events};
+
+    use crate::writer::error::test::reported_errors;
+
+    /// A mock HTTP server answering requests with the given statuses, then `204`, and recording the bodies.
+    #[derive(Clone, Debug)]
+    struct MockServer {
+        url: String,
+        bodies: Arc<Mutex<Vec<Value>>>,
+    }
+
+    impl MockServer {
+        fn start(statuses: &[u16]) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/loki/api/v1/push", listener.local_addr().unwrap());
+            let bodies = Arc::new(Mutex::new(Vec::new()));
+            let recorded = Arc::clone(&bodies);
+            let statuses = statuses.to_vec();
+            let _handle = thread::spawn(move || {
+                let mut statuses = statuses.into_iter();
+                for stream in listener.incoming() {
+                    let mut reader = BufReader::new(stream.unwrap());
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        let _len = reader.read_line(&mut line).unwrap();
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':')
+                            && name.eq_ignore_ascii_case("content-length")
+                        {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    let status = statuses.next().unwrap_or(204);
+                    if status == 204 {
+                        recorded
+                            .lock()
+                            .unwrap()
+                            .push(serde_json::from_slice(&body).unwrap());
+                    }
+                    let response = format!(
+                        "HTTP/1.1 {status} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    );
+                    reader.get_mut().write_all(response.as_bytes()).unwrap();
+                }
+            });
+            Self { url, bodies }
+        }
+
+        fn bodies(&self) -> Vec<Value> {
+            self.bodies.lock().unwrap().clone()
+        }
+    }
+
+    fn output(server: &MockServer) -> HttpOutput {
+        HttpOutput {
+            url: server.url.clone(),
+            labels: vec!["service".to_string()],
+            batch_size: 2,
+            batch_interval: Duration::from_secs(60),
+            initial_backoff: Duration::from_millis(10),
+            ..HttpOutput::default()
+        }
+    }
+
+    fn static_fields() -> Vec<(String, String)> {
+        vec![
+            ("service".to_string(), "billing".to_string()),
+            ("version".to_string(), "1.2.3".to_string()),
+        ]
+    }
+
+    #[test]
+    fn pushes_loki_batches_and_flushes_on_drop() {
+        let server = MockServer::start(&[]);
+        let writer = HttpWriter::new(output(&server), &static_fields()).unwrap();
+        let mut sink = &writer;
+        for line in ["one\n", "two\n", "three\n"] {
+            sink.write_all(line.as_bytes()).unwrap();
+        }
+        drop(writer);
+
+        let bodies = server.bodies();
+        assert_eq!(bodies.len(), 2);
+        let stream = &bodies[0]["streams"][0];
+        // only the configured static fields are labels
+        assert_eq!(stream["stream"], json!({ "service": "billing" }));
+        assert_eq!(stream["values"][0][1], "one");
+        assert_eq!(stream["values"][1][1], "two");
+        assert!(
+            stream["values"][0][0]
+                .as_str()
+                .unwrap()
+                .parse::<u128>()
+                .is_ok()
+        );
+        assert_eq!(bodies[1]["streams"][0]["values"][0][1], "three");
+    }
+
+    #[test]
+    fn json_array_batches() {
+        let server = MockServer::start(&[]);
+        let writer = HttpWriter::new(
+            HttpOutput {
+                format: HttpFormat::JsonArray,
+                ..output(&server)
+            },
+            &[],
+        )
+        .unwrap();
+        let mut sink = &writer;
+        sink.write_all(b"{\"message\":\"json\"}\n").unwrap();
+        sink.write_all(b"plain text\n").unwrap();
+        assert!(flush_http(Duration::from_secs(5)));
+        assert_eq!(
+            server.bodies(),
+            vec![json!([{ "message": "json" }, "plain text"])]
+        );
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let server = MockServer::start(&[503, 429]);
+        let writer = HttpWriter::new(output(&server), &static_fields()).unwrap();
+        let mut sink = &writer;
+        sink.write_all(b"retried\n").unwrap();
+        drop(writer);
+        let bodies = server.bodies();
+        assert_eq!(bodies.len(), 1);
+        assert_eq!(bodies[0]["streams"][0]["values"][0][1], "retried");
+    }
+
+    #[test]
+    fn drops_rejected_batches() {
+        let _reported = reported_errors();
+        let server = MockServer::start(&[400]);
+        let writer = HttpWriter::new(output(&server), &static_fields()).unwrap();
+        let dropped = http_dropped_events();
+        let mut sink = &writer;
+        sink.write_all(b"rejected\n").unwrap();
+        sink.write_all(b"twice\n").unwrap();
+        drop(writer);
+        assert!(server.bodies().is_empty());
+        assert!(http_dropped_events() >= dropped + 2);
+        let rejected = format!(
+            "unable to push 2 events to {}: the endpoint answered 400",
+            server.url
+        );
+        assert!(
+            reported_errors()
+                .iter()
+                .any(|error| error.starts_with(&rejected))
+        );
+    }
+
+    #[test]
+    fn loki_outputs_need_labels() {
+        let empty = HttpOutput {
+            labels: Vec::new(),
+            ..HttpOutput::default()
+        };
+        assert!(HttpWriter::new(empty, &static_fields()).is_err());
+        // the default service_name label has no value
+        assert!(HttpWriter::new(HttpOutput::default(), &static_fields()).is_err());
+        let service_name = vec![("service_name".to_string(), "billing".to_string())];
+        assert!(HttpWriter::new(HttpOutput::default(), &service_name).is_ok());
+    }
+}
@@ -0,0 +1,21 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+pub(crate) mod compress;
+pub(crate) mod error;
+pub(crate) mod file;
+#[cfg(feature = "http")]
+pub(crate) mod http;
+pub(crate) mod network;
+pub(crate) mod non_blocking;
+pub(crate) mod output;
+pub(crate) mod recorder;
+#[cfg(all(feature = "signal", unix))]
+pub(crate) mod signal;
+pub(crate) mod split;
+pub(crate) mod tee;
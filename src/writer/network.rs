@@ -0,0 +1,473 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{
+        Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::writer::error::report_error;
+
+static NETWORK_DROPPED_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of events dropped by any [`NetworkWriter`] since the process started, because its buffer was full
+/// while it was disconnected or because the event could not be sent, i.e. a datagram too large for UDP.
+#[must_use]
+pub fn network_dropped_events() -> u64 {
+    NETWORK_DROPPED_EVENTS.load(Ordering::Relaxed)
+}
+
+/// The transport of a [`NetworkWriter`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Protocol {
+    /// A TCP stream
+    #[default]
+    Tcp,
+    /// A UDP datagram per event
+    Udp,
+}
+
+/// How a [`NetworkWriter`] delimits the events it sends.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Framing {
+    /// Every event ends with a newline, so events must not contain newlines, see
+    /// [`newline_policy`](crate::TracingConfig::newline_policy)
+    #[default]
+    Newline,
+    /// Every event, without its trailing newline, is preceded by its length in bytes and a space, as in RFC 6587
+    OctetCounted,
+}
+
+/// The network output configuration, see [`network_output`](crate::TracingConfig::network_output).
+///
+/// Events are sent to a collector as framed lines.  The connection is opened with the first event, and after
+/// a failure it is reopened with an exponential backoff.  While disconnected, events are kept in a buffer, and
+/// once the buffer is full the oldest events are dropped and counted by [`network_dropped_events`].  An event
+/// that fails to send while the connection is up, i.e. a datagram too large for UDP, is dropped, counted and
+/// reported to the [error handler](crate::set_error_handler).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct NetworkOutput {
+    /// The `host:port` address of the collector, resolved on every connection attempt
+    pub address: String,
+    /// The transport
+    pub protocol: Protocol,
+    /// How events are delimited
+    pub framing: Framing,
+    /// The maximum number of events buffered while disconnected
+    pub buffer_capacity: usize,
+    /// The delay before the first reconnection attempt, doubled after every failed attempt
+    pub initial_backoff: Duration,
+    /// The maximum delay between reconnection attempts
+    pub max_backoff: Duration,
+    /// The connection and write timeout of a TCP stream
+    pub timeout: Duration,
+}
+
+impl Default for NetworkOutput {
+    fn default() -> Self {
+        Self {
+            address: "localhost:5170".to_string(),
+            protocol: Protocol::default(),
+            framing: Framing::default(),
+            buffer_capacity: 1024,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A [`MakeWriter`] that sends events to a collector over TCP or UDP, as configured by a [`NetworkOutput`].
+///
+/// Writes never fail, an event that cannot be sent is buffered until the connection is back.  The collector is
+/// connected to without holding the buffer lock, so other threads only buffer their events while one connects.
+#[derive(Debug)]
+pub struct NetworkWriter {
+    config: NetworkOutput,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    connection: Option<Connection>,
+    /// The framed events waiting for a connection
+    buffer: VecDeque<Vec<u8>>,
+    /// Set while a thread connects without the lock
+    connecting: bool,
+    /// The delay after the next failed connection attempt
+    backoff: Duration,
+    /// The earliest time of the next connection attempt
+    next_attempt: Option<Instant>,
+}
+
+#[derive(Debug)]
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl Connection {
+    fn send(&mut self, event: &[u8]) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.write_all(event),
+            Connection::Udp(socket) => socket.send(event).map(|_| ()),
+        }
+    }
+}
+
+impl NetworkWriter {
+    /// Create a [`NetworkWriter`] sending as configured by the given [`NetworkOutput`].
+    #[must_use]
+    pub fn new(config: NetworkOutput) -> Self {
+        let backoff = config.initial_backoff;
+        Self {
+            config,
+            state: Mutex::new(State {
+                connection: None,
+                buffer: VecDeque::new(),
+                connecting: false,
+                backoff,
+                next_attempt: None,
+            }),
+        }
+    }
+
+    /// The number of events waiting for a connection.
+    #[must_use]
+    pub fn buffered(&self) -> usize {
+        self.lock()
+            .map(|state| state.buffer.len())
+            .unwrap_or_default()
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<'_, State>> {
+        self.state
+            .lock()
+            .map_err(|_| io::Error::other("network writer lock poisoned"))
+    }
+
+    fn frame(&self, event: &[u8]) -> Vec<u8> {
+        match self.config.framing {
+            Framing::Newline => {
+                let mut framed = event.to_vec();
+                if !framed.ends_with(b"\n") {
+                    framed.push(b'\n');
+                }
+                framed
+            }
+            Framing::OctetCounted => {
+                let event = event.strip_suffix(b"\n").unwrap_or(event);
+                let mut framed = format!("{} ", event.len()).into_bytes();
+                framed.extend_from_slice(event);
+                framed
+            }
+        }
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        let mut last_error = None;
+        for address in self.config.address.to_socket_addrs()? {
+            match self.connect_to(address) {
+                Ok(connection) => return Ok(connection),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "collector address not resolved")
+        }))
+    }
+
+    fn connect_to(&self, address: SocketAddr) -> io::Result<Connection> {
+        match self.config.protocol {
+            Protocol::Tcp => {
+                let stream = TcpStream::connect_timeout(&address, self.config.timeout)?;
+                stream.set_write_timeout(Some(self.config.timeout))?;
+                stream.set_nodelay(true)?;
+                Ok(Connection::Tcp(stream))
+            }
+            Protocol::Udp => {
+                let local: SocketAddr = if address.is_ipv4() {
+                    ([0, 0, 0, 0], 0).into()
+                } else {
+                    ([0_u16; 8], 0).into()
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(address)?;
+                Ok(Connection::Udp(socket))
+            }
+        }
+    }
+
+    /// Connect if disconnected and the backoff has elapsed.  The address is resolved and connected without the
+    /// lock, so other threads keep buffering their events meanwhile.
+    fn ensure_connected<'s>(
+        &'s self,
+        mut state: MutexGuard<'s, State>,
+    ) -> io::Result<MutexGuard<'s, State>> {
+        if state.connection.is_some()
+            || state.connecting
+            || state
+                .next_attempt
+                .is_some_and(|next_attempt| Instant::now() < next_attempt)
+        {
+            return Ok(state);
+        }
+        state.connecting = true;
+        drop(state);
+        let connection = self.connect();
+        let mut state = self.lock()?;
+        state.connecting = false;
+        if let Ok(connection) = connection {
+            state.connection = Some(connection);
+            state.backoff = self.config.initial_backoff;
+            state.next_attempt = None;
+        } else {
+            self.disconnected(&mut state);
+        }
+        Ok(state)
+    }
+
+    fn disconnected(&self, state: &mut State) {
+        state.connection = None;
+        state.next_attempt = Some(Instant::now() + state.backoff);
+        state.backoff = state.backoff.saturating_mul(2).min(self.config.max_backoff);
+    }
+
+    /// Send the buffered events until the connection fails.
+    fn send_buffered(&self, state: &mut State) {
+        while let Some(connection) = state.connection.as_mut() {
+            let Some(next) = state.buffer.pop_front() else {
+                break;
+            };
+            match connection.send(&next) {
+                Ok(()) => {}
+                Err(e) if is_connection_error(&e) => {
+                    // resent whole on the next connection
+                    state.buffer.push_front(next);
+                    self.disconnected(state);
+                }
+                Err(e) => {
+                    // the event itself cannot be sent, i.e. a datagram too large for UDP
+                    let _count = NETWORK_DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed);
+                    report_error(anyhow::Error::new(e).context(format!(
+                        "unable to send an event of {} bytes to {}",
+                        next.len(),
+                        self.config.address
+                    )));
+                }
+            }
+        }
+    }
+
+    fn write_event(&self, event: &[u8]) -> io::Result<usize> {
+        let framed = self.frame(event);
+        let mut state = self.lock()?;
+        state.buffer.push_back(framed);
+        let mut state = self.ensure_connected(state)?;
+        self.send_buffered(&mut state);
+        while state.buffer.len() > self.config.buffer_capacity.max(1) {
+            let _oldest = state.buffer.pop_front();
+            let _count = NETWORK_DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(event.len())
+    }
+}
+
+/// Whether or not a send failed because of the connection, rather than because of the event.
+fn is_connection_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::NetworkDown
+            | io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::WriteZero
+    )
+}
+
+impl Write for &NetworkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_event(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for NetworkWriter {
+    type Writer = &'a NetworkWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, UdpSocket},
+        time::Duration,
+    };
+
+    use super::{Framing, NetworkOutput, NetworkWriter, Protocol, network_dropped_events};
+    use crate::writer::error::test::reported_errors;
+
+    fn tcp(address: String) -> NetworkOutput {
+        NetworkOutput {
+            address,
+            initial_backoff: Duration::ZERO,
+            ..NetworkOutput::default()
+        }
+    }
+
+    #[test]
+    fn tcp_newline_framing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let writer = NetworkWriter::new(tcp(listener.local_addr().unwrap().to_string()));
+        let mut sink = &writer;
+        sink.write_all(b"first\n").unwrap();
+        sink.write_all(b"second").unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        let lines: Vec<String> = BufReader::new(stream)
+            .lines()
+            .take(2)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn tcp_octet_counted_framing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let writer = NetworkWriter::new(NetworkOutput {
+            framing: Framing::OctetCounted,
+            ..tcp(listener.local_addr().unwrap().to_string())
+        });
+        let mut sink = &writer;
+        sink.write_all(b"one\n").unwrap();
+        sink.write_all(b"line two\n").unwrap();
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = [0; 15];
+        stream.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"3 one8 line two");
+    }
+
+    #[test]
+    fn udp_datagrams() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let writer = NetworkWriter::new(NetworkOutput {
+            protocol: Protocol::Udp,
+            ..tcp(receiver.local_addr().unwrap().to_string())
+        });
+        let mut sink = &writer;
+        sink.write_all(b"datagram\n").unwrap();
+
+        let mut datagram = [0; 64];
+        let len = receiver.recv(&mut datagram).unwrap();
+        assert_eq!(&datagram[..len], b"datagram\n");
+    }
+
+    #[test]
+    fn unsendable_datagrams_are_dropped() {
+        let _errors = reported_errors();
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let writer = NetworkWriter::new(NetworkOutput {
+            protocol: Protocol::Udp,
+            ..tcp(receiver.local_addr().unwrap().to_string())
+        });
+        let mut sink = &writer;
+        let dropped = network_dropped_events();
+        sink.write_all(&vec![b'x'; 70_000]).unwrap();
+        sink.write_all(b"next\n").unwrap();
+
+        let mut datagram = [0; 64];
+        let len = receiver.recv(&mut datagram).unwrap();
+        assert_eq!(&datagram[..len], b"next\n");
+        assert_eq!(writer.buffered(), 0);
+        assert!(network_dropped_events() > dropped);
+        assert!(
+            reported_errors()
+                .iter()
+                .any(|e| e.starts_with("unable to send an event of 70001 bytes"))
+        );
+    }
+
+    #[test]
+    fn buffers_while_disconnected_and_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let writer = NetworkWriter::new(NetworkOutput {
+            buffer_capacity: 2,
+            ..tcp(address.to_string())
+        });
+        let mut sink = &writer;
+        let dropped = network_dropped_events();
+        for line in ["lost\n", "kept\n", "also kept\n"] {
+            sink.write_all(line.as_bytes()).unwrap();
+        }
+        assert_eq!(writer.buffered(), 2);
+        assert!(network_dropped_events() > dropped);
+
+        // the collector comes back
+        let listener = TcpListener::bind(address).unwrap();
+        sink.write_all(b"after\n").unwrap();
+        assert_eq!(writer.buffered(), 0);
+        let (stream, _) = listener.accept().unwrap();
+        let lines: Vec<String> = BufReader::new(stream)
+            .lines()
+            .take(3)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines, vec!["kept", "also kept", "after"]);
+    }
+
+    #[test]
+    fn backs_off_between_attempts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let writer = NetworkWriter::new(NetworkOutput {
+            initial_backoff: Duration::from_secs(60),
+            ..tcp(address.to_string())
+        });
+        let mut sink = &writer;
+        sink.write_all(b"refused\n").unwrap();
+
+        // the next attempt is not due yet
+        let _listener = TcpListener::bind(address).unwrap();
+        sink.write_all(b"buffered\n").unwrap();
+        assert_eq!(writer.buffered(), 2);
+    }
+}
@@ -0,0 +1,435 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use tracing::Level;
+
+use crate::writer::error::report_error;
+
+static DROPPED_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+/// The queues of all running non-blocking writers, for [`flush_non_blocking`].
+static QUEUES: Mutex<Vec<Weak<Queue>>> = Mutex::new(Vec::new());
+
+/// The number of events dropped by any non-blocking writer since the process started.
+#[must_use]
+pub fn dropped_events() -> u64 {
+    DROPPED_EVENTS.load(Ordering::Relaxed)
+}
+
+/// Wait until every event queued by a non-blocking writer has been written, or the timeout elapses.
+///
+/// Non-blocking writers are drained when their format layer is dropped, but the layers of a global
+/// subscriber never are, so call this before the process exits.  Returns whether or not all queues
+/// were drained.
+#[must_use]
+pub fn flush_non_blocking(timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    let queues: Vec<Arc<Queue>> = QUEUES
+        .lock()
+        .map(|queues| queues.iter().filter_map(Weak::upgrade).collect())
+        .unwrap_or_default();
+    queues.iter().all(|queue| queue.drain(deadline))
+}
+
+/// What a non-blocking writer does with an event when its queue is full.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum BackpressurePolicy {
+    /// Wait for room in the queue, blocking the thread that emitted the event
+    Block,
+    /// Drop the event
+    #[default]
+    DropNewest,
+    /// Drop the oldest queued event to make room for the event
+    DropOldest,
+}
+
+/// The non-blocking writer configuration, see [`non_blocking`](crate::TracingConfig::non_blocking).
+///
+/// Formatted events are queued and written by a background thread.  Every dropped event is counted by
+/// [`dropped_events`], and once the queue drains an `N events dropped` error is passed to the
+/// [`error handler`](crate::set_error_handler), at most once per warning interval.  The warning is not written to
+/// the wrapped writer, so it cannot break the framing of a structured format.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct NonBlocking {
+    /// The maximum number of queued events
+    pub capacity: usize,
+    /// What to do with an event when the queue is full
+    pub policy: BackpressurePolicy,
+    /// The minimum time between two dropped events warnings
+    pub warning_interval: Duration,
+}
+
+impl Default for NonBlocking {
+    fn default() -> Self {
+        Self {
+            capacity: 8192,
+            policy: BackpressurePolicy::default(),
+            warning_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The queue shared by a [`NonBlockingWriter`] and its background thread.
+#[derive(Debug)]
+struct Queue {
+    config: NonBlocking,
+    state: Mutex<QueueState>,
+    /// Signalled when an event is queued or the writer is closed
+    queued: Condvar,
+    /// Signalled when an event is taken from the queue, or the queue is drained
+    taken: Condvar,
+}
+
+/// The next piece of work of the background thread.
+enum Work {
+    /// A queued event, with its level if known
+    Event(Option<Level>, Vec<u8>),
+    /// The number of events dropped since the last warning
+    Dropped(u64),
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    /// The queued events, with their level if known
+    events: VecDeque<(Option<Level>, Vec<u8>)>,
+    /// Whether or not the background thread is writing an event it took from the queue
+    writing: bool,
+    /// The events dropped since the last warning
+    dropped: u64,
+    closed: bool,
+}
+
+impl Queue {
+    fn lock(&self) -> io::Result<MutexGuard<'_, QueueState>> {
+        self.state
+            .lock()
+            .map_err(|_| io::Error::other("non-blocking queue lock poisoned"))
+    }
+
+    fn push(&self, event: (Option<Level>, Vec<u8>)) -> io::Result<()> {
+        let mut state = self.lock()?;
+        if state.events.len() >= self.config.capacity.max(1) {
+            match self.config.policy {
+                BackpressurePolicy::Block => {
+                    while state.events.len() >= self.config.capacity.max(1) && !state.closed {
+                        state = self
+                            .taken
+                            .wait(state)
+                            .map_err(|_| io::Error::other("non-blocking queue lock poisoned"))?;
+                    }
+                }
+                BackpressurePolicy::DropNewest => {
+                    drop_event(&mut state);
+                    return Ok(());
+                }
+                BackpressurePolicy::DropOldest => {
+                    let _oldest = state.events.pop_front();
+                    drop_event(&mut state);
+                }
+            }
+        }
+        state.events.push_back(event);
+        self.queued.notify_one();
+        Ok(())
+    }
+
+    fn close(&self) {
+        if let Ok(mut state) = self.lock() {
+            state.closed = true;
+        }
+        self.queued.notify_all();
+        self.taken.notify_all();
+    }
+
+    /// Wait until the queue is empty and nothing is being written, or the deadline passes.
+    fn drain(&self, deadline: Instant) -> bool {
+        let Ok(mut state) = self.lock() else {
+            return false;
+        };
+        while !state.events.is_empty() || state.writing {
+            let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                return false;
+            };
+            let Ok((next, _timeout)) = self.taken.wait_timeout(state, timeout) else {
+                return false;
+            };
+            state = next;
+        }
+        true
+    }
+
+    /// Write the queued events until the queue is closed and drained.
+    fn run<F>(&self, write: &F)
+    where
+        F: Fn(Option<Level>, &[u8]) -> io::Result<()>,
+    {
+        let mut last_warning: Option<Instant> = None;
+        loop {
+            let Ok(mut state) = self.lock() else {
+                return;
+            };
+            state.writing = false;
+            self.taken.notify_all();
+            let work = loop {
+                if let Some((level, event)) = state.events.pop_front() {
+                    break Some(Work::Event(level, event));
+                }
+                // the queue has drained, so report any dropped events
+                if state.dropped > 0 {
+                    let interval = self.config.warning_interval;
+                    let wait = last_warning
+                        .and_then(|last| interval.checked_sub(last.elapsed()))
+                        .filter(|wait| !wait.is_zero());
+                    // a closing writer reports straight away
+                    if let Some(wait) = wait
+                        && !state.closed
+                    {
+                        let Ok((next, _timeout)) = self.queued.wait_timeout(state, wait) else {
+                            return;
+                        };
+                        state = next;
+                        continue;
+                    }
+                    let dropped = state.dropped;
+                    state.dropped = 0;
+                    last_warning = Some(Instant::now());
+                    break Some(Work::Dropped(dropped));
+                }
+                if state.closed {
+                    break None;
+                }
+                let Ok(next) = self.queued.wait(state) else {
+                    return;
+                };
+                state = next;
+            };
+            match work {
+                Some(Work::Event(level, event)) => {
+                    state.writing = true;
+                    drop(state);
+                    // write failures are ignored, as with the blocking writers
+                    let _res = write(level, &event);
+                }
+                Some(Work::Dropped(dropped)) => {
+                    // the handler may log, so the queue is not locked while it runs
+                    drop(state);
+                    report_error(anyhow!(dropped_warning(dropped)));
+                }
+                None => return,
+            }
+        }
+    }
+}
+
+fn drop_event(state: &mut QueueState) {
+    state.dropped += 1;
+    let _count = DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn dropped_warning(dropped: u64) -> String {
+    let events = if dropped == 1 { "event" } else { "events" };
+    format!("{dropped} {events} dropped by a non-blocking writer")
+}
+
+/// A writer that queues formatted events for a background thread, so the thread emitting an event never
+/// waits on the underlying writer, see [`NonBlocking`].
+///
+/// The queued events are written when the last clone of the writer is dropped.
+#[derive(Debug)]
+pub(crate) struct NonBlockingWriter {
+    queue: Arc<Queue>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl NonBlockingWriter {
+    /// Start the background thread writing the queued events, with their level if known, with the given function.
+    pub(crate) fn new<F>(config: NonBlocking, write: F) -> Self
+    where
+        F: Fn(Option<Level>, &[u8]) -> io::Result<()> + Send + 'static,
+    {
+        let queue = Arc::new(Queue {
+            config,
+            state: Mutex::new(QueueState::default()),
+            queued: Condvar::new(),
+            taken: Condvar::new(),
+        });
+        if let Ok(mut queues) = QUEUES.lock() {
+            queues.retain(|queue| queue.strong_count() > 0);
+            queues.push(Arc::downgrade(&queue));
+        }
+        let worker_queue = Arc::clone(&queue);
+        let worker = thread::Builder::new()
+            .name("tracing-non-blocking".to_string())
+            .spawn(move || worker_queue.run(&write))
+            .ok();
+        Self { queue, worker }
+    }
+
+    /// Queue a formatted event, with its level if known.
+    pub(crate) fn write_event(&self, level: Option<Level>, event: &[u8]) -> io::Result<usize> {
+        if self.worker.is_none() {
+            return Err(io::Error::other("non-blocking writer thread not running"));
+        }
+        self.queue.push((level, event.to_vec()))?;
+        Ok(event.len())
+    }
+}
+
+impl Drop for NonBlockingWriter {
+    fn drop(&mut self) {
+        self.queue.close();
+        if let Some(worker) = self.worker.take() {
+            let _res = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Condvar, Mutex},
+        time::Duration,
+    };
+
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use super::{
+        BackpressurePolicy, NonBlocking, NonBlockingWriter, dropped_events, dropped_warning,
+        flush_non_blocking,
+    };
+
+    use crate::{utils::test::TestWriter, writer::error::test::reported_errors};
+
+    /// A writer that blocks every write until it is opened, and signals the first write.
+    #[derive(Clone, Debug, Default)]
+    struct Gate {
+        state: Arc<(Mutex<(bool, bool)>, Condvar)>,
+        writer: TestWriter,
+    }
+
+    impl Gate {
+        fn wait_for_write(&self) {
+            let (lock, condvar) = &*self.state;
+            let _state = condvar
+                .wait_while(lock.lock().unwrap(), |(_, writing)| !*writing)
+                .unwrap();
+        }
+
+        fn open(&self) {
+            let (lock, condvar) = &*self.state;
+            lock.lock().unwrap().0 = true;
+            condvar.notify_all();
+        }
+    }
+
+    impl Write for Gate {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let (lock, condvar) = &*self.state;
+            let mut state = lock.lock().unwrap();
+            state.1 = true;
+            condvar.notify_all();
+            let _state = condvar.wait_while(state, |(open, _)| !*open).unwrap();
+            self.writer.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Gate {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn stalled(policy: BackpressurePolicy) -> String {
+        let gate = Gate::default();
+        let config = NonBlocking {
+            capacity: 2,
+            policy,
+            warning_interval: Duration::ZERO,
+        };
+        let sink = gate.clone();
+        let writer = NonBlockingWriter::new(config, move |level, event: &[u8]| {
+            assert!(level.is_none());
+            sink.make_writer().write_all(event)
+        });
+        let before = dropped_events();
+        let _len = writer.write_event(None, b"1\n").unwrap();
+        // the first event is taken from the queue, then the queue fills up
+        gate.wait_for_write();
+        for event in ["2\n", "3\n", "4\n"] {
+            let _len = writer.write_event(None, event.as_bytes()).unwrap();
+        }
+        assert!(dropped_events() > before);
+        gate.open();
+        drop(writer);
+        gate.writer.output()
+    }
+
+    #[test]
+    fn drop_policies_work() {
+        let _reported = reported_errors();
+        assert_eq!(stalled(BackpressurePolicy::DropNewest), "1\n2\n3\n");
+        assert_eq!(stalled(BackpressurePolicy::DropOldest), "1\n3\n4\n");
+        // the warning goes to the error handler rather than the wrapped writer
+        assert!(
+            reported_errors()
+                .iter()
+                .any(|error| error == "1 event dropped by a non-blocking writer")
+        );
+    }
+
+    #[test]
+    fn block_policy_keeps_every_event() {
+        let output = TestWriter::default();
+        let config = NonBlocking {
+            capacity: 1,
+            policy: BackpressurePolicy::Block,
+            ..NonBlocking::default()
+        };
+        let sink = output.clone();
+        let writer = NonBlockingWriter::new(config, move |_, event: &[u8]| {
+            sink.make_writer().write_all(event)
+        });
+        let events: Vec<String> = (0..100).map(|idx| format!("{idx}\n")).collect();
+        for event in &events {
+            let _len = writer.write_event(None, event.as_bytes()).unwrap();
+        }
+        assert!(flush_non_blocking(Duration::from_secs(5)));
+        assert_eq!(output.output(), events.concat());
+    }
+
+    #[test]
+    fn dropped_warning_is_readable() {
+        assert_eq!(
+            dropped_warning(1),
+            "1 event dropped by a non-blocking writer"
+        );
+        assert_eq!(
+            dropped_warning(12),
+            "12 events dropped by a non-blocking writer"
+        );
+    }
+}
@@ -0,0 +1,446 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    io::{self, IsTerminal, Stderr, Stdout, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::{MakeWriter, writer::EitherWriter};
+
+#[cfg(feature = "http")]
+use crate::writer::{
+    error::report_error,
+    http::{HttpWriter, SERVICE_NAME_LABEL},
+};
+use crate::{
+    TracingConfig,
+    writer::{
+        file::{RollingFile, shared_file},
+        network::NetworkWriter,
+        non_blocking::NonBlockingWriter,
+        split::{AnsiWriter, SplitWriter},
+    },
+};
+
+/// Standard error for the events at or above the [`stderr_level`](crate::TracingConfig::stderr_level), standard
+/// output for the others.
+type StdSplit = SplitWriter<fn() -> Stderr, fn() -> Stdout>;
+
+/// The [`MakeWriter`] of the format layers, configured from a [`TracingConfig`].
+///
+/// Writes to every configured destination: a [`RollingFile`] for a [`file_output`](crate::TracingConfig::file_output),
+/// a [`NetworkWriter`] for a [`network_output`](crate::TracingConfig::network_output) and, with the `http` feature,
+/// an [`HttpWriter`](crate::HttpWriter) for an [`http_output`](crate::TracingConfig::http_output).  Events go to
+/// standard output when no destination is configured, or with [`tee_stdout`](crate::TracingConfig::tee_stdout).
+/// Each event is formatted once, and a failing destination does not stop the writes to the others.  Writing to
+/// standard output, a [`stderr_level`](crate::TracingConfig::stderr_level) sends the more severe events to standard
+/// error, with colors decided for each stream.  With a [`non_blocking`](crate::TracingConfig::non_blocking)
+/// configuration, the events are queued and written by a background thread instead.
+#[derive(Clone, Debug)]
+pub struct Output {
+    kind: OutputKind,
+    header: Option<Arc<Header>>,
+}
+
+/// A header written before the first event of a stream or network destination.  Files write their header
+/// themselves, at the start of every file.
+#[derive(Debug)]
+struct Header {
+    row: Arc<[u8]>,
+    written: AtomicBool,
+}
+
+#[derive(Clone, Debug)]
+enum OutputKind {
+    Stdout,
+    Split(StdSplit),
+    File(Arc<RollingFile>),
+    Network(Arc<NetworkWriter>),
+    #[cfg(feature = "http")]
+    Http(Arc<HttpWriter>),
+    /// The outputs an event is written to, with whether or not they keep the colors
+    Tee(Vec<(Output, bool)>),
+    NonBlocking {
+        writer: Arc<NonBlockingWriter>,
+        terminal: bool,
+    },
+}
+
+impl Output {
+    /// Create the [`Output`] configured by the given [`TracingConfig`].
+    pub fn new<C>(config: &C) -> Self
+    where
+        C: TracingConfig + ?Sized,
+    {
+        Self::with_header(config, None)
+    }
+
+    /// Create the [`Output`] configured by the given [`TracingConfig`], starting every file and the other
+    /// destinations with the given header.
+    pub(crate) fn with_header<C>(config: &C, header: Option<Arc<[u8]>>) -> Self
+    where
+        C: TracingConfig + ?Sized,
+    {
+        let leaf = |kind| {
+            let header = match &kind {
+                OutputKind::File(_) => None,
+                _ => header.clone().map(|row| {
+                    Arc::new(Header {
+                        row,
+                        written: AtomicBool::new(false),
+                    })
+                }),
+            };
+            Self { kind, header }
+        };
+        let color = config.color();
+        let standard = match config.stderr_level() {
+            Some(level) => {
+                let split: StdSplit = SplitWriter::new(level, io::stderr, io::stdout);
+                let split =
+                    split.with_ansi(color.enabled(&io::stderr()), color.enabled(&io::stdout()));
+                OutputKind::Split(split)
+            }
+            None => OutputKind::Stdout,
+        };
+        let mut destinations = Vec::new();
+        if let Some(file_output) = config.file_output() {
+            destinations.push(OutputKind::File(shared_file(file_output, header.clone())));
+        }
+        if let Some(network_output) = config.network_output() {
+            destinations.push(OutputKind::Network(Arc::new(NetworkWriter::new(
+                network_output,
+            ))));
+        }
+        #[cfg(feature = "http")]
+        if let Some(http_output) = config.http_output() {
+            let mut fields = config.static_fields();
+            if !fields.iter().any(|(name, _)| name == SERVICE_NAME_LABEL) {
+                fields.push((SERVICE_NAME_LABEL.to_string(), config.app_name()));
+            }
+            // a rejected configuration leaves the events to the other destinations
+            match HttpWriter::new(http_output, &fields) {
+                Ok(writer) => destinations.push(OutputKind::Http(Arc::new(writer))),
+                Err(e) => report_error(e.context("unable to push events over HTTP")),
+            }
+        }
+        if destinations.is_empty() || config.tee_stdout() {
+            destinations.insert(0, standard);
+        }
+        let mut outputs: Vec<Self> = destinations.into_iter().map(leaf).collect();
+        let output = match outputs.pop() {
+            Some(output) if outputs.is_empty() => output,
+            last => {
+                let outputs = outputs
+                    .into_iter()
+                    .chain(last)
+                    .map(|output| {
+                        let ansi = color.enabled_for(&output);
+                        (output, ansi)
+                    })
+                    .collect();
+                Self {
+                    kind: OutputKind::Tee(outputs),
+                    header: None,
+                }
+            }
+        };
+        match config.non_blocking() {
+            Some(non_blocking) => {
+                let terminal = output.is_terminal();
+                let writer = NonBlockingWriter::new(non_blocking, move |level, event: &[u8]| {
+                    output.write_event(level, event)
+                });
+                let writer = Arc::new(writer);
+                let kind = OutputKind::NonBlocking { writer, terminal };
+                Self { kind, header: None }
+            }
+            None => output,
+        }
+    }
+
+    /// Whether or not this output is a terminal, for the [`color`](crate::TracingConfig::color) mode.
+    pub(crate) fn is_terminal(&self) -> bool {
+        match &self.kind {
+            OutputKind::Stdout => io::stdout().is_terminal(),
+            OutputKind::Split(_) => io::stdout().is_terminal() || io::stderr().is_terminal(),
+            OutputKind::File(_) | OutputKind::Network(_) => false,
+            #[cfg(feature = "http")]
+            OutputKind::Http(_) => false,
+            OutputKind::Tee(outputs) => outputs.iter().any(|(output, _)| output.is_terminal()),
+            OutputKind::NonBlocking { terminal, .. } => *terminal,
+        }
+    }
+
+    /// The writer of an event at the given level, or of output without event metadata.
+    fn writer_for_level(&self, level: Option<Level>) -> OutputWriter<'_> {
+        let kind = match &self.kind {
+            OutputKind::Stdout => WriterKind::Stdout(io::stdout()),
+            OutputKind::Split(split) => WriterKind::Split(split.writer_for_level(level)),
+            OutputKind::File(file) => WriterKind::File(file),
+            OutputKind::Network(network) => WriterKind::Network(network),
+            #[cfg(feature = "http")]
+            OutputKind::Http(http) => WriterKind::Http(http),
+            OutputKind::Tee(outputs) => WriterKind::Tee(
+                outputs
+                    .iter()
+                    .map(|(output, ansi)| AnsiWriter::new(output.writer_for_level(level), *ansi))
+                    .collect(),
+            ),
+            OutputKind::NonBlocking { writer, .. } => WriterKind::NonBlocking(writer, level),
+        };
+        OutputWriter {
+            kind,
+            header: self.header.as_deref(),
+        }
+    }
+
+    /// Write a formatted event, for the background thread of a non-blocking writer.
+    fn write_event(&self, level: Option<Level>, event: &[u8]) -> io::Result<()> {
+        let mut writer = self.writer_for_level(level);
+        writer.write_all(event)?;
+        writer.flush()
+    }
+}
+
+/// The [`Write`] implementation of an [`Output`].
+#[derive(Debug)]
+pub struct OutputWriter<'a> {
+    kind: WriterKind<'a>,
+    header: Option<&'a Header>,
+}
+
+impl OutputWriter<'_> {
+    /// Write the header before the first event.
+    fn write_header(&mut self) -> io::Result<()> {
+        match self.header.take() {
+            Some(header) if !header.written.swap(true, Ordering::AcqRel) => {
+                self.write_all(&header.row)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum WriterKind<'a> {
+    Stdout(Stdout),
+    Split(EitherWriter<AnsiWriter<Stderr>, AnsiWriter<Stdout>>),
+    File(&'a RollingFile),
+    Network(&'a NetworkWriter),
+    #[cfg(feature = "http")]
+    Http(&'a HttpWriter),
+    Tee(Vec<AnsiWriter<OutputWriter<'a>>>),
+    NonBlocking(&'a NonBlockingWriter, Option<Level>),
+}
+
+impl Write for OutputWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+        match &mut self.kind {
+            WriterKind::Stdout(stdout) => stdout.write(buf),
+            WriterKind::Split(split) => split.write(buf),
+            WriterKind::File(file) => file.write(buf),
+            WriterKind::Network(network) => network.write(buf),
+            #[cfg(feature = "http")]
+            WriterKind::Http(http) => http.write(buf),
+            // a partial write to one output cannot be retried without repeating it on the others
+            WriterKind::Tee(_) => self.write_all(buf).map(|()| buf.len()),
+            WriterKind::NonBlocking(writer, level) => writer.write_event(*level, buf),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.write_header()?;
+        match &mut self.kind {
+            WriterKind::Stdout(stdout) => stdout.write_all(buf),
+            WriterKind::Split(split) => split.write_all(buf),
+            WriterKind::File(file) => file.write_all(buf),
+            WriterKind::Network(network) => network.write_all(buf),
+            #[cfg(feature = "http")]
+            WriterKind::Http(http) => http.write_all(buf),
+            // a failing output does not stop the others, the first error is returned
+            WriterKind::Tee(writers) => writers
+                .iter_mut()
+                .map(|writer| writer.write_all(buf))
+                .fold(Ok(()), Result::and),
+            WriterKind::NonBlocking(writer, level) => writer.write_event(*level, buf).map(|_| ()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.kind {
+            WriterKind::Stdout(stdout) => stdout.flush(),
+            WriterKind::Split(split) => split.flush(),
+            WriterKind::File(file) => file.flush(),
+            WriterKind::Network(network) => network.flush(),
+            #[cfg(feature = "http")]
+            WriterKind::Http(http) => http.flush(),
+            WriterKind::Tee(writers) => writers
+                .iter_mut()
+                .map(Write::flush)
+                .fold(Ok(()), Result::and),
+            // the background thread flushes after every event
+            WriterKind::NonBlocking(..) => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for Output {
+    type Writer = OutputWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.writer_for_level(None)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        self.writer_for_level(Some(*meta.level()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, net::UdpSocket, path::PathBuf, time::Duration};
+
+    use tracing::info;
+    use tracing_subscriber::Layer;
+
+    use crate::{
+        FileOutput, NetworkOutput, NonBlocking, Protocol, TimerConfig, TracingConfig, compact_text,
+        flush_non_blocking, set_default,
+    };
+
+    #[derive(Clone, Debug, Default)]
+    struct TestFile {
+        directory: PathBuf,
+        non_blocking: bool,
+        tee_stdout: bool,
+        network: Option<String>,
+    }
+
+    impl TestFile {
+        fn new(name: &str) -> Self {
+            let directory = std::env::temp_dir().join(format!("tsi-{name}-{}", std::process::id()));
+            let _res = fs::remove_dir_all(&directory);
+            Self {
+                directory,
+                non_blocking: false,
+                tee_stdout: false,
+                network: None,
+            }
+        }
+
+        fn contents(&self) -> String {
+            fs::read_to_string(self.directory.join("app.log")).unwrap()
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _res = fs::remove_dir_all(&self.directory);
+        }
+    }
+
+    impl TracingConfig for TestFile {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn file_output(&self) -> Option<FileOutput> {
+            Some(FileOutput {
+                directory: self.directory.clone(),
+                prefix: "app".to_string(),
+                ..FileOutput::default()
+            })
+        }
+
+        fn network_output(&self) -> Option<NetworkOutput> {
+            self.network.clone().map(|address| NetworkOutput {
+                address,
+                protocol: Protocol::Udp,
+                ..NetworkOutput::default()
+            })
+        }
+
+        fn non_blocking(&self) -> Option<NonBlocking> {
+            self.non_blocking.then(NonBlocking::default)
+        }
+
+        fn tee_stdout(&self) -> bool {
+            self.tee_stdout
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+    }
+
+    #[test]
+    fn format_layers_write_to_the_file() {
+        let config = TestFile::new("output");
+        let (layer, _) = compact_text(&config);
+        {
+            let _unused = set_default(vec![layer.boxed()]);
+            info!("to the file");
+        }
+        // files are never terminals, so no colors are written
+        assert_eq!(config.contents(), " INFO to the file\n");
+    }
+
+    #[test]
+    fn non_blocking_layers_write_to_the_file() {
+        let mut config = TestFile::new("non-blocking");
+        config.non_blocking = true;
+        let (layer, _) = compact_text(&config);
+        let _unused = set_default(vec![layer.boxed()]);
+        info!("queued");
+        info!("in order");
+        assert!(flush_non_blocking(Duration::from_secs(5)));
+        assert_eq!(config.contents(), " INFO queued\n INFO in order\n");
+    }
+
+    #[test]
+    fn tee_layers_write_to_the_file() {
+        let mut config = TestFile::new("tee");
+        config.tee_stdout = true;
+        let (layer, _) = compact_text(&config);
+        {
+            let _unused = set_default(vec![layer.boxed()]);
+            info!("to both");
+        }
+        assert_eq!(config.contents(), " INFO to both\n");
+    }
+
+    #[test]
+    fn layers_write_to_every_destination() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut config = TestFile::new("destinations");
+        config.network = Some(receiver.local_addr().unwrap().to_string());
+        let (layer, _) = compact_text(&config);
+        {
+            let _unused = set_default(vec![layer.boxed()]);
+            info!("to both");
+        }
+        assert_eq!(config.contents(), " INFO to both\n");
+        let mut datagram = [0; 64];
+        let len = receiver.recv(&mut datagram).unwrap();
+        assert_eq!(&datagram[..len], b" INFO to both\n");
+    }
+}
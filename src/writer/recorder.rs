@@ -0,0 +1,345 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    io::{self, Write},
+    panic,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, Once, Weak},
+};
+
+use tracing::Level;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::writer::error::report_error;
+
+/// The rings of all live flight recorders, for [`dump_flight_recorders`] and the panic hook.
+static RECORDERS: Mutex<Vec<Weak<Ring>>> = Mutex::new(Vec::new());
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Dump the events of every live flight recorder to its destination, see [`FlightRecorder`].
+///
+/// # Errors
+/// * The first error writing a dump, once every recorder has been dumped.
+pub fn dump_flight_recorders() -> io::Result<()> {
+    live()
+        .iter()
+        .map(|ring| ring.dump())
+        .fold(Ok(()), Result::and)
+}
+
+/// The flight recorder configuration, see [`flight_recorder`](crate::TracingConfig::flight_recorder).
+///
+/// A flight recorder keeps the most recent formatted events, usually at a more verbose level than the other
+/// layers write, and dumps them on [`dump_flight_recorders`], on a signal, or when the process panics.  Every
+/// dump starts with a header line, and leaves the events in place.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct FlightRecorder {
+    /// The number of events kept, older events are discarded
+    pub capacity: usize,
+    /// The least severe level of the recorded events
+    pub level: Level,
+    /// The file the events are appended to when dumped, [`None`](std::option::Option::None) for standard error
+    pub dump_path: Option<PathBuf>,
+    /// Whether or not the events are dumped when any thread panics.  When set, creating the recorder chains a
+    /// process-wide panic hook, installed once with [`std::panic::set_hook`], that dumps the recorders set to
+    /// dump on panic before running the previous hook.  A hook set later replaces it unless it chains too.
+    /// This defaults to false
+    pub dump_on_panic: bool,
+    /// The signal the events are dumped on, i.e. `SIGUSR2`
+    #[cfg(all(feature = "signal", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "signal", unix))))]
+    pub dump_signal: Option<i32>,
+}
+
+impl Default for FlightRecorder {
+    fn default() -> Self {
+        Self {
+            capacity: 4096,
+            level: Level::TRACE,
+            dump_path: None,
+            dump_on_panic: false,
+            #[cfg(all(feature = "signal", unix))]
+            dump_signal: None,
+        }
+    }
+}
+
+/// The recorded events of a [`FlightRecorderWriter`].
+#[derive(Debug)]
+struct Ring {
+    config: FlightRecorder,
+    events: Mutex<VecDeque<Vec<u8>>>,
+}
+
+impl Ring {
+    fn lock(&self) -> io::Result<MutexGuard<'_, VecDeque<Vec<u8>>>> {
+        self.events
+            .lock()
+            .map_err(|_| io::Error::other("flight recorder lock poisoned"))
+    }
+
+    fn push(&self, event: &[u8]) -> io::Result<()> {
+        if self.config.capacity == 0 {
+            return Ok(());
+        }
+        let mut events = self.lock()?;
+        while events.len() >= self.config.capacity {
+            let _oldest = events.pop_front();
+        }
+        events.push_back(event.to_vec());
+        Ok(())
+    }
+
+    fn dump(&self) -> io::Result<()> {
+        let dump = {
+            let events = self.lock()?;
+            let noun = if events.len() == 1 { "event" } else { "events" };
+            let mut dump = format!(
+                "[tracing-subscriber-init] flight recorder dump, the last {} {noun}\n",
+                events.len()
+            )
+            .into_bytes();
+            for event in events.iter() {
+                dump.extend_from_slice(event);
+            }
+            dump
+        };
+        match &self.config.dump_path {
+            Some(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                file.write_all(&dump)?;
+                file.flush()
+            }
+            None => io::stderr().lock().write_all(&dump),
+        }
+    }
+}
+
+/// The live rings.
+fn live() -> Vec<Arc<Ring>> {
+    RECORDERS
+        .lock()
+        .map(|recorders| recorders.iter().filter_map(Weak::upgrade).collect())
+        .unwrap_or_default()
+}
+
+/// Dump the flight recorders dumped on the given signal.
+#[cfg(all(feature = "signal", unix))]
+pub(crate) fn dump_on_signal(signal: i32) {
+    for ring in live() {
+        if ring.config.dump_signal == Some(signal)
+            && let Err(e) = ring.dump()
+        {
+            report_error(anyhow::Error::new(e).context("unable to dump a flight recorder"));
+        }
+    }
+}
+
+/// Chain a panic hook dumping the flight recorders dumped on panic to the current hook, once.
+fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            for ring in live() {
+                if ring.config.dump_on_panic
+                    && let Err(e) = ring.dump()
+                {
+                    report_error(anyhow::Error::new(e).context("unable to dump a flight recorder"));
+                }
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// A [`MakeWriter`] keeping the most recent events in memory, see [`FlightRecorder`] and
+/// [`flight_recorder`](crate::flight_recorder).
+///
+/// Every write is recorded as one event, as the format layers write each event at once.
+#[derive(Clone, Debug)]
+pub struct FlightRecorderWriter {
+    ring: Arc<Ring>,
+}
+
+impl FlightRecorderWriter {
+    /// Create a [`FlightRecorderWriter`] with the given configuration, installing its panic hook and signal
+    /// handler as configured.
+    #[must_use]
+    pub fn new(config: FlightRecorder) -> Self {
+        if config.dump_on_panic {
+            install_panic_hook();
+        }
+        #[cfg(all(feature = "signal", unix))]
+        if let Some(signal) = config.dump_signal
+            && let Err(e) =
+                super::signal::on_signal(signal, super::signal::Action::DumpFlightRecorders)
+        {
+            report_error(anyhow::Error::new(e).context(format!(
+                "unable to dump a flight recorder on signal {signal}"
+            )));
+        }
+        let ring = Arc::new(Ring {
+            config,
+            events: Mutex::new(VecDeque::new()),
+        });
+        if let Ok(mut recorders) = RECORDERS.lock() {
+            recorders.retain(|recorder| recorder.strong_count() > 0);
+            recorders.push(Arc::downgrade(&ring));
+        }
+        Self { ring }
+    }
+
+    /// Dump the recorded events to the destination of this recorder.
+    ///
+    /// # Errors
+    /// * An error writing the dump.
+    pub fn dump(&self) -> io::Result<()> {
+        self.ring.dump()
+    }
+}
+
+impl Write for &FlightRecorderWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.ring.push(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for FlightRecorderWriter {
+    type Writer = &'a FlightRecorderWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, panic, path::PathBuf, process};
+
+    use tracing::{debug, info, trace};
+    use tracing_subscriber::Layer;
+
+    use super::{FlightRecorder, FlightRecorderWriter};
+    use crate::{TimerConfig, TracingConfig, compact_text, set_default};
+
+    #[derive(Clone, Copy, Debug)]
+    struct Plain;
+
+    impl TracingConfig for Plain {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            3
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+    }
+
+    fn dump_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("flight-{name}-{}.log", process::id()));
+        let _removed = fs::remove_file(&path);
+        path
+    }
+
+    fn record(config: FlightRecorder) -> FlightRecorderWriter {
+        let recorder = FlightRecorderWriter::new(config);
+        let (layer, _) = compact_text(&Plain);
+        let layer = layer.with_ansi(false).with_writer(recorder.clone());
+        let _unused = set_default(vec![layer.boxed()]);
+        for idx in 0..5 {
+            debug!(idx, "debug");
+        }
+        trace!("last");
+        recorder
+    }
+
+    #[test]
+    fn keeps_the_last_events() {
+        let path = dump_path("keeps");
+        let recorder = record(FlightRecorder {
+            capacity: 3,
+            dump_path: Some(path.clone()),
+            dump_on_panic: false,
+            ..FlightRecorder::default()
+        });
+        recorder.dump().unwrap();
+        let dump = fs::read_to_string(&path).unwrap();
+        let _removed = fs::remove_file(&path);
+        assert_eq!(
+            dump,
+            "[tracing-subscriber-init] flight recorder dump, the last 3 events\n\
+             DEBUG debug idx=3\n\
+             DEBUG debug idx=4\n\
+             TRACE last\n"
+        );
+    }
+
+    #[test]
+    fn dumps_on_panic() {
+        // the panic hook is opt-in
+        assert!(!FlightRecorder::default().dump_on_panic);
+        let path = dump_path("panic");
+        let recorder = record(FlightRecorder {
+            capacity: 10,
+            dump_path: Some(path.clone()),
+            dump_on_panic: true,
+            ..FlightRecorder::default()
+        });
+        let _panicked = panic::catch_unwind(|| {
+            info!("unrecorded");
+            panic!("crashed");
+        });
+        drop(recorder);
+        let dump = fs::read_to_string(&path).unwrap();
+        let _removed = fs::remove_file(&path);
+        assert!(
+            dump.starts_with("[tracing-subscriber-init] flight recorder dump, the last 6 events\n")
+        );
+        assert!(dump.ends_with("TRACE last\n"));
+        assert!(!dump.contains("unrecorded"));
+    }
+
+    #[cfg(all(feature = "signal", unix))]
+    #[test]
+    fn dumps_on_signal() {
+        use std::{thread, time::Duration};
+
+        let path = dump_path("signal");
+        let _recorder = record(FlightRecorder {
+            capacity: 10,
+            dump_path: Some(path.clone()),
+            dump_on_panic: false,
+            dump_signal: Some(signal_hook::consts::SIGUSR2),
+            ..FlightRecorder::default()
+        });
+        signal_hook::low_level::raise(signal_hook::consts::SIGUSR2).unwrap();
+        let mut dump = String::new();
+        for _ in 0..100 {
+            dump = fs::read_to_string(&path).unwrap_or_default();
+            if dump.ends_with("TRACE last\n") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let _removed = fs::remove_file(&path);
+        assert!(dump.ends_with("TRACE last\n"));
+    }
+}
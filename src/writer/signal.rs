@@ -0,0 +1,68 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{io, sync::Mutex, thread};
+
+use signal_hook::iterator::Signals;
+
+/// The actions run on a signal, with their signal.
+static ACTIONS: Mutex<Vec<(i32, Action)>> = Mutex::new(Vec::new());
+
+/// What to do when a signal is received.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Action {
+    /// Dump the flight recorders dumped on the signal
+    DumpFlightRecorders,
+    /// Reopen the log files reopened on the signal
+    ReopenLogFiles,
+}
+
+impl Action {
+    fn run(self, signal: i32) {
+        match self {
+            Self::DumpFlightRecorders => super::recorder::dump_on_signal(signal),
+            Self::ReopenLogFiles => super::file::reopen_on_signal(signal),
+        }
+    }
+}
+
+/// Run the given action every time the given signal is received, from a background thread.
+///
+/// Registering the same action for a signal twice runs it once.
+pub(crate) fn on_signal(signal: i32, action: Action) -> io::Result<()> {
+    let mut actions = ACTIONS
+        .lock()
+        .map_err(|_| io::Error::other("signal actions lock poisoned"))?;
+    if actions.contains(&(signal, action)) {
+        return Ok(());
+    }
+    if !actions.iter().any(|(registered, _)| *registered == signal) {
+        let mut signals = Signals::new([signal])?;
+        let _handle = thread::Builder::new()
+            .name(format!("tracing-signal-{signal}"))
+            .spawn(move || {
+                for signal in signals.forever() {
+                    let actions: Vec<Action> = ACTIONS
+                        .lock()
+                        .map(|actions| {
+                            actions
+                                .iter()
+                                .filter(|(registered, _)| *registered == signal)
+                                .map(|(_, action)| *action)
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    for action in actions {
+                        action.run(signal);
+                    }
+                }
+            })?;
+    }
+    actions.push((signal, action));
+    Ok(())
+}
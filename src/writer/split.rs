@@ -0,0 +1,238 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::{MakeWriter, writer::EitherWriter};
+
+/// A [`MakeWriter`] that writes the events at or above a level to one writer, and the other events to another,
+/// i.e. errors and warnings to standard error and the rest to standard output.
+///
+/// Colors are a setting of the format layer, so a layer writing to a terminal and a pipe has to choose between
+/// escape codes in the pipe or no colors on the terminal.  [`with_ansi`](SplitWriter::with_ansi) removes the
+/// escape codes from the writer that should not get them, so enable colors on the layer if either should.
+///
+/// ```
+/// # use std::io;
+/// # use tracing::Level;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{ColorMode, SplitWriter, TestAll, full, set_default};
+/// let color = ColorMode::Auto;
+/// let stderr_ansi = color.enabled(&io::stderr());
+/// let stdout_ansi = color.enabled(&io::stdout());
+/// let writer = SplitWriter::new(Level::WARN, io::stderr, io::stdout).with_ansi(stderr_ansi, stdout_ansi);
+/// let (layer, level_filter) = full(&TestAll);
+/// let layer = layer.with_ansi(stderr_ansi || stdout_ansi).with_writer(writer);
+/// let _unused = set_default(vec![layer.with_filter(level_filter).boxed()]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct SplitWriter<H, L> {
+    level: Level,
+    high: H,
+    low: L,
+    high_ansi: bool,
+    low_ansi: bool,
+}
+
+impl<H, L> SplitWriter<H, L> {
+    /// Create a [`SplitWriter`] writing the events at or above the given level to `high`, and the others to
+    /// `low`.  Output without event metadata goes to `low`.
+    pub fn new(level: Level, high: H, low: L) -> Self {
+        Self {
+            level,
+            high,
+            low,
+            high_ansi: true,
+            low_ansi: true,
+        }
+    }
+
+    /// Whether or not ANSI escape codes are kept in the output of the `high` and `low` writers.  Both are kept
+    /// by default.
+    #[must_use]
+    pub fn with_ansi(mut self, high: bool, low: bool) -> Self {
+        self.high_ansi = high;
+        self.low_ansi = low;
+        self
+    }
+
+    /// The writer of an event at the given level, or of output without event metadata.
+    pub(crate) fn writer_for_level<'a>(
+        &'a self,
+        level: Option<Level>,
+    ) -> EitherWriter<AnsiWriter<H::Writer>, AnsiWriter<L::Writer>>
+    where
+        H: MakeWriter<'a>,
+        L: MakeWriter<'a>,
+    {
+        if level.is_some_and(|level| level <= self.level) {
+            EitherWriter::A(AnsiWriter::new(self.high.make_writer(), self.high_ansi))
+        } else {
+            EitherWriter::B(AnsiWriter::new(self.low.make_writer(), self.low_ansi))
+        }
+    }
+}
+
+impl<'a, H, L> MakeWriter<'a> for SplitWriter<H, L>
+where
+    H: MakeWriter<'a>,
+    L: MakeWriter<'a>,
+{
+    type Writer = EitherWriter<AnsiWriter<H::Writer>, AnsiWriter<L::Writer>>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.writer_for_level(None)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        self.writer_for_level(Some(*meta.level()))
+    }
+}
+
+/// A writer removing the ANSI escape codes from its output unless they are enabled, see [`SplitWriter`].
+#[derive(Debug)]
+pub struct AnsiWriter<W> {
+    inner: W,
+    ansi: bool,
+}
+
+impl<W> AnsiWriter<W> {
+    /// Create an [`AnsiWriter`] writing to the given writer, keeping the escape codes if `ansi` is true.
+    pub fn new(inner: W, ansi: bool) -> Self {
+        Self { inner, ansi }
+    }
+}
+
+impl<W> Write for AnsiWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.ansi {
+            self.inner.write(buf)
+        } else {
+            // an escape code must not be split across writes
+            self.inner.write_all(&strip_ansi(buf))?;
+            Ok(buf.len())
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.ansi {
+            self.inner.write_all(buf)
+        } else {
+            self.inner.write_all(&strip_ansi(buf))
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Remove the ANSI control sequences, `ESC [` up to a final byte, from the given output.
+fn strip_ansi(buf: &[u8]) -> Cow<'_, [u8]> {
+    if !buf.contains(&0x1b) {
+        return Cow::Borrowed(buf);
+    }
+    let mut stripped = Vec::with_capacity(buf.len());
+    let mut bytes = buf.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        if byte == 0x1b && bytes.peek() == Some(&b'[') {
+            for byte in bytes.by_ref().skip(1) {
+                if (0x40..=0x7e).contains(&byte) {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(byte);
+        }
+    }
+    Cow::Owned(stripped)
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::{Level, error, info, warn};
+    use tracing_subscriber::Layer;
+
+    use super::{SplitWriter, strip_ansi};
+    use crate::{
+        TestAll, TimerConfig, TracingConfig, compact_text, set_default, utils::test::TestWriter,
+    };
+
+    #[derive(Clone, Copy, Debug)]
+    struct Plain;
+
+    impl TracingConfig for Plain {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+    }
+
+    #[test]
+    fn strips_control_sequences() {
+        assert_eq!(&*strip_ansi(b"plain"), b"plain");
+        assert_eq!(
+            &*strip_ansi(b"\x1b[32m INFO\x1b[0m \x1b[2mtarget\x1b[0m: x"),
+            b" INFO target: x"
+        );
+        // other escapes are not control sequences
+        assert_eq!(&*strip_ansi(b"\x1bc\x1b"), b"\x1bc\x1b");
+    }
+
+    #[test]
+    fn splits_by_level() {
+        let stderr = TestWriter::default();
+        let stdout = TestWriter::default();
+        let (layer, _) = compact_text(&Plain);
+        let layer = layer.with_writer(SplitWriter::new(
+            Level::WARN,
+            stderr.clone(),
+            stdout.clone(),
+        ));
+        {
+            let _unused = set_default(vec![layer.boxed()]);
+            info!("to stdout");
+            warn!("to stderr");
+            error!("to stderr too");
+        }
+        assert_eq!(stdout.output(), " INFO to stdout\n");
+        assert_eq!(stderr.output(), " WARN to stderr\nERROR to stderr too\n");
+    }
+
+    #[test]
+    fn ansi_is_per_writer() {
+        let stderr = TestWriter::default();
+        let stdout = TestWriter::default();
+        let (layer, _) = compact_text(&TestAll);
+        let layer = layer.with_ansi(true).with_writer(
+            SplitWriter::new(Level::WARN, stderr.clone(), stdout.clone()).with_ansi(true, false),
+        );
+        {
+            let _unused = set_default(vec![layer.boxed()]);
+            info!("plain");
+            warn!("colored");
+        }
+        assert!(!stdout.output().contains('\x1b'));
+        assert!(stdout.output().contains("plain"));
+        assert!(stderr.output().contains("\x1b["));
+    }
+}
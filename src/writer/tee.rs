@@ -0,0 +1,175 @@
+// Copyright (c) 2023 tracing-subscriber-init developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::io::{self, Write};
+
+use tracing::Metadata;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// A [`MakeWriter`] that writes every formatted event to two writers, so an event is formatted once however
+/// many writers it goes to.  Chain [`and`](Tee::and) for more writers.
+///
+/// A failing writer does not stop the others, i.e. a full disk does not stop the events to standard output.
+/// The first error is still returned once every writer has been written to, so the format layer can report it.
+///
+/// ```
+/// # use std::io;
+/// # use tracing_subscriber::Layer;
+/// # use tracing_subscriber_init::{FileOutput, RollingFile, Tee, TestAll, full, set_default};
+/// let file = RollingFile::new(FileOutput::default());
+/// let (layer, level_filter) = full(&TestAll);
+/// let layer = layer.with_writer(Tee::new(io::stdout, file));
+/// let _unused = set_default(vec![layer.with_filter(level_filter).boxed()]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Tee<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Tee<A, B> {
+    /// Create a [`Tee`] writing to both of the given writers, `first` first.
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+
+    /// Write to another writer, after the writers of this [`Tee`].
+    pub fn and<C>(self, next: C) -> Tee<Self, C> {
+        Tee::new(self, next)
+    }
+}
+
+impl<'a, A, B> MakeWriter<'a> for Tee<A, B>
+where
+    A: MakeWriter<'a>,
+    B: MakeWriter<'a>,
+{
+    type Writer = TeeWriter<A::Writer, B::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        TeeWriter::new(self.first.make_writer(), self.second.make_writer())
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        TeeWriter::new(
+            self.first.make_writer_for(meta),
+            self.second.make_writer_for(meta),
+        )
+    }
+}
+
+/// The [`Write`] implementation of a [`Tee`].
+#[derive(Debug)]
+pub struct TeeWriter<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> TeeWriter<A, B> {
+    /// Create a [`TeeWriter`] writing to both of the given writers, `first` first.
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A, B> Write for TeeWriter<A, B>
+where
+    A: Write,
+    B: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // a partial write to one writer cannot be retried without repeating it on the other
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let first = self.first.write_all(buf);
+        let second = self.second.write_all(buf);
+        first.and(second)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let first = self.first.flush();
+        let second = self.second.flush();
+        first.and(second)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Write};
+
+    use tracing::info;
+    use tracing_subscriber::Layer;
+
+    use super::Tee;
+    use crate::{TimerConfig, TracingConfig, compact_text, set_default, utils::test::TestWriter};
+
+    #[derive(Clone, Copy, Debug)]
+    struct Plain;
+
+    impl TracingConfig for Plain {
+        fn quiet(&self) -> u8 {
+            0
+        }
+
+        fn verbose(&self) -> u8 {
+            1
+        }
+
+        fn timer(&self) -> TimerConfig {
+            TimerConfig::None
+        }
+    }
+
+    /// A writer for a full disk.
+    #[derive(Clone, Copy, Debug)]
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_to_every_writer() {
+        let first = TestWriter::default();
+        let second = TestWriter::default();
+        let third = TestWriter::default();
+        let (layer, _) = compact_text(&Plain);
+        let layer = layer.with_writer(Tee::new(first.clone(), second.clone()).and(third.clone()));
+        {
+            let _unused = set_default(vec![layer.boxed()]);
+            info!("everywhere");
+        }
+        for writer in [first, second, third] {
+            assert_eq!(writer.output(), " INFO everywhere\n");
+        }
+    }
+
+    #[test]
+    fn failing_writers_are_isolated() {
+        let output = TestWriter::default();
+        let (layer, _) = compact_text(&Plain);
+        let layer = layer
+            .log_internal_errors(false)
+            .with_writer(Tee::new(|| Full, output.clone()));
+        {
+            let _unused = set_default(vec![layer.boxed()]);
+            info!("still written");
+            info!("and again");
+        }
+        assert_eq!(output.output(), " INFO still written\n INFO and again\n");
+    }
+}